@@ -1,250 +1,1382 @@
-use std::fs;
+use std::collections::HashMap;
+use std::path::Path;
 
+use crate::encoder;
+use crate::intrinsics;
+use crate::ir::{self, Instr};
 use crate::lexer;
+use crate::optimizer;
 use crate::parser;
+use crate::runtime;
+use crate::sourcemap;
+use crate::validator;
+use crate::Diagnostic;
 
 pub struct Compiler {
-    parser: parser::Parser,
+    // Source of the program, source maps embed it
+    source: String,
     program: Program,
-    functions: Vec<Function>
+    functions: Vec<Function>,
+    signatures: HashMap<String, Signature>,
+    structs: HashMap<String, Struct>,
+    enums: HashMap<String, Enum>,
+    runtime: Vec<runtime::RuntimeFn>,
+    runtime_base: u32,
+    heap: Option<Heap>,
+    wasi: Option<Wasi>,
+    pub memory: Memory,
+    pub target: Target,
+    pub validate: bool,
+    pub opt_level: u8,
+    pub bounds_checks: bool,
+    pub allocator: runtime::Allocator,
+    pub names: bool,
+    pub source_map: bool,
+    // Embedded in the module for debuggers to find the source map, next to the module by default
+    pub source_map_url: Option<String>,
+    // Whether the wasi target gets a _start function that calls main
+    pub start: bool,
+    // Types of the compiled expressions by their span, recorded when set
+    pub types: Option<HashMap<lexer::Span, parser::PlatTypes>>,
+    source_name: Option<String>,
+    map: Option<String>,
+    span: lexer::Span,
+    // Innermost statement or expression being compiled, the source map maps instructions to it
+    location: lexer::Span
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Target {
+    Wasm,
+    Wasi
+}
+
+// Imports and scratch memory used by the wasi runtime prelude
+struct Wasi {
+    fd_write: u32,
+    proc_exit: u32,
+    scratch: u32
+}
+
+// Globals of the heap allocator, created when it's linked. The heap base is only known once all
+// data is interned, so the initializers of base and top are patched at the end of compilation.
+struct Heap {
+    base: u32,
+    top: u32,
+    free_list: u32
+}
+
+// Linear memory limits, in 64KiB pages
+#[derive(Debug, Clone)]
+pub struct Memory {
+    pub initial: u32,
+    pub maximum: Option<u32>,
+    pub export: Option<String>
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self { initial: 1, maximum: None, export: Some("memory".to_string()) }
+    }
 }
 
 struct Program {
-    pub sec_type: Vec<u8>,
-    pub n_types: u8,
-    pub sec_func: Vec<u8>,
-    pub n_funcs: u8,
-    pub sec_expo: Vec<u8>,
-    pub n_expos: u8,
-    pub sec_code: Vec<u8>
+    pub module: ir::Module,
+    pub data: Vec<u8>,
+    pub strings: HashMap<String, u32>,
+    pub types: HashMap<ir::FuncType, u32>
 }
 
+// String literals are interned into a single data segment starting at DATA_BASE.
+// A `str` value is the i32 address of a 4 byte aligned record: [length: u32][utf-8 bytes]
+const DATA_BASE: u32 = 1024;
+const PAGE_SIZE: u32 = 65536;
+
 struct Function {
     _name: String,
+    args: Vec<Variable>,
     vars: Vec<Variable>,
+    ret_kind: parser::PlatTypes,
+    body: Vec<ir::Instr>,
+    // Positions in vars of the match bindings that are in scope, innermost last
+    scope: Vec<usize>
+}
+
+#[derive(Clone)]
+struct Signature {
+    index: u32,
+    args: Vec<parser::PlatTypes>,
     ret_kind: parser::PlatTypes
 }
 
 struct Variable {
     name: String,
-    index: u8,
+    index: u32,
     _mutable: bool,
+    binding: bool,
+    // False while the initializer of a `let` is compiled, the variable can't be used in it
+    defined: bool,
     kind: parser::PlatTypes
 }
 
+// A struct value is the i32 address of an instance on the heap. Fields are laid out in declaration
+// order at their natural alignment, and nested structs are stored as addresses.
+struct Struct {
+    fields: Vec<Field>,
+    size: u32,
+    align: u32
+}
+
+struct Field {
+    name: String,
+    kind: parser::PlatTypes,
+    offset: u32
+}
+
+impl Struct {
+    fn field(&self, name: &str, field: &str, span: lexer::Span) -> Result<&Field, Diagnostic> {
+        self.fields.iter().find(|f| f.name == field).ok_or_else(|| Diagnostic::new(format!("The struct {} has no field {}", name, field), span))
+    }
+}
+
+// An enum value is the i32 address of an instance holding the i32 tag (the index of its variant),
+// followed by the variant's payload laid out like struct fields. Every variant gets the same size.
+struct Enum {
+    variants: Vec<Variant>,
+    size: u32,
+    align: u32
+}
+
+struct Variant {
+    name: String,
+    fields: Vec<(parser::PlatTypes, u32)>
+}
+
+impl Enum {
+    fn variant(&self, name: &str, variant: &str, span: lexer::Span) -> Result<(u32, &Variant), Diagnostic> {
+        let tag = self.variants.iter().position(|v| v.name == variant).ok_or_else(|| Diagnostic::new(format!("The enum {} has no variant {}", name, variant), span))?;
+        Ok((tag.try_into().unwrap(), &self.variants[tag]))
+    }
+}
+
+// Offsets of values stored back to back from `start` at their natural alignment, the total size
+// rounded up to the largest alignment and that alignment
+fn layout<'a>(kinds: impl Iterator<Item = &'a parser::PlatTypes>, start: u32) -> (Vec<u32>, u32, u32) {
+    let (mut offsets, mut size, mut align) = (vec![], start, start.max(1));
+    for kind in kinds {
+        let slot = slot_size(kind);
+        offsets.push(size.next_multiple_of(slot));
+        size = offsets.last().unwrap() + slot;
+        align = align.max(slot);
+    }
+    (offsets, size.next_multiple_of(align), align)
+}
+
 impl Program {
     pub fn new() -> Self {
-        Self{sec_type: vec![], n_types: 0, sec_func: vec![], n_funcs: 0, sec_expo: vec![], n_expos: 0, sec_code: vec![] }
+        Self{module: ir::Module::default(), data: vec![], strings: HashMap::new(), types: HashMap::new() }
     }
 
-    pub fn unite(&mut self) -> Vec<u8> {
-        self.sec_type = [&[0x01, <usize as TryInto<u8>>::try_into(self.sec_type.len()).unwrap() + self.n_types, self.n_types], &self.sec_type[..]].concat();
-        self.sec_func = [&[0x03, <usize as TryInto<u8>>::try_into(self.sec_func.len()).unwrap() + self.n_funcs, self.n_funcs], &self.sec_func[..]].concat();
-        self.sec_expo = [&[0x07, <usize as TryInto<u8>>::try_into(self.sec_expo.len()).unwrap() + self.n_expos, self.n_expos], &self.sec_expo[..]].concat();
-        self.sec_code = [&[self.n_funcs], &self.sec_code[..]].concat();
-        self.sec_code = [&[0x0A, <usize as TryInto<u8>>::try_into(self.sec_code.len()).unwrap()], &self.sec_code[..]].concat();
+    pub fn intern_string(&mut self, value: &str) -> u32 {
+        if let Some(address) = self.strings.get(value) {
+            return *address;
+        }
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0x00);
+        }
+        let address = self.data_end();
+        self.data.extend_from_slice(&<usize as TryInto<u32>>::try_into(value.len()).unwrap().to_le_bytes());
+        self.data.extend_from_slice(value.as_bytes());
+        self.strings.insert(value.to_string(), address);
+        address
+    }
 
-        [
-            &vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00], // WASM Magic + Version
-            &self.sec_type[..],
-            &self.sec_func[..],
-            &self.sec_expo[..],
-            &self.sec_code[..]
-        ].concat()
+    pub fn reserve_data(&mut self, size: u32) -> u32 {
+        while !self.data.len().is_multiple_of(4) {
+            self.data.push(0x00);
+        }
+        let address = self.data_end();
+        self.data.resize(self.data.len() + size as usize, 0x00);
+        address
+    }
+
+    pub fn data_end(&self) -> u32 {
+        DATA_BASE + <usize as TryInto<u32>>::try_into(self.data.len()).unwrap()
+    }
+
+    pub fn n_funcs(&self) -> u32 {
+        (self.module.imports.len() + self.module.functions.len()).try_into().unwrap()
+    }
+
+    // Identical signatures share one entry of the type section
+    pub fn add_type(&mut self, args: &[parser::PlatTypes], ret_kind: &parser::PlatTypes) -> u32 {
+        let params = args.iter().map(val_type).collect();
+        let results = if *ret_kind == parser::PlatTypes::Void {vec![]} else {vec![val_type(ret_kind)]};
+        let kind = ir::FuncType { params, results };
+        if let Some(index) = self.types.get(&kind) {
+            return *index;
+        }
+        self.module.types.push(kind.clone());
+        let index = (self.module.types.len() - 1).try_into().unwrap();
+        self.types.insert(kind, index);
+        index
+    }
+
+    pub fn add_import(&mut self, module: &str, name: &str, type_index: u32) -> u32 {
+        self.module.imports.push(ir::Import { module: module.to_string(), name: name.to_string(), type_index });
+        (self.module.imports.len() - 1).try_into().unwrap()
+    }
+
+    pub fn add_function(&mut self, func: ir::Function) -> u32 {
+        self.module.functions.push(func);
+        self.n_funcs() - 1
+    }
+
+    pub fn add_global(&mut self, name: &str, kind: ir::ValType, mutable: bool, init: Instr) -> u32 {
+        self.module.globals.push(ir::Global { name: name.to_string(), kind, mutable, init });
+        (self.module.globals.len() - 1).try_into().unwrap()
+    }
+
+    pub fn add_export(&mut self, name: &str, kind: ir::ExportKind, index: u32) {
+        self.module.exports.push(ir::Export { name: name.to_string(), kind, index });
+    }
+
+    // Moves the interned data into the module as a single segment
+    pub fn finish(&mut self) {
+        if !self.data.is_empty() {
+            self.module.data = vec![ir::Data { offset: DATA_BASE, bytes: self.data.clone() }];
+        }
+    }
+
+    pub fn unite(&mut self) -> (Vec<u8>, Vec<(u32, lexer::Span)>) {
+        self.finish();
+        encoder::encode_with_locations(&self.module)
     }
 }
 
-fn plat_type_to_byte(kind: parser::PlatTypes) -> u8 {
+fn val_type(kind: &parser::PlatTypes) -> ir::ValType {
     match kind {
-        parser::PlatTypes::Void => 0x00,
-        parser::PlatTypes::I32 => 0x7F,
-        parser::PlatTypes::I64 => 0x7E,
-        parser::PlatTypes::F32 => 0x7D,
-        parser::PlatTypes::F64 => 0x7C,
+        parser::PlatTypes::I32 | parser::PlatTypes::Str | parser::PlatTypes::Named(_) | parser::PlatTypes::Array(..) => ir::ValType::I32,
+        parser::PlatTypes::I64 => ir::ValType::I64,
+        parser::PlatTypes::F32 => ir::ValType::F32,
+        parser::PlatTypes::F64 => ir::ValType::F64,
+        parser::PlatTypes::Void => panic!("Void has no value type")
     }
 }
 
+// Size of a struct field or array element, which is also its alignment
+fn slot_size(kind: &parser::PlatTypes) -> u32 {
+    1 << store_kind(kind).natural_align()
+}
+
+fn load_kind(kind: &parser::PlatTypes) -> ir::LoadKind {
+    match val_type(kind) {
+        ir::ValType::I32 => ir::LoadKind::I32,
+        ir::ValType::I64 => ir::LoadKind::I64,
+        ir::ValType::F32 => ir::LoadKind::F32,
+        ir::ValType::F64 => ir::LoadKind::F64
+    }
+}
+
+fn store_kind(kind: &parser::PlatTypes) -> ir::StoreKind {
+    match val_type(kind) {
+        ir::ValType::I32 => ir::StoreKind::I32,
+        ir::ValType::I64 => ir::StoreKind::I64,
+        ir::ValType::F32 => ir::StoreKind::F32,
+        ir::ValType::F64 => ir::StoreKind::F64
+    }
+}
+
+pub(crate) fn is_identifier(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_alphabetic() || c == '_')
+}
+
+fn is_comparison(kind: &lexer::PlatTokenKinds) -> bool {
+    matches!(kind,
+        lexer::PlatTokenKinds::Equals | lexer::PlatTokenKinds::BangEquals |
+        lexer::PlatTokenKinds::Smaller | lexer::PlatTokenKinds::SmallerEquals |
+        lexer::PlatTokenKinds::Greater | lexer::PlatTokenKinds::GreaterEquals)
+}
+
+fn binary_instr(operator: &lexer::PlatToken, kind: &parser::PlatTypes, span: lexer::Span) -> Result<ir::Instr, Diagnostic> {
+    let float = matches!(kind, parser::PlatTypes::F32 | parser::PlatTypes::F64);
+    ensure!(matches!(kind, parser::PlatTypes::I32 | parser::PlatTypes::I64) || float, span, "Invalid operand type for \"{}\": {}", operator.value, kind);
+    let kind = val_type(kind);
+    Ok(match operator.kind {
+        lexer::PlatTokenKinds::Plus          => Instr::Binary(kind, ir::BinOp::Add),
+        lexer::PlatTokenKinds::Minus         => Instr::Binary(kind, ir::BinOp::Sub),
+        lexer::PlatTokenKinds::Asterisk      => Instr::Binary(kind, ir::BinOp::Mul),
+        lexer::PlatTokenKinds::Slash         => Instr::Binary(kind, if float {ir::BinOp::Div} else {ir::BinOp::DivS}),
+        lexer::PlatTokenKinds::Equals        => Instr::Compare(kind, ir::RelOp::Eq),
+        lexer::PlatTokenKinds::BangEquals    => Instr::Compare(kind, ir::RelOp::Ne),
+        lexer::PlatTokenKinds::Smaller       => Instr::Compare(kind, if float {ir::RelOp::Lt} else {ir::RelOp::LtS}),
+        lexer::PlatTokenKinds::Greater       => Instr::Compare(kind, if float {ir::RelOp::Gt} else {ir::RelOp::GtS}),
+        lexer::PlatTokenKinds::SmallerEquals => Instr::Compare(kind, if float {ir::RelOp::Le} else {ir::RelOp::LeS}),
+        lexer::PlatTokenKinds::GreaterEquals => Instr::Compare(kind, if float {ir::RelOp::Ge} else {ir::RelOp::GeS}),
+        _ => panic!("Invalid binary operator {:?}", operator.kind)
+    })
+}
+
 impl Compiler {
-    pub fn new(filename: &str) -> Self {
-        let file = Compiler::read_file(filename);
+    // `file_name` names the module and the source of source maps
+    pub fn new(source: String, file_name: Option<&str>) -> Self {
+        let mut program = Program::new();
+        program.module.name = file_name.and_then(|name| Path::new(name).file_stem()).map(|name| name.to_string_lossy().into_owned());
+        let source_name = file_name.and_then(|name| Path::new(name).file_name()).map(|name| name.to_string_lossy().into_owned());
 
-        let lexer = lexer::Lexer::new(file);
+        Self { source, program, functions: vec![], signatures: HashMap::new(), structs: HashMap::new(), enums: HashMap::new(), runtime: vec![], runtime_base: 0, heap: None, wasi: None, memory: Memory::default(), target: Target::Wasm, validate: cfg!(debug_assertions), opt_level: 0, bounds_checks: true, allocator: runtime::Allocator::Bump, names: cfg!(debug_assertions), source_map: false, source_map_url: None, start: true, types: None, source_name, map: None, span: lexer::Span::default(), location: lexer::Span::default() }
+    }
+
+    fn error(&self, message: String) -> Diagnostic {
+        Diagnostic::new(message, self.span)
+    }
 
-        Self { parser: parser::Parser::new(lexer), program: Program::new(), functions: vec![] }
+    fn declare_wasi(&mut self) {
+        let fd_write_type = self.program.add_type(&vec![parser::PlatTypes::I32; 4], &parser::PlatTypes::I32);
+        let proc_exit_type = self.program.add_type(&[parser::PlatTypes::I32], &parser::PlatTypes::Void);
+        self.wasi = Some(Wasi {
+            fd_write: self.program.add_import("wasi_snapshot_preview1", "fd_write", fd_write_type),
+            proc_exit: self.program.add_import("wasi_snapshot_preview1", "proc_exit", proc_exit_type),
+            scratch: 0
+        });
     }
 
-    pub fn read_file(filename: &str) -> String {
-        fs::read_to_string(filename).expect(&format!("File {} was not found!", filename))
+    fn check_type(&self, kind: &parser::PlatTypes) -> Result<(), Diagnostic> {
+        match kind {
+            parser::PlatTypes::Named(name) => ensure!(self.structs.contains_key(name) || self.enums.contains_key(name), self.span, "The type {} does not exist...", name),
+            parser::PlatTypes::Array(elem, _) => {
+                ensure!(**elem != parser::PlatTypes::Void, self.span, "Arrays can't hold void values");
+                self.check_type(elem)?;
+            },
+            _ => {}
+        }
+        Ok(())
     }
 
-    pub fn write_file(filename: &str, data: Vec<u8>) {
-        fs::write(filename, data).expect(&format!("Unable to write the compiled program {}", filename));
+    // Structs and enums share one namespace and can refer to each other in any order
+    fn declare_types(&mut self, ast: &[parser::AST]) -> Result<(), Diagnostic> {
+        let mut names = std::collections::HashSet::new();
+        for node in ast.iter() {
+            self.span = node.span();
+            match node {
+                parser::AST::Struct(parser::StructExpr { name, .. }) => {
+                    ensure!(names.insert(name), self.span, "The type {} is defined more than once", name);
+                    self.structs.insert(name.clone(), Struct { fields: vec![], size: 0, align: 1 });
+                },
+                parser::AST::Enum(parser::EnumExpr { name, .. }) => {
+                    ensure!(names.insert(name), self.span, "The type {} is defined more than once", name);
+                    self.enums.insert(name.clone(), Enum { variants: vec![], size: 0, align: 4 });
+                },
+                _ => {}
+            }
+        }
+        for node in ast.iter() {
+            self.span = node.span();
+            match node {
+                parser::AST::Struct(expr) => {
+                    for (i, field) in expr.fields.iter().enumerate() {
+                        ensure!(field.kind != parser::PlatTypes::Void, self.span, "The field {}.{} can't be void", expr.name, field.name);
+                        ensure!(expr.fields[..i].iter().all(|f| f.name != field.name), self.span, "The field {}.{} is defined more than once", expr.name, field.name);
+                        self.check_type(&field.kind)?;
+                    }
+                    let (offsets, size, align) = layout(expr.fields.iter().map(|field| &field.kind), 0);
+                    let fields = expr.fields.iter().zip(offsets).map(|(field, offset)| Field { name: field.name.clone(), kind: field.kind.clone(), offset }).collect();
+                    self.structs.insert(expr.name.clone(), Struct { fields, size, align });
+                },
+                parser::AST::Enum(expr) => {
+                    ensure!(!expr.variants.is_empty(), self.span, "The enum {} needs at least one variant", expr.name);
+                    let (mut variants, mut size, mut align) = (vec![], 0, 4);
+                    for (i, variant) in expr.variants.iter().enumerate() {
+                        ensure!(expr.variants[..i].iter().all(|v| v.name != variant.name), self.span, "The variant {}::{} is defined more than once", expr.name, variant.name);
+                        for kind in variant.fields.iter() {
+                            ensure!(*kind != parser::PlatTypes::Void, self.span, "The variant {}::{} can't hold void values", expr.name, variant.name);
+                            self.check_type(kind)?;
+                        }
+                        let (offsets, variant_size, variant_align) = layout(variant.fields.iter(), 4);
+                        variants.push(Variant { name: variant.name.clone(), fields: variant.fields.iter().cloned().zip(offsets).collect() });
+                        size = size.max(variant_size);
+                        align = align.max(variant_align);
+                    }
+                    self.enums.insert(expr.name.clone(), Enum { variants, size, align });
+                },
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
-    pub fn parse(&mut self) -> Vec<parser::AST> {
-        self.parser.parse(None)
+    fn declare_fns(&mut self, ast: &[parser::AST]) -> Result<(), Diagnostic> {
+        if self.target == Target::Wasi {
+            self.declare_wasi();
+        }
+        self.declare_types(ast)?;
+        let n_imports: u32 = (self.program.module.imports.len() + ast.iter().filter(|node| matches!(node, parser::AST::Func(func) if func.module.is_some())).count()).try_into().unwrap();
+        let mut n_defined = 0;
+        for node in ast.iter() {
+            if let parser::AST::Func(func) = node {
+                self.span = func.span;
+                ensure!(intrinsics::lookup(&func.name).is_none(), self.span, "The function {} shadows a built-in intrinsic", func.name);
+                for (i, arg) in func.args.iter().enumerate() {
+                    ensure!(arg.kind != parser::PlatTypes::Void, self.span, "The argument {} of {} can't be void", arg.name, func.name);
+                    ensure!(func.args[..i].iter().all(|other| other.name != arg.name), self.span, "The argument {} of {} is defined more than once", arg.name, func.name);
+                }
+                let args: Vec<parser::PlatTypes> = func.args.iter().map(|arg| arg.kind.clone()).collect();
+                for kind in args.iter().chain([&func.ret_kind]) {
+                    self.check_type(kind)?;
+                }
+                let index = match &func.module {
+                    Some(module) => {
+                        let type_index = self.program.add_type(&args, &func.ret_kind);
+                        self.program.add_import(module, &func.name, type_index)
+                    },
+                    None => {n_defined += 1; n_imports + n_defined - 1}
+                };
+                let signature = Signature { index, args, ret_kind: func.ret_kind.clone() };
+                ensure!(self.signatures.insert(func.name.clone(), signature).is_none(), self.span, "The function {} is defined more than once", func.name);
+            }
+        }
+        self.runtime_base = n_imports + n_defined;
+        Ok(())
     }
 
-    fn compile_fn(&mut self, expr: &parser::FuncExpr) {
-        self.program.n_types += 1;
-        self.program.sec_type.append(&mut vec![
-            0x60,                                                                            // Function
-            <usize as TryInto<u8>>::try_into(expr.args.len()).unwrap(),                      // Number of arguments
-        ]);
-        for arg in expr.args.iter() {
-            self.program.sec_type.push(plat_type_to_byte(arg.kind));
+    // Links a runtime prelude function (and its dependencies) into the module, returning its index
+    fn runtime_fn(&mut self, func: runtime::RuntimeFn) -> Result<u32, Diagnostic> {
+        if let Some(position) = self.runtime.iter().position(|linked| *linked == func) {
+            return Ok(self.runtime_base + <usize as TryInto<u32>>::try_into(position).unwrap());
+        }
+        if func.needs_wasi() {
+            ensure!(self.wasi.is_some(), self.span, "{} is only available with the wasi target", func.name());
+            let wasi = self.wasi.as_mut().unwrap();
+            if wasi.scratch == 0 {
+                wasi.scratch = self.program.reserve_data(runtime::SCRATCH_SIZE);
+            }
         }
+        if func.uses_heap() && self.heap.is_none() {
+            let base = self.program.add_global("__heap_base", ir::ValType::I32, false, Instr::I32Const(0));
+            let top = self.program.add_global("__heap_top", ir::ValType::I32, true, Instr::I32Const(0));
+            let free_list = if self.allocator == runtime::Allocator::FreeList {
+                self.program.add_global("__free_list", ir::ValType::I32, true, Instr::I32Const(0))
+            } else {0};
+            self.program.add_export("__heap_base", ir::ExportKind::Global, base);
+            self.heap = Some(Heap { base, top, free_list });
+        }
+        for string in func.strings() {
+            self.program.intern_string(string);
+        }
+        self.runtime.push(func);
+        for dependency in func.dependencies() {
+            self.runtime_fn(dependency)?;
+        }
+        self.runtime_fn(func)
+    }
 
-        let mut ret_kind: parser::PlatTypes = parser::PlatTypes::Void;
-        if expr.ret_kind == parser::PlatTypes::Void {
-            self.program.sec_type.push(0x00);                                                // No returns
-        } else {
-            self.program.sec_type.append(&mut vec![0x01, plat_type_to_byte(expr.ret_kind)]); // Return type
-            ret_kind = expr.ret_kind;
+    fn compile_runtime(&mut self) {
+        let ctx = runtime::Context {
+            fd_write: self.wasi.as_ref().map_or(0, |wasi| wasi.fd_write),
+            scratch: self.wasi.as_ref().map_or(0, |wasi| wasi.scratch),
+            heap_top: self.heap.as_ref().map_or(0, |heap| heap.top),
+            free_list: self.heap.as_ref().map_or(0, |heap| heap.free_list),
+            index: &|func| self.runtime_base + <usize as TryInto<u32>>::try_into(self.runtime.iter().position(|linked| *linked == func).unwrap()).unwrap(),
+            string: &|string| self.program.strings[string]
+        };
+        let bodies: Vec<Vec<Instr>> = self.runtime.iter().map(|func| func.body(&ctx)).collect();
+        for (func, body) in self.runtime.clone().iter().zip(bodies) {
+            let type_index = self.program.add_type(&func.args(), &func.ret_kind());
+            self.program.add_function(ir::Function { name: func.name().to_string(), type_index, locals: func.locals(), local_names: vec![], body });
         }
-        self.functions.push(Function{_name: expr.name.clone(), vars: vec![], ret_kind: ret_kind});
+    }
 
-        self.program.sec_func.push(self.program.n_funcs);
-        self.program.n_funcs += 1;
+    // _start calls main and hands an i32 result to proc_exit
+    fn compile_start(&mut self) -> Result<(), Diagnostic> {
+        let main = self.signatures.get("main").ok_or_else(|| self.error("The wasi target needs a main function".to_string()))?.clone();
+        ensure!(main.args.is_empty(), self.span, "The main function can't take arguments with the wasi target");
+        let mut body = vec![Instr::Call(main.index)];
+        match main.ret_kind {
+            parser::PlatTypes::I32 => body.push(Instr::Call(self.wasi.as_ref().unwrap().proc_exit)),
+            parser::PlatTypes::Void => {},
+            _ => body.push(Instr::Drop)
+        }
 
-        self.program.sec_code.push(0x00); // Function size placeholder
-        let func_size_index = self.program.sec_code.len() - 1;
-        let code_size = self.program.sec_code.len();
+        let type_index = self.program.add_type(&[], &parser::PlatTypes::Void);
+        let index = self.program.add_function(ir::Function { name: "_start".to_string(), type_index, locals: vec![], local_names: vec![], body });
+        self.program.add_export("_start", ir::ExportKind::Func, index);
+        Ok(())
+    }
 
-        self.program.sec_code.push(0x00); // Local declarations placeholder
-        let mut func_locals_index = self.program.sec_code.len() - 1;
-        for node in expr.body.as_ref().as_ref().unwrap().iter() {
+    // Exports of the memory, the heap base and the wasi entry point, which functions can't share a name with
+    fn reserved_export(&self, name: &str) -> bool {
+        self.memory.export.as_deref() == Some(name) || name == "__heap_base" || (self.target == Target::Wasi && name == "_start")
+    }
+
+    fn compile_fn(&mut self, expr: &parser::FuncExpr) -> Result<(), Diagnostic> {
+        if expr.module.is_some() {
+            return Ok(()); // Imports are declared up front by declare_fns
+        }
+
+        let signature = self.signatures[&expr.name].clone();
+        let type_index = self.program.add_type(&signature.args, &signature.ret_kind);
+        ensure!(!self.reserved_export(&expr.name), self.span, "The function {} has the name of an export the compiler adds", expr.name);
+        self.program.add_export(&expr.name, ir::ExportKind::Func, signature.index);
+
+        let args = expr.args.iter().enumerate().map(|(index, arg)| Variable { name: arg.name.clone(), index: index.try_into().unwrap(), _mutable: true, binding: false, defined: true, kind: arg.kind.clone() }).collect();
+        self.functions.push(Function{_name: expr.name.clone(), args, vars: vec![], ret_kind: expr.ret_kind.clone(), body: vec![], scope: vec![]});
+        let body = expr.body.as_ref().as_ref().unwrap();
+        self.compile_body(body)?;
+        self.span = expr.span;
+        ensure!(expr.ret_kind == parser::PlatTypes::Void || body.iter().any(|node| matches!(node, parser::AST::Ret(..))), self.span, "The function {} ends without returning a value", expr.name);
+
+        let current_fn = self.functions.last_mut().unwrap();
+        let locals = current_fn.vars.iter().map(|var| val_type(&var.kind)).collect();
+        let local_names = current_fn.args.iter().chain(current_fn.vars.iter()).map(|var| (var.index, var.name.clone())).collect();
+        let body = std::mem::take(&mut current_fn.body);
+        self.program.add_function(ir::Function { name: expr.name.clone(), type_index, locals, local_names, body });
+        Ok(())
+    }
+
+    fn compile_body(&mut self, body: &[parser::AST]) -> Result<(), Diagnostic> {
+        for node in body.iter() {
+            self.span = node.span();
             match node {
-                parser::AST::Func(_) => assert!(false, "Nested functions aren't supported..."),
-                parser::AST::Def(def) => {
-                    let current_fn = self.functions.last_mut().unwrap();
-                    current_fn.vars.push(Variable { name: def.name.clone(), index: (current_fn.vars.len() + expr.args.len()).try_into().unwrap(), _mutable: true, kind: def.kind });
+                parser::AST::Func(_) => bail!(self.span, "Nested functions aren't supported..."),
+                parser::AST::Struct(_) | parser::AST::Enum(_) => bail!(self.span, "Types have to be defined at the top level"),
+                // Defining a variable again assigns to it
+                parser::AST::Def(def) => match self.find_var(&def.name) {
+                    Ok(var) => ensure!(var.kind == def.kind, self.span, "The variable {} is already defined as {}", def.name, var.kind),
+                    Err(_) => {
+                        self.check_type(&def.kind)?;
+                        self.add_local(&def.name, def.kind.clone());
+                        self.functions.last_mut().unwrap().vars.last_mut().unwrap().defined = false;
+                    }
                 },
                 _ => {}
             }
-            self.compile_node(node);
+            self.compile_node(node)?;
         }
-        self.program.sec_code.push(0x0B); // End of function
-        
-        // Add local declarations
-        self.program.sec_code[func_locals_index] = <usize as TryInto<u8>>::try_into(self.functions.last().unwrap().vars.len()).unwrap();  // Number of local decls
-        func_locals_index += 1;
-        for node in self.functions.last().unwrap().vars.iter() {    
-            self.program.sec_code.insert({let temp = func_locals_index; func_locals_index += 1; temp}, 1);
-            self.program.sec_code.insert({let temp = func_locals_index; func_locals_index += 1; temp}, plat_type_to_byte(node.kind)); // Decl type
-        }
-
-        self.program.sec_code[func_size_index] = <usize as TryInto<u8>>::try_into(self.program.sec_code.len() - code_size).unwrap();
-    }
-
-    fn compile_def(&mut self, expr: &parser::DefExpr) {
-        let mut body: Vec<u8> = vec![];
-
-        let var_index: u8 = self.functions.last().unwrap().vars.iter().find(|var| var.name == expr.name).expect(&format!("The variable {} does not exist...", expr.name)).index.try_into().unwrap();
-
-        match expr.kind {
-            parser::PlatTypes::I32 => {
-                body.push(0x41);
-                match expr.value.as_ref().unwrap() {
-                    parser::Expr::Unary(_) => todo!(),
-                    parser::Expr::Binary(_) => todo!(),
-                    parser::Expr::Literal(expr) => {
-                        let mut first = true;
-                        for byte in expr.parse::<i32>().unwrap().to_le_bytes() { 
-                            if byte == 0 {
-                                if first {body.push(0x00);}
-                                break;
-                            } else {
-                                first = false;
-                                body.push(byte);
-                            }
-                        };
-                        body.append(&mut vec![0x21, var_index]);
-                    },
-                    parser::Expr::Group(_) => todo!(),
-                };
+        Ok(())
+    }
+
+    // Type of `expr` in a function of `ast` that starts with `body`, which the REPL needs to give
+    // an entry's function its return type
+    pub fn expr_type(&mut self, ast: &[parser::AST], body: &[parser::AST], expr: &parser::Expr, span: lexer::Span) -> Result<parser::PlatTypes, Diagnostic> {
+        self.declare_fns(ast)?;
+        self.functions.push(Function{_name: String::new(), args: vec![], vars: vec![], ret_kind: parser::PlatTypes::Void, body: vec![], scope: vec![]});
+        self.compile_body(body)?;
+        self.span = span;
+        self.compile_expr(expr, None)
+    }
+
+    fn add_local(&mut self, name: &str, kind: parser::PlatTypes) -> u32 {
+        let current_fn = self.functions.last_mut().unwrap();
+        let index = (current_fn.args.len() + current_fn.vars.len()).try_into().unwrap();
+        current_fn.vars.push(Variable { name: name.to_string(), index, _mutable: true, binding: false, defined: true, kind });
+        index
+    }
+
+    // Local that is only visible while it is in scope, shadowing variables with the same name
+    fn add_binding(&mut self, name: &str, kind: parser::PlatTypes) -> u32 {
+        let index = self.add_local(name, kind);
+        let current_fn = self.functions.last_mut().unwrap();
+        current_fn.vars.last_mut().unwrap().binding = true;
+        current_fn.scope.push(current_fn.vars.len() - 1);
+        index
+    }
+
+    // Hidden local holding an intermediate value, e.g. the address of a new struct instance
+    fn add_temp(&mut self, prefix: &str, kind: parser::PlatTypes) -> u32 {
+        let n_locals = self.functions.last().unwrap().vars.len();
+        self.add_local(&format!("__{}{}", prefix, n_locals), kind)
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.functions.last_mut().unwrap().body.push(instr);
+    }
+
+    // Instructions emitted by `compile`, which are left out of the current function's body
+    fn capture(&mut self, compile: impl FnOnce(&mut Self) -> Result<(), Diagnostic>) -> Result<Vec<Instr>, Diagnostic> {
+        let body = std::mem::take(&mut self.functions.last_mut().unwrap().body);
+        let result = compile(self);
+        let captured = std::mem::replace(&mut self.functions.last_mut().unwrap().body, body);
+        result.map(|_| captured)
+    }
+
+    fn find_var(&self, name: &str) -> Result<&Variable, Diagnostic> {
+        let current_fn = self.functions.last().unwrap();
+        let var = current_fn.scope.iter().rev().map(|position| &current_fn.vars[*position])
+            .chain(current_fn.args.iter().chain(current_fn.vars.iter()).filter(|var| !var.binding))
+            .find(|var| var.name == name).ok_or_else(|| self.error(format!("The variable {} does not exist...", name)))?;
+        ensure!(var.defined, self.span, "The variable {} is used in its own definition", name);
+        Ok(var)
+    }
+
+    fn literal_type(value: &str) -> parser::PlatTypes {
+        if value.contains('.') {parser::PlatTypes::F64} else {parser::PlatTypes::I32}
+    }
+
+    // Type of an expression made only of untyped literals
+    fn default_type(expr: &parser::Expr) -> parser::PlatTypes {
+        match expr {
+            parser::Expr::Literal(value, _) => Compiler::literal_type(value),
+            parser::Expr::Unary(expr) => Compiler::default_type(&expr.expr),
+            parser::Expr::Binary(expr) => Compiler::default_type(&expr.left),
+            parser::Expr::Group(expr, _) => Compiler::default_type(expr),
+            _ => parser::PlatTypes::I32
+        }
+    }
+
+    fn infer_type(&self, expr: &parser::Expr) -> Result<Option<parser::PlatTypes>, Diagnostic> {
+        self.infer_type_in(expr, &[])
+    }
+
+    // Type of `expr`, or the type of an expression made only of untyped literals
+    fn infer_or_default(&self, expr: &parser::Expr) -> Result<parser::PlatTypes, Diagnostic> {
+        Ok(self.infer_type(expr)?.unwrap_or_else(|| Compiler::default_type(expr)))
+    }
+
+    // Types of match arms are inferred before their bindings are declared, so they are passed along
+    fn infer_type_in(&self, expr: &parser::Expr, bindings: &[(String, parser::PlatTypes)]) -> Result<Option<parser::PlatTypes>, Diagnostic> {
+        Ok(match expr {
+            parser::Expr::Literal(value, _) if is_identifier(value) => match bindings.iter().rev().find(|(name, _)| name == value) {
+                Some((_, kind)) => Some(kind.clone()),
+                None => Some(self.find_var(value)?.kind.clone())
             },
-            parser::PlatTypes::I64 => {
-                body.push(0x42);
-                match expr.value.as_ref().unwrap() {
-                    parser::Expr::Unary(_) => todo!(),
-                    parser::Expr::Binary(_) => todo!(),
-                    parser::Expr::Literal(expr) => {
-                        let mut first = true;
-                        for byte in expr.parse::<i64>().unwrap().to_le_bytes() { 
-                            if byte == 0 {
-                                if first {body.push(0x00);}
-                                break;
-                            } else {
-                                first = false;
-                                body.push(byte);
-                            }
-                        };
-                        body.append(&mut vec![0x21, var_index]);
-                    },
-                    parser::Expr::Group(_) => todo!(),
-                };
+            parser::Expr::Literal(_, _) => None,
+            parser::Expr::Str(_, _) => Some(parser::PlatTypes::Str),
+            parser::Expr::Unary(expr) if expr.operator.kind == lexer::PlatTokenKinds::Bang => Some(parser::PlatTypes::I32),
+            parser::Expr::Unary(expr) => self.infer_type_in(&expr.expr, bindings)?,
+            parser::Expr::Binary(expr) if is_comparison(&expr.operator.kind) => Some(parser::PlatTypes::I32),
+            parser::Expr::Binary(expr) => match self.infer_type_in(&expr.left, bindings)? {
+                Some(kind) => Some(kind),
+                None => self.infer_type_in(&expr.right, bindings)?
+            },
+            parser::Expr::Group(expr, _) => self.infer_type_in(expr, bindings)?,
+            parser::Expr::Call(expr) => match intrinsics::lookup(&expr.name) {
+                Some(intrinsics::Intrinsic::Load { kind, .. }) => Some(kind),
+                Some(intrinsics::Intrinsic::Store { .. }) => Some(parser::PlatTypes::Void),
+                Some(intrinsics::Intrinsic::MemorySize) | Some(intrinsics::Intrinsic::MemoryGrow) => Some(parser::PlatTypes::I32),
+                Some(intrinsics::Intrinsic::StrLen) | Some(intrinsics::Intrinsic::StrPtr) | Some(intrinsics::Intrinsic::Len) => Some(parser::PlatTypes::I32),
+                Some(intrinsics::Intrinsic::Print { .. }) | Some(intrinsics::Intrinsic::Free) => Some(parser::PlatTypes::Void),
+                Some(intrinsics::Intrinsic::Alloc) => Some(parser::PlatTypes::I32),
+                None => Some(self.signatures.get(&expr.name).ok_or_else(|| self.error(format!("The function {} does not exist...", expr.name)))?.ret_kind.clone())
+            },
+            parser::Expr::StructLit(expr) => Some(parser::PlatTypes::Named(expr.name.clone())),
+            parser::Expr::Field(expr) => match self.infer_type_in(&expr.expr, bindings)? {
+                Some(parser::PlatTypes::Named(name)) => Some(self.find_struct(&name)?.field(&name, &expr.field, self.span)?.kind.clone()),
+                kind => bail!(self.span, "Can't access the field {} of {}", expr.field, kind.unwrap_or_else(|| Compiler::default_type(&expr.expr)))
+            },
+            parser::Expr::ArrayLit(expr) => {
+                let count = expr.count.unwrap_or(expr.elements.len().try_into().unwrap());
+                self.infer_type_in(&expr.elements[0], bindings)?.map(|elem| parser::PlatTypes::Array(Box::new(elem), count))
+            },
+            parser::Expr::Index(expr) => match self.infer_type_in(&expr.expr, bindings)? {
+                Some(parser::PlatTypes::Array(elem, _)) => Some(*elem),
+                kind => bail!(self.span, "Can't index {}", kind.unwrap_or_else(|| Compiler::default_type(&expr.expr)))
+            },
+            parser::Expr::Variant(expr) => Some(parser::PlatTypes::Named(expr.enum_name.clone())),
+            parser::Expr::Match(expr) => {
+                let kind = self.infer_type_in(&expr.expr, bindings)?.unwrap_or_else(|| Compiler::default_type(&expr.expr));
+                let mut found = None;
+                for arm in expr.arms.iter() {
+                    let mut bindings = bindings.to_vec();
+                    bindings.extend(self.pattern_bindings(&kind, &arm.pattern)?.into_iter().map(|(name, kind, _)| (name, kind)));
+                    found = self.infer_type_in(&arm.body, &bindings)?;
+                    if found.is_some() {
+                        break;
+                    }
+                }
+                found
+            }
+        })
+    }
+
+    fn find_struct(&self, name: &str) -> Result<&Struct, Diagnostic> {
+        self.structs.get(name).ok_or_else(|| self.error(format!("The struct {} does not exist...", name)))
+    }
+
+    fn find_enum(&self, name: &str) -> Result<&Enum, Diagnostic> {
+        self.enums.get(name).ok_or_else(|| self.error(format!("The enum {} does not exist...", name)))
+    }
+
+    // Names, types and payload offsets of the values a pattern binds, `_` bindings are left out
+    fn pattern_bindings(&self, kind: &parser::PlatTypes, pattern: &parser::Pattern) -> Result<Vec<(String, parser::PlatTypes, u32)>, Diagnostic> {
+        let parser::Pattern::Variant { enum_name, variant, bindings } = pattern else {return Ok(vec![])};
+        ensure!(*kind == parser::PlatTypes::Named(enum_name.clone()), self.span, "Can't match {} against {}", kind, pattern);
+        let (_, variant) = self.find_enum(enum_name)?.variant(enum_name, variant, self.span)?;
+        ensure!(bindings.len() == variant.fields.len(), self.span, "The variant {}::{} has {} values, got {} bindings", enum_name, variant.name, variant.fields.len(), bindings.len());
+        Ok(bindings.iter().zip(variant.fields.iter()).filter(|(name, _)| *name != "_").map(|(name, (kind, offset))| (name.clone(), kind.clone(), *offset)).collect())
+    }
+
+    fn compile_const(&mut self, value: &str, kind: &parser::PlatTypes) -> Result<(), Diagnostic> {
+        let instr = match kind {
+            parser::PlatTypes::I32 => Instr::I32Const(value.parse::<i32>().map_err(|_| self.error(format!("Invalid i32 literal {}", value)))?),
+            parser::PlatTypes::I64 => Instr::I64Const(value.parse::<i64>().map_err(|_| self.error(format!("Invalid i64 literal {}", value)))?),
+            parser::PlatTypes::F32 => Instr::F32Const(value.parse::<f32>().map_err(|_| self.error(format!("Invalid f32 literal {}", value)))?),
+            parser::PlatTypes::F64 => Instr::F64Const(value.parse::<f64>().map_err(|_| self.error(format!("Invalid f64 literal {}", value)))?),
+            _ => bail!(self.span, "Invalid literal type! Type: {}", kind)
+        };
+
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn const_operand(&self, expr: &parser::Expr) -> Result<u32, Diagnostic> {
+        match expr {
+            parser::Expr::Literal(value, _) if !is_identifier(value) => value.parse::<u32>().map_err(|_| self.error(format!("Invalid memory offset {}", value))),
+            _ => bail!(self.span, "Memory offsets have to be constant! Got {}", expr)
+        }
+    }
+
+    fn compile_intrinsic(&mut self, intrinsic: intrinsics::Intrinsic, expr: &parser::CallExpr) -> Result<parser::PlatTypes, Diagnostic> {
+        Ok(match intrinsic {
+            intrinsics::Intrinsic::Load { op, kind } => {
+                ensure!(expr.args.len() == 1 || expr.args.len() == 2, self.span, "{} expects an address and an optional offset", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::I32)?;
+                let offset = expr.args.get(1).map(|arg| self.const_operand(arg)).transpose()?.unwrap_or(0);
+                self.emit(Instr::Load(op, ir::MemArg { align: op.natural_align(), offset }));
+                kind
+            },
+            intrinsics::Intrinsic::Store { op, kind } => {
+                ensure!(expr.args.len() == 2 || expr.args.len() == 3, self.span, "{} expects an address, a value and an optional offset", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::I32)?;
+                self.compile_operand(&expr.args[1], kind)?;
+                let offset = expr.args.get(2).map(|arg| self.const_operand(arg)).transpose()?.unwrap_or(0);
+                self.emit(Instr::Store(op, ir::MemArg { align: op.natural_align(), offset }));
+                parser::PlatTypes::Void
+            },
+            intrinsics::Intrinsic::MemorySize => {
+                ensure!(expr.args.is_empty(), self.span, "{} doesn't take any arguments", expr.name);
+                self.emit(Instr::MemorySize);
+                parser::PlatTypes::I32
+            },
+            intrinsics::Intrinsic::MemoryGrow => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects the number of pages", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::I32)?;
+                self.emit(Instr::MemoryGrow);
+                parser::PlatTypes::I32
             },
-            parser::PlatTypes::F32 => {
-                body.push(0x43);
-                match expr.value.as_ref().unwrap() {
-                    parser::Expr::Unary(_) => todo!(),
-                    parser::Expr::Binary(_) => todo!(),
-                    parser::Expr::Literal(expr) => {
-                        for byte in expr.parse::<f32>().unwrap().to_le_bytes() { body.push(byte) };
-                        body.append(&mut vec![0x21, var_index]);
+            intrinsics::Intrinsic::StrLen => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects a string", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::Str)?;
+                self.emit(Instr::Load(ir::LoadKind::I32, ir::MemArg::natural(2)));
+                parser::PlatTypes::I32
+            },
+            intrinsics::Intrinsic::StrPtr => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects a string", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::Str)?;
+                self.emit(Instr::I32Const(4));
+                self.emit(Instr::Binary(ir::ValType::I32, ir::BinOp::Add));
+                parser::PlatTypes::I32
+            },
+            intrinsics::Intrinsic::Len => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects an array or a string", expr.name);
+                match self.compile_expr(&expr.args[0], None)? {
+                    parser::PlatTypes::Array(_, count) => {
+                        self.emit(Instr::Drop);
+                        self.emit(Instr::I32Const(count.try_into().unwrap()));
                     },
-                    parser::Expr::Group(_) => todo!(),
+                    parser::PlatTypes::Str => self.emit(Instr::Load(ir::LoadKind::I32, ir::MemArg::natural(2))),
+                    kind => bail!(self.span, "{} expects an array or a string, got {}", expr.name, kind)
+                }
+                parser::PlatTypes::I32
+            },
+            intrinsics::Intrinsic::Alloc => {
+                ensure!(expr.args.len() == 2, self.span, "{} expects a size and an alignment", expr.name);
+                self.compile_operand(&expr.args[0], parser::PlatTypes::I32)?;
+                self.compile_operand(&expr.args[1], parser::PlatTypes::I32)?;
+                let index = self.alloc_fn()?;
+                self.emit(Instr::Call(index));
+                parser::PlatTypes::I32
+            },
+            intrinsics::Intrinsic::Free => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects an address", expr.name);
+                let kind = self.infer_or_default(&expr.args[0])?;
+                ensure!(matches!(kind, parser::PlatTypes::I32 | parser::PlatTypes::Named(_) | parser::PlatTypes::Array(..)), self.span, "{} expects an address or a heap value, got {}", expr.name, kind);
+                self.compile_operand(&expr.args[0], kind)?;
+                // The bump allocator never reuses memory
+                match self.allocator {
+                    runtime::Allocator::Bump => self.emit(Instr::Drop),
+                    runtime::Allocator::FreeList => {
+                        let index = self.runtime_fn(runtime::RuntimeFn::Free)?;
+                        self.emit(Instr::Call(index));
+                    }
+                }
+                parser::PlatTypes::Void
+            },
+            intrinsics::Intrinsic::Print { newline } => {
+                ensure!(expr.args.len() == 1, self.span, "{} expects a single value", expr.name);
+                ensure!(self.target == Target::Wasi, self.span, "{} is only available with the wasi target", expr.name);
+                let kind = self.infer_or_default(&expr.args[0])?;
+                self.compile_operand(&expr.args[0], kind.clone())?;
+                let func = match kind {
+                    parser::PlatTypes::Str => runtime::RuntimeFn::PrintStr,
+                    parser::PlatTypes::I32 => {self.emit(Instr::Convert(ir::ConvOp::I64ExtendI32S)); runtime::RuntimeFn::PrintI64},
+                    parser::PlatTypes::I64 => runtime::RuntimeFn::PrintI64,
+                    parser::PlatTypes::F32 => {self.emit(Instr::Convert(ir::ConvOp::F64PromoteF32)); runtime::RuntimeFn::PrintF64},
+                    parser::PlatTypes::F64 => runtime::RuntimeFn::PrintF64,
+                    parser::PlatTypes::Void => bail!(self.span, "Can't print a void value"),
+                    parser::PlatTypes::Named(name) => bail!(self.span, "Can't print the struct {}", name),
+                    parser::PlatTypes::Array(..) => bail!(self.span, "Can't print an array")
                 };
+                let index = self.runtime_fn(func)?;
+                self.emit(Instr::Call(index));
+                if newline {
+                    let newline = self.program.intern_string("\n");
+                    let index = self.runtime_fn(runtime::RuntimeFn::PrintStr)?;
+                    self.emit(Instr::I32Const(newline.try_into().unwrap()));
+                    self.emit(Instr::Call(index));
+                }
+                parser::PlatTypes::Void
+            }
+        })
+    }
+
+    fn compile_call(&mut self, expr: &parser::CallExpr) -> Result<parser::PlatTypes, Diagnostic> {
+        let signature = self.signatures.get(&expr.name).ok_or_else(|| self.error(format!("The function {} does not exist...", expr.name)))?.clone();
+        ensure!(expr.args.len() == signature.args.len(), self.span, "The function {} expects {} arguments, got {}", expr.name, signature.args.len(), expr.args.len());
+        for (arg, kind) in expr.args.iter().zip(signature.args.iter()) {
+            self.compile_operand(arg, kind.clone())?;
+        }
+        self.emit(Instr::Call(signature.index));
+        Ok(signature.ret_kind)
+    }
+
+    fn compile_operand(&mut self, expr: &parser::Expr, kind: parser::PlatTypes) -> Result<(), Diagnostic> {
+        let got = self.compile_expr(expr, Some(kind.clone()))?;
+        ensure!(got == kind, self.span, "Invalid operand type, expected {} got {}", kind, got);
+        Ok(())
+    }
+
+    fn compile_expr(&mut self, expr: &parser::Expr, expected: Option<parser::PlatTypes>) -> Result<parser::PlatTypes, Diagnostic> {
+        let outer = self.locate(expr.span());
+        let kind = self.compile_expr_inner(expr, expected)?;
+        self.locate(outer);
+        self.record(expr, kind.clone());
+        Ok(kind)
+    }
+
+    // Maps the instructions emitted from now on to `span`, returns what they were mapped to
+    fn locate(&mut self, span: lexer::Span) -> lexer::Span {
+        if self.source_map {
+            self.emit(Instr::Location(span));
+        }
+        std::mem::replace(&mut self.location, span)
+    }
+
+    fn record(&mut self, expr: &parser::Expr, kind: parser::PlatTypes) {
+        if let Some(types) = &mut self.types {
+            types.insert(expr.span(), kind);
+        }
+    }
+
+    fn compile_expr_inner(&mut self, expr: &parser::Expr, expected: Option<parser::PlatTypes>) -> Result<parser::PlatTypes, Diagnostic> {
+        let expected = expected.filter(|kind| *kind != parser::PlatTypes::Void);
+        Ok(match expr {
+            parser::Expr::Literal(value, _) if is_identifier(value) => {
+                let var = self.find_var(value)?;
+                let (index, kind) = (var.index, var.kind.clone());
+                self.emit(Instr::LocalGet(index));
+                kind
             },
-            parser::PlatTypes::F64 => {
-                body.push(0x44);
-                match expr.value.as_ref().unwrap() {
-                    parser::Expr::Unary(_) => todo!(),
-                    parser::Expr::Binary(_) => todo!(),
-                    parser::Expr::Literal(expr) => {
-                        for byte in expr.parse::<f64>().unwrap().to_le_bytes() { body.push(byte) };
-                        body.append(&mut vec![0x21, var_index]);
+            parser::Expr::Literal(value, _) => {
+                let kind = expected.unwrap_or(Compiler::literal_type(value));
+                self.compile_const(value, &kind)?;
+                kind
+            },
+            parser::Expr::Unary(unary) => {
+                match unary.operator.kind {
+                    lexer::PlatTokenKinds::Bang => {
+                        let kind = self.infer_type(&unary.expr)?.unwrap_or(parser::PlatTypes::I32);
+                        self.compile_operand(&unary.expr, kind.clone())?;
+                        match kind {
+                            parser::PlatTypes::I32 | parser::PlatTypes::I64 => self.emit(Instr::Eqz(val_type(&kind))),
+                            _ => bail!(self.span, "Invalid operand type for \"!\": {}", kind)
+                        }
+                        parser::PlatTypes::I32
+                    },
+                    lexer::PlatTokenKinds::Minus => {
+                        let kind = self.infer_type(&unary.expr)?.or(expected).unwrap_or_else(|| Compiler::default_type(&unary.expr));
+                        match (&unary.expr, &kind) {
+                            (parser::Expr::Literal(value, _), _) if !is_identifier(value) => {
+                                self.compile_const(&format!("-{}", value), &kind)?;
+                                self.record(&unary.expr, kind.clone());
+                            },
+                            (_, parser::PlatTypes::I32) | (_, parser::PlatTypes::I64) => {
+                                self.compile_const("0", &kind)?;
+                                self.compile_operand(&unary.expr, kind.clone())?;
+                                self.emit(Instr::Binary(val_type(&kind), ir::BinOp::Sub));
+                            },
+                            (_, parser::PlatTypes::F32) | (_, parser::PlatTypes::F64) => {
+                                self.compile_operand(&unary.expr, kind.clone())?;
+                                self.emit(Instr::Unary(val_type(&kind), ir::UnOp::Neg));
+                            },
+                            _ => bail!(self.span, "Invalid operand type for \"-\": {}", kind)
+                        }
+                        kind
                     },
-                    parser::Expr::Group(_) => todo!(),
+                    _ => panic!("Invalid unary operator {:?}", unary.operator)
+                }
+            },
+            parser::Expr::Binary(binary) => {
+                let comparison = is_comparison(&binary.operator.kind);
+                let inferred = match self.infer_type(&binary.left)? {
+                    Some(kind) => Some(kind),
+                    None => self.infer_type(&binary.right)?
                 };
+                let kind = inferred
+                    .or(if comparison {None} else {expected})
+                    .unwrap_or_else(|| Compiler::default_type(&binary.left));
+                self.compile_operand(&binary.left, kind.clone())?;
+                self.compile_operand(&binary.right, kind.clone())?;
+                self.emit(binary_instr(&binary.operator, &kind, self.span)?);
+                if comparison {parser::PlatTypes::I32} else {kind}
+            },
+            parser::Expr::Str(value, _) => {
+                let address = self.program.intern_string(value);
+                self.emit(Instr::I32Const(address.try_into().unwrap()));
+                parser::PlatTypes::Str
+            },
+            parser::Expr::Group(expr, _) => self.compile_expr(expr, expected)?,
+            parser::Expr::Call(call) => {
+                match intrinsics::lookup(&call.name) {
+                    Some(intrinsic) => self.compile_intrinsic(intrinsic, call)?,
+                    None => self.compile_call(call)?
+                }
+            },
+            parser::Expr::StructLit(lit) => self.compile_struct_lit(lit)?,
+            parser::Expr::Field(field) => {
+                let (kind, offset) = self.compile_field(field)?;
+                let op = load_kind(&kind);
+                self.emit(Instr::Load(op, ir::MemArg { align: op.natural_align(), offset }));
+                kind
             },
-            _ => {assert!(false, "Invalid definition type! Type: {:?}", expr.kind)}
+            parser::Expr::ArrayLit(lit) => self.compile_array_lit(lit, expected)?,
+            parser::Expr::Index(index) => {
+                let (kind, offset) = self.compile_index(index)?;
+                let op = load_kind(&kind);
+                self.emit(Instr::Load(op, ir::MemArg { align: op.natural_align(), offset }));
+                kind
+            },
+            parser::Expr::Variant(variant) => self.compile_variant(variant)?,
+            parser::Expr::Match(expr) => self.compile_match(expr, expected)?
+        })
+    }
+
+    fn alloc_fn(&mut self) -> Result<u32, Diagnostic> {
+        match self.allocator {
+            runtime::Allocator::Bump => self.runtime_fn(runtime::RuntimeFn::Alloc),
+            runtime::Allocator::FreeList => self.runtime_fn(runtime::RuntimeFn::FreeListAlloc)
+        }
+    }
+
+    // Allocates `size` bytes on the heap, returning the hidden local that holds the address
+    fn compile_alloc(&mut self, size: u32, align: u32, prefix: &str, kind: parser::PlatTypes) -> Result<u32, Diagnostic> {
+        let local = self.add_temp(prefix, kind);
+        let alloc = self.alloc_fn()?;
+        self.emit(Instr::I32Const(size.try_into().unwrap()));
+        self.emit(Instr::I32Const(align.try_into().unwrap()));
+        self.emit(Instr::Call(alloc));
+        self.emit(Instr::LocalSet(local));
+        Ok(local)
+    }
+
+    fn compile_store(&mut self, address: u32, value: &parser::Expr, kind: parser::PlatTypes, offset: u32) -> Result<(), Diagnostic> {
+        self.emit(Instr::LocalGet(address));
+        self.compile_operand(value, kind.clone())?;
+        let op = store_kind(&kind);
+        self.emit(Instr::Store(op, ir::MemArg { align: op.natural_align(), offset }));
+        Ok(())
+    }
+
+    fn compile_variant(&mut self, expr: &parser::VariantExpr) -> Result<parser::PlatTypes, Diagnostic> {
+        let layout = self.find_enum(&expr.enum_name)?;
+        let (size, align) = (layout.size, layout.align);
+        let (tag, variant) = layout.variant(&expr.enum_name, &expr.variant, self.span)?;
+        let fields = variant.fields.clone();
+        ensure!(expr.args.len() == fields.len(), self.span, "The variant {}::{} expects {} values, got {}", expr.enum_name, expr.variant, fields.len(), expr.args.len());
+
+        let kind = parser::PlatTypes::Named(expr.enum_name.clone());
+        let instance = self.compile_alloc(size, align, &expr.enum_name, kind.clone())?;
+        self.emit(Instr::LocalGet(instance));
+        self.emit(Instr::I32Const(tag.try_into().unwrap()));
+        self.emit(Instr::Store(ir::StoreKind::I32, ir::MemArg::natural(2)));
+        for (arg, (kind, offset)) in expr.args.iter().zip(fields) {
+            self.compile_store(instance, arg, kind, offset)?;
+        }
+        self.emit(Instr::LocalGet(instance));
+        Ok(kind)
+    }
+
+    // Every arm gets a block, nested so that the dispatch code sits in the innermost one and
+    // branching out of the block of arm i lands at the code of arm i:
+    //
+    // block $done (result T)
+    //   block $arm1
+    //     block $arm0
+    //       dispatch (br_table or a br_if per literal)
+    //     end
+    //     arm 0, br $done
+    //   end
+    //   arm 1
+    // end
+    fn compile_match(&mut self, expr: &parser::MatchExpr, expected: Option<parser::PlatTypes>) -> Result<parser::PlatTypes, Diagnostic> {
+        let scrutinee = self.infer_or_default(&expr.expr)?;
+        let wildcard = expr.arms.iter().position(|arm| matches!(arm.pattern, parser::Pattern::Wildcard));
+        if let Some(position) = wildcard {
+            ensure!(position == expr.arms.len() - 1, self.span, "The match arms after `_` are unreachable");
+        }
+        let value = self.add_temp("match", scrutinee.clone());
+        self.compile_operand(&expr.expr, scrutinee.clone())?;
+        self.emit(Instr::LocalSet(value));
+        let dispatch = match &scrutinee {
+            parser::PlatTypes::Named(name) if self.enums.contains_key(name) => self.enum_dispatch(name, expr, value, wildcard)?,
+            parser::PlatTypes::I32 | parser::PlatTypes::I64 => self.literal_dispatch(&scrutinee, expr, value, wildcard)?,
+            kind => bail!(self.span, "Can't match on {}", kind)
         };
 
-        self.program.sec_code.append(&mut body);
+        let arm_bindings = expr.arms.iter().map(|arm| self.pattern_bindings(&scrutinee, &arm.pattern)).collect::<Result<Vec<_>, _>>()?;
+        let mut inferred = None;
+        for (arm, bindings) in expr.arms.iter().zip(arm_bindings.iter()) {
+            inferred = self.infer_type_in(&arm.body, &bindings.iter().map(|(name, kind, _)| (name.clone(), kind.clone())).collect::<Vec<_>>())?;
+            if inferred.is_some() {
+                break;
+            }
+        }
+        let kind = inferred
+            .or(expected)
+            .unwrap_or_else(|| Compiler::default_type(&expr.arms[0].body));
+
+        let mut code = dispatch;
+        let n_arms = expr.arms.len();
+        for (i, (arm, bindings)) in expr.arms.iter().zip(arm_bindings).enumerate() {
+            let arm_code = self.capture(|compiler| {
+                let scope = compiler.functions.last().unwrap().scope.len();
+                for (name, binding_kind, offset) in bindings {
+                    let op = load_kind(&binding_kind);
+                    let local = compiler.add_binding(&name, binding_kind);
+                    compiler.emit(Instr::LocalGet(value));
+                    compiler.emit(Instr::Load(op, ir::MemArg { align: op.natural_align(), offset }));
+                    compiler.emit(Instr::LocalSet(local));
+                }
+                compiler.compile_operand(&arm.body, kind.clone())?;
+                compiler.functions.last_mut().unwrap().scope.truncate(scope);
+                Ok(())
+            })?;
+            code = [vec![Instr::Block(ir::BlockType::Empty, code)], arm_code].concat();
+            if i + 1 < n_arms {
+                code.push(Instr::Br((n_arms - 1 - i).try_into().unwrap()));
+            }
+        }
+        let block_type = if kind == parser::PlatTypes::Void {ir::BlockType::Empty} else {ir::BlockType::Value(val_type(&kind))};
+        self.emit(Instr::Block(block_type, code));
+        Ok(kind)
+    }
+
+    // br_table over the tag, every variant has to be covered by an arm or `_`
+    fn enum_dispatch(&self, name: &str, expr: &parser::MatchExpr, value: u32, wildcard: Option<usize>) -> Result<Vec<Instr>, Diagnostic> {
+        let layout = self.find_enum(name)?;
+        let mut labels: Vec<Option<u32>> = vec![None; layout.variants.len()];
+        for (i, arm) in expr.arms.iter().enumerate() {
+            match &arm.pattern {
+                parser::Pattern::Wildcard => {},
+                parser::Pattern::Variant { enum_name, variant, .. } => {
+                    ensure!(enum_name == name, self.span, "Can't match {} against {}", name, arm.pattern);
+                    let (tag, _) = layout.variant(enum_name, variant, self.span)?;
+                    ensure!(labels[tag as usize].is_none(), self.span, "The match arm {} is unreachable", arm.pattern);
+                    labels[tag as usize] = Some(i.try_into().unwrap());
+                },
+                parser::Pattern::Literal(_) => bail!(self.span, "Can't match {} against {}", name, arm.pattern)
+            }
+        }
+        let wildcard = wildcard.map(|position| position.try_into().unwrap());
+        let labels: Vec<u32> = labels.into_iter().zip(layout.variants.iter()).map(|(label, variant)| {
+            label.or(wildcard).ok_or_else(|| self.error(format!("The match on {} doesn't cover {}::{}", name, name, variant.name)))
+        }).collect::<Result<_, _>>()?;
+        let default = wildcard.unwrap_or(*labels.last().unwrap());
+        Ok(vec![Instr::LocalGet(value), Instr::Load(ir::LoadKind::I32, ir::MemArg::natural(2)), Instr::BrTable(labels, default)])
+    }
+
+    // br_table when the literals are dense i32 values, otherwise a br_if per literal. Integer matches need a `_` arm.
+    fn literal_dispatch(&self, kind: &parser::PlatTypes, expr: &parser::MatchExpr, value: u32, wildcard: Option<usize>) -> Result<Vec<Instr>, Diagnostic> {
+        let wildcard: u32 = wildcard.ok_or_else(|| self.error(format!("The match on {} needs a `_` arm", kind)))?.try_into().unwrap();
+        let mut literals: Vec<(i64, u32)> = vec![];
+        for (i, arm) in expr.arms.iter().enumerate() {
+            match &arm.pattern {
+                parser::Pattern::Wildcard => {},
+                parser::Pattern::Literal(literal) => {
+                    let parsed = match kind {
+                        parser::PlatTypes::I32 => literal.parse::<i32>().map(i64::from).ok(),
+                        _ => literal.parse::<i64>().ok()
+                    }.ok_or_else(|| self.error(format!("Invalid {} pattern {}", kind, literal)))?;
+                    ensure!(literals.iter().all(|(other, _)| *other != parsed), self.span, "The match arm {} is unreachable", arm.pattern);
+                    literals.push((parsed, i.try_into().unwrap()));
+                },
+                parser::Pattern::Variant { .. } => bail!(self.span, "Can't match {} against {}", kind, arm.pattern)
+            }
+        }
+
+        let (min, max) = (literals.iter().map(|(v, _)| *v).min().unwrap_or(0), literals.iter().map(|(v, _)| *v).max().unwrap_or(0));
+        let range = max - min + 1;
+        if *kind == parser::PlatTypes::I32 && !literals.is_empty() && range <= 2 * literals.len() as i64 {
+            let labels = (min..=max).map(|v| literals.iter().find(|(literal, _)| *literal == v).map_or(wildcard, |(_, label)| *label)).collect();
+            let mut code = vec![Instr::LocalGet(value)];
+            if min != 0 {
+                code.extend([Instr::I32Const(min as i32), Instr::Binary(ir::ValType::I32, ir::BinOp::Sub)]);
+            }
+            code.push(Instr::BrTable(labels, wildcard));
+            return Ok(code);
+        }
+        let mut code = vec![];
+        for (literal, label) in literals {
+            code.push(Instr::LocalGet(value));
+            code.push(if *kind == parser::PlatTypes::I32 {Instr::I32Const(literal as i32)} else {Instr::I64Const(literal)});
+            code.push(Instr::Compare(val_type(kind), ir::RelOp::Eq));
+            code.push(Instr::BrIf(label));
+        }
+        code.push(Instr::Br(wildcard));
+        Ok(code)
+    }
+
+    fn compile_array_lit(&mut self, expr: &parser::ArrayLitExpr, expected: Option<parser::PlatTypes>) -> Result<parser::PlatTypes, Diagnostic> {
+        let elem = match expected {
+            Some(parser::PlatTypes::Array(elem, _)) => *elem,
+            _ => self.infer_or_default(&expr.elements[0])?
+        };
+        let count = expr.count.unwrap_or(expr.elements.len().try_into().unwrap());
+        let kind = parser::PlatTypes::Array(Box::new(elem.clone()), count);
+        self.check_type(&kind)?;
+        let (size, store) = (slot_size(&elem), store_kind(&elem));
+        let memarg = |offset| ir::MemArg { align: store.natural_align(), offset };
+
+        let array = self.compile_alloc(count * size, size, "array", kind.clone())?;
+        if expr.count.is_none() {
+            for (i, element) in expr.elements.iter().enumerate() {
+                self.compile_store(array, element, elem.clone(), <usize as TryInto<u32>>::try_into(i).unwrap() * size)?;
+            }
+        } else {
+            // [value; count] stores the value once per element in a loop
+            let value = self.add_temp("fill", elem.clone());
+            let index = self.add_temp("index", parser::PlatTypes::I32);
+            self.compile_operand(&expr.elements[0], elem)?;
+            self.emit(Instr::LocalSet(value));
+            self.emit(Instr::I32Const(0));
+            self.emit(Instr::LocalSet(index));
+            self.emit(Instr::Block(ir::BlockType::Empty, vec![Instr::Loop(ir::BlockType::Empty, vec![
+                Instr::LocalGet(index), Instr::I32Const(count.try_into().unwrap()), Instr::Compare(ir::ValType::I32, ir::RelOp::GeU), Instr::BrIf(1),
+                Instr::LocalGet(array), Instr::LocalGet(index), Instr::I32Const(size.try_into().unwrap()), Instr::Binary(ir::ValType::I32, ir::BinOp::Mul),
+                Instr::Binary(ir::ValType::I32, ir::BinOp::Add), Instr::LocalGet(value), Instr::Store(store, memarg(0)),
+                Instr::LocalGet(index), Instr::I32Const(1), Instr::Binary(ir::ValType::I32, ir::BinOp::Add), Instr::LocalSet(index),
+                Instr::Br(0)
+            ])]));
+        }
+        self.emit(Instr::LocalGet(array));
+        Ok(kind)
+    }
+
+    // Pushes the address of the element, returning its type and the offset of a constant index.
+    // Dynamic indices trap with unreachable when they are out of bounds, unless bounds checks are off.
+    fn compile_index(&mut self, expr: &parser::IndexExpr) -> Result<(parser::PlatTypes, u32), Diagnostic> {
+        let (elem, count) = match self.compile_expr(&expr.expr, None)? {
+            parser::PlatTypes::Array(elem, count) => (*elem, count),
+            kind => bail!(self.span, "Can't index {}", kind)
+        };
+        let size = slot_size(&elem);
+        if let parser::Expr::Literal(value, _) = &expr.index {
+            if !is_identifier(value) {
+                let index = value.parse::<u32>().map_err(|_| self.error(format!("Invalid index {}", value)))?;
+                ensure!(index < count, self.span, "The index {} is out of bounds for an array of length {}", index, count);
+                return Ok((elem, index * size));
+            }
+        }
+
+        self.compile_operand(&expr.index, parser::PlatTypes::I32)?;
+        if self.bounds_checks {
+            let index = self.add_temp("index", parser::PlatTypes::I32);
+            self.emit(Instr::LocalTee(index));
+            self.emit(Instr::I32Const(count.try_into().unwrap()));
+            self.emit(Instr::Compare(ir::ValType::I32, ir::RelOp::GeU));
+            self.emit(Instr::If(ir::BlockType::Empty, vec![Instr::Unreachable], vec![]));
+            self.emit(Instr::LocalGet(index));
+        }
+        self.emit(Instr::I32Const(size.try_into().unwrap()));
+        self.emit(Instr::Binary(ir::ValType::I32, ir::BinOp::Mul));
+        self.emit(Instr::Binary(ir::ValType::I32, ir::BinOp::Add));
+        Ok((elem, 0))
+    }
+
+    // Allocates the instance and stores the fields in the order they are written
+    fn compile_struct_lit(&mut self, expr: &parser::StructLitExpr) -> Result<parser::PlatTypes, Diagnostic> {
+        let layout = self.find_struct(&expr.name)?;
+        let (size, align) = (layout.size, layout.align);
+        let mut fields: Vec<(&str, parser::PlatTypes, u32, &parser::Expr)> = vec![];
+        for (name, value) in expr.fields.iter() {
+            let field = layout.field(&expr.name, name, self.span)?;
+            ensure!(fields.iter().all(|(other, ..)| other != name), self.span, "The field {}.{} is initialized more than once", expr.name, name);
+            fields.push((name, field.kind.clone(), field.offset, value));
+        }
+        if let Some(missing) = layout.fields.iter().find(|field| fields.iter().all(|(name, ..)| *name != field.name)) {
+            bail!(self.span, "The field {}.{} is not initialized", expr.name, missing.name);
+        }
+
+        let instance = self.compile_alloc(size, align, &expr.name, parser::PlatTypes::Named(expr.name.clone()))?;
+        for (_, kind, offset, value) in fields {
+            self.compile_store(instance, value, kind, offset)?;
+        }
+        self.emit(Instr::LocalGet(instance));
+        Ok(parser::PlatTypes::Named(expr.name.clone()))
+    }
+
+    // Pushes the address of the instance, returning the field's type and offset
+    fn compile_field(&mut self, expr: &parser::FieldExpr) -> Result<(parser::PlatTypes, u32), Diagnostic> {
+        match self.compile_expr(&expr.expr, None)? {
+            parser::PlatTypes::Named(name) => {
+                let field = self.find_struct(&name)?.field(&name, &expr.field, self.span)?;
+                Ok((field.kind.clone(), field.offset))
+            },
+            kind => bail!(self.span, "Can't access the field {} of {}", expr.field, kind)
+        }
+    }
+
+    fn compile_def(&mut self, expr: &parser::DefExpr) -> Result<(), Diagnostic> {
+        ensure!(expr.kind != parser::PlatTypes::Void, self.span, "Invalid definition type! Type: {}", expr.kind);
+        let value = expr.value.as_ref().ok_or_else(|| self.error(format!("The variable {} has no value...", expr.name)))?;
+        let kind = self.compile_expr(value, Some(expr.kind.clone()))?;
+        ensure!(kind == expr.kind, self.span, "Invalid definition value, expected {} got {}", expr.kind, kind);
+
+        let current_fn = self.functions.last_mut().unwrap();
+        let var = current_fn.args.iter_mut().chain(current_fn.vars.iter_mut()).find(|var| !var.binding && var.name == expr.name).unwrap();
+        var.defined = true;
+        let var_index = var.index;
+        self.emit(Instr::LocalSet(var_index));
+        Ok(())
     }
 
-    fn compile_ret(&mut self, expr: &Option<parser::Expr>) {
-        match expr.as_ref().unwrap() {
-            parser::Expr::Literal(name) => {
-                let var: &Variable = self.functions.last().unwrap().vars.iter().find(|var| &var.name == name).expect(&format!("The variable {} does not exist...", name));
-                let ret_kind: parser::PlatTypes = self.functions.last().unwrap().ret_kind;
-                if  ret_kind != var.kind {assert!(false, "Invalid return type, expected {:?} got {:?}", ret_kind, var.kind)}
-                self.program.sec_code.append(&mut vec![0x20, var.index, 0x0F]); // local.get (var_index) return
+    fn compile_assign(&mut self, expr: &parser::AssignExpr) -> Result<(), Diagnostic> {
+        let kind = match &expr.target {
+            parser::Expr::Literal(name, _) if is_identifier(name) => {
+                let var = self.find_var(name)?;
+                let (index, kind) = (var.index, var.kind.clone());
+                self.compile_operand(&expr.value, kind.clone())?;
+                self.emit(Instr::LocalSet(index));
+                kind
             },
-            parser::Expr::Unary(expr) => {println!("ret unary  {:?}", expr); todo!()},
-            parser::Expr::Binary(expr) => {println!("ret binary  {:?}", expr); todo!()},
-            parser::Expr::Group(expr) => {println!("ret group  {:?}", expr); todo!()},
+            parser::Expr::Field(field) => {
+                let (kind, offset) = self.compile_field(field)?;
+                self.compile_operand(&expr.value, kind.clone())?;
+                let op = store_kind(&kind);
+                self.emit(Instr::Store(op, ir::MemArg { align: op.natural_align(), offset }));
+                kind
+            },
+            parser::Expr::Index(index) => {
+                let (kind, offset) = self.compile_index(index)?;
+                self.compile_operand(&expr.value, kind.clone())?;
+                let op = store_kind(&kind);
+                self.emit(Instr::Store(op, ir::MemArg { align: op.natural_align(), offset }));
+                kind
+            },
+            target => bail!(self.span, "Can't assign to {}", target)
         };
+        self.record(&expr.target, kind);
+        Ok(())
+    }
+
+    fn compile_ret(&mut self, expr: &Option<parser::Expr>) -> Result<(), Diagnostic> {
+        let ret_kind: parser::PlatTypes = self.functions.last().unwrap().ret_kind.clone();
+        match expr.as_ref() {
+            Some(expr) => {
+                let kind = self.compile_expr(expr, Some(ret_kind.clone()))?;
+                if ret_kind != kind {bail!(self.span, "Invalid return type, expected {} got {}", ret_kind, kind)}
+            },
+            None => ensure!(ret_kind == parser::PlatTypes::Void, self.span, "Invalid return type, expected {} got void", ret_kind)
+        };
+        self.emit(Instr::Return);
+        Ok(())
+    }
+
+    fn compile_stmt_expr(&mut self, expr: &parser::Expr) -> Result<(), Diagnostic> {
+        if self.compile_expr(expr, None)? != parser::PlatTypes::Void {
+            self.emit(Instr::Drop);
+        }
+        Ok(())
     }
 
-    pub fn compile_node(&mut self, node: &parser::AST) {
+    pub fn compile_node(&mut self, node: &parser::AST) -> Result<(), Diagnostic> {
+        self.span = node.span();
+        if !matches!(node, parser::AST::Func(_) | parser::AST::Struct(_) | parser::AST::Enum(_)) {
+            self.locate(node.span());
+        }
         match node {
-            parser::AST::Func(expr) => self.compile_fn(expr),
-            parser::AST::Def(expr) => self.compile_def(expr),
-            parser::AST::Ret(expr) => self.compile_ret(expr),
+            parser::AST::Func(expr) => self.compile_fn(expr)?,
+            parser::AST::Struct(_) | parser::AST::Enum(_) => {}, // Layouts are computed up front by declare_types
+            parser::AST::Def(expr) => self.compile_def(expr)?,
+            parser::AST::Assign(expr) => self.compile_assign(expr)?,
+            parser::AST::Ret(expr, _) => self.compile_ret(expr)?,
+            parser::AST::Expr(expr, _) => self.compile_stmt_expr(expr)?,
         }
+        Ok(())
+    }
+
+    // Source map of the last compiled module, if source maps are enabled
+    pub fn source_map_json(&self) -> Option<&str> {
+        self.map.as_deref()
+    }
+
+    // The module built by the last call to compile
+    pub fn module(&self) -> &ir::Module {
+        &self.program.module
     }
 
-    pub fn compile(&mut self, ast: Vec<parser::AST>) -> Vec<u8> {
-        self.program.n_expos = 1;
-        self.program.sec_expo.append(&mut vec![0x04, 0x6D, 0x61, 0x69, 0x6E, 0x00, 0x00]);
+    pub fn compile(&mut self, ast: &[parser::AST]) -> Result<Vec<u8>, Diagnostic> {
+        self.declare_fns(ast)?;
+
+        if let Some(maximum) = self.memory.maximum {
+            ensure!(maximum >= self.memory.initial, self.span, "The maximum memory size has to be at least the initial size");
+        }
+        self.program.module.memories.push(ir::Limits { min: self.memory.initial, max: self.memory.maximum });
+        if self.target == Target::Wasi {
+            ensure!(self.memory.export.as_deref() == Some("memory"), self.span, "The wasi target has to export its memory as \"memory\"");
+        }
+        if let Some(name) = &self.memory.export {
+            self.program.add_export(name, ir::ExportKind::Memory, 0);
+        }
 
         for node in ast.iter() {
-            self.compile_node(node);
+            ensure!(matches!(node, parser::AST::Func(_) | parser::AST::Struct(_) | parser::AST::Enum(_)), node.span(), "Statements have to be inside a function");
+            self.compile_node(node)?;
         }
+        self.compile_runtime();
+        if let Some(heap) = &self.heap {
+            let base = Instr::I32Const(self.program.data_end().next_multiple_of(runtime::HEAP_ALIGN).try_into().unwrap());
+            self.program.module.globals[heap.base as usize].init = base.clone();
+            self.program.module.globals[heap.top as usize].init = base;
+        }
+        if self.target == Target::Wasi && self.start {
+            self.compile_start()?;
+        }
+        optimizer::optimize(&mut self.program.module, self.opt_level);
+        optimizer::group_locals(&mut self.program.module);
+        if self.names {
+            let bytes = encoder::name_section(&self.program.module);
+            self.program.module.customs.push(ir::Custom { name: "name".to_string(), bytes });
+        }
+        if self.source_map {
+            let source_name = self.source_name.as_deref().ok_or_else(|| Diagnostic { message: "Source maps need the name of the source file".to_string(), span: None })?;
+            let url = self.source_map_url.clone().unwrap_or_else(|| format!("{}.map", Path::new(source_name).with_extension("wasm").to_string_lossy()));
+            let mut bytes = vec![];
+            encoder::encode_name(&url, &mut bytes);
+            self.program.module.customs.push(ir::Custom { name: "sourceMappingURL".to_string(), bytes });
+        }
+
+        let pages_needed = self.program.data_end().div_ceil(PAGE_SIZE);
+        ensure!(self.memory.initial >= pages_needed, self.span, "The data segment needs {} pages of memory but only {} are configured", pages_needed, self.memory.initial);
         
-        self.program.unite()
+        let (bytes, locations) = self.program.unite();
+        if self.source_map {
+            self.map = Some(sourcemap::generate(self.source_name.as_deref().unwrap(), &self.source, &locations));
+        }
+        if self.validate {
+            if let Err(errors) = validator::validate(&bytes) {
+                panic!("The compiled module is invalid:\n{}", errors.join("\n"));
+            }
+        }
+        Ok(bytes)
     }
 }
\ No newline at end of file
@@ -0,0 +1,431 @@
+
+use crate::leb128;
+
+// Decodes WASM binaries produced by the compiler back into a structured module.
+// Only the subset of the format the compiler emits is supported.
+
+pub const I32: u8 = 0x7F;
+pub const I64: u8 = 0x7E;
+pub const F32: u8 = 0x7D;
+pub const F64: u8 = 0x7C;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FuncType {
+    pub params: Vec<u8>,
+    pub results: Vec<u8>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub type_index: u32
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Limits {
+    pub min: u32,
+    pub max: Option<u32>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Global {
+    pub kind: u8,
+    pub mutable: bool,
+    pub init: Vec<Instruction>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Export {
+    pub name: String,
+    pub kind: u8,
+    pub index: u32
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Immediate {
+    None,
+    Block(Option<u8>),
+    Index(u32),
+    BrTable(Vec<u32>, u32),
+    Memarg { align: u32, offset: u32 },
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64)
+}
+
+// Prefixed opcodes are stored as (prefix << 8) | sub-opcode
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub opcode: u32,
+    pub immediate: Immediate,
+    pub offset: usize
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Body {
+    pub locals: Vec<(u32, u8)>,
+    pub instructions: Vec<Instruction>,
+    pub offset: usize
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Data {
+    pub offset: Vec<Instruction>,
+    pub bytes: Vec<u8>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Custom {
+    pub name: String,
+    pub bytes: Vec<u8>
+}
+
+// Contents of the `name` custom section
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Names {
+    pub module: Option<String>,
+    pub functions: Vec<(u32, String)>,
+    pub locals: Vec<(u32, Vec<(u32, String)>)>,
+    pub globals: Vec<(u32, String)>
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<u32>,
+    pub memories: Vec<Limits>,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    pub code: Vec<Body>,
+    pub data: Vec<Data>,
+    pub customs: Vec<Custom>
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImmediateKind {
+    None,
+    Block,
+    Index,
+    BrTable,
+    Memarg,
+    Memory,
+    I32,
+    I64,
+    F32,
+    F64
+}
+
+pub fn value_type_name(kind: u8) -> Result<&'static str, String> {
+    match kind {
+        I32 => Ok("i32"),
+        I64 => Ok("i64"),
+        F32 => Ok("f32"),
+        F64 => Ok("f64"),
+        _ => Err(format!("Invalid value type 0x{:02X}", kind))
+    }
+}
+
+// log2 of the access size of a load or store
+pub fn natural_align(opcode: u32) -> Option<u32> {
+    match opcode {
+        0x2C | 0x2D | 0x30 | 0x31 | 0x3A | 0x3C => Some(0),
+        0x2E | 0x2F | 0x32 | 0x33 | 0x3B | 0x3D => Some(1),
+        0x28 | 0x2A | 0x34 | 0x35 | 0x36 | 0x38 | 0x3E => Some(2),
+        0x29 | 0x2B | 0x37 | 0x39 => Some(3),
+        _ => None
+    }
+}
+
+pub fn opcode_info(opcode: u32) -> Option<(&'static str, ImmediateKind)> {
+    use ImmediateKind as K;
+    let info = match opcode {
+        0x00 => ("unreachable", K::None), 0x01 => ("nop", K::None),
+        0x02 => ("block", K::Block), 0x03 => ("loop", K::Block), 0x04 => ("if", K::Block),
+        0x05 => ("else", K::None), 0x0B => ("end", K::None),
+        0x0C => ("br", K::Index), 0x0D => ("br_if", K::Index), 0x0E => ("br_table", K::BrTable),
+        0x0F => ("return", K::None), 0x10 => ("call", K::Index),
+        0x1A => ("drop", K::None), 0x1B => ("select", K::None),
+        0x20 => ("local.get", K::Index), 0x21 => ("local.set", K::Index), 0x22 => ("local.tee", K::Index),
+        0x23 => ("global.get", K::Index), 0x24 => ("global.set", K::Index),
+        0x28 => ("i32.load", K::Memarg), 0x29 => ("i64.load", K::Memarg),
+        0x2A => ("f32.load", K::Memarg), 0x2B => ("f64.load", K::Memarg),
+        0x2C => ("i32.load8_s", K::Memarg), 0x2D => ("i32.load8_u", K::Memarg),
+        0x2E => ("i32.load16_s", K::Memarg), 0x2F => ("i32.load16_u", K::Memarg),
+        0x30 => ("i64.load8_s", K::Memarg), 0x31 => ("i64.load8_u", K::Memarg),
+        0x32 => ("i64.load16_s", K::Memarg), 0x33 => ("i64.load16_u", K::Memarg),
+        0x34 => ("i64.load32_s", K::Memarg), 0x35 => ("i64.load32_u", K::Memarg),
+        0x36 => ("i32.store", K::Memarg), 0x37 => ("i64.store", K::Memarg),
+        0x38 => ("f32.store", K::Memarg), 0x39 => ("f64.store", K::Memarg),
+        0x3A => ("i32.store8", K::Memarg), 0x3B => ("i32.store16", K::Memarg),
+        0x3C => ("i64.store8", K::Memarg), 0x3D => ("i64.store16", K::Memarg), 0x3E => ("i64.store32", K::Memarg),
+        0x3F => ("memory.size", K::Memory), 0x40 => ("memory.grow", K::Memory),
+        0x41 => ("i32.const", K::I32), 0x42 => ("i64.const", K::I64),
+        0x43 => ("f32.const", K::F32), 0x44 => ("f64.const", K::F64),
+        0x45 => ("i32.eqz", K::None), 0x46 => ("i32.eq", K::None), 0x47 => ("i32.ne", K::None),
+        0x48 => ("i32.lt_s", K::None), 0x49 => ("i32.lt_u", K::None), 0x4A => ("i32.gt_s", K::None),
+        0x4B => ("i32.gt_u", K::None), 0x4C => ("i32.le_s", K::None), 0x4D => ("i32.le_u", K::None),
+        0x4E => ("i32.ge_s", K::None), 0x4F => ("i32.ge_u", K::None),
+        0x50 => ("i64.eqz", K::None), 0x51 => ("i64.eq", K::None), 0x52 => ("i64.ne", K::None),
+        0x53 => ("i64.lt_s", K::None), 0x54 => ("i64.lt_u", K::None), 0x55 => ("i64.gt_s", K::None),
+        0x56 => ("i64.gt_u", K::None), 0x57 => ("i64.le_s", K::None), 0x58 => ("i64.le_u", K::None),
+        0x59 => ("i64.ge_s", K::None), 0x5A => ("i64.ge_u", K::None),
+        0x5B => ("f32.eq", K::None), 0x5C => ("f32.ne", K::None), 0x5D => ("f32.lt", K::None),
+        0x5E => ("f32.gt", K::None), 0x5F => ("f32.le", K::None), 0x60 => ("f32.ge", K::None),
+        0x61 => ("f64.eq", K::None), 0x62 => ("f64.ne", K::None), 0x63 => ("f64.lt", K::None),
+        0x64 => ("f64.gt", K::None), 0x65 => ("f64.le", K::None), 0x66 => ("f64.ge", K::None),
+        0x67 => ("i32.clz", K::None), 0x68 => ("i32.ctz", K::None), 0x69 => ("i32.popcnt", K::None),
+        0x6A => ("i32.add", K::None), 0x6B => ("i32.sub", K::None), 0x6C => ("i32.mul", K::None),
+        0x6D => ("i32.div_s", K::None), 0x6E => ("i32.div_u", K::None), 0x6F => ("i32.rem_s", K::None),
+        0x70 => ("i32.rem_u", K::None), 0x71 => ("i32.and", K::None), 0x72 => ("i32.or", K::None),
+        0x73 => ("i32.xor", K::None), 0x74 => ("i32.shl", K::None), 0x75 => ("i32.shr_s", K::None),
+        0x76 => ("i32.shr_u", K::None), 0x77 => ("i32.rotl", K::None), 0x78 => ("i32.rotr", K::None),
+        0x79 => ("i64.clz", K::None), 0x7A => ("i64.ctz", K::None), 0x7B => ("i64.popcnt", K::None),
+        0x7C => ("i64.add", K::None), 0x7D => ("i64.sub", K::None), 0x7E => ("i64.mul", K::None),
+        0x7F => ("i64.div_s", K::None), 0x80 => ("i64.div_u", K::None), 0x81 => ("i64.rem_s", K::None),
+        0x82 => ("i64.rem_u", K::None), 0x83 => ("i64.and", K::None), 0x84 => ("i64.or", K::None),
+        0x85 => ("i64.xor", K::None), 0x86 => ("i64.shl", K::None), 0x87 => ("i64.shr_s", K::None),
+        0x88 => ("i64.shr_u", K::None), 0x89 => ("i64.rotl", K::None), 0x8A => ("i64.rotr", K::None),
+        0x8B => ("f32.abs", K::None), 0x8C => ("f32.neg", K::None), 0x8D => ("f32.ceil", K::None),
+        0x8E => ("f32.floor", K::None), 0x8F => ("f32.trunc", K::None), 0x90 => ("f32.nearest", K::None),
+        0x91 => ("f32.sqrt", K::None), 0x92 => ("f32.add", K::None), 0x93 => ("f32.sub", K::None),
+        0x94 => ("f32.mul", K::None), 0x95 => ("f32.div", K::None), 0x96 => ("f32.min", K::None),
+        0x97 => ("f32.max", K::None), 0x98 => ("f32.copysign", K::None),
+        0x99 => ("f64.abs", K::None), 0x9A => ("f64.neg", K::None), 0x9B => ("f64.ceil", K::None),
+        0x9C => ("f64.floor", K::None), 0x9D => ("f64.trunc", K::None), 0x9E => ("f64.nearest", K::None),
+        0x9F => ("f64.sqrt", K::None), 0xA0 => ("f64.add", K::None), 0xA1 => ("f64.sub", K::None),
+        0xA2 => ("f64.mul", K::None), 0xA3 => ("f64.div", K::None), 0xA4 => ("f64.min", K::None),
+        0xA5 => ("f64.max", K::None), 0xA6 => ("f64.copysign", K::None),
+        0xA7 => ("i32.wrap_i64", K::None), 0xA8 => ("i32.trunc_f32_s", K::None), 0xA9 => ("i32.trunc_f32_u", K::None),
+        0xAA => ("i32.trunc_f64_s", K::None), 0xAB => ("i32.trunc_f64_u", K::None),
+        0xAC => ("i64.extend_i32_s", K::None), 0xAD => ("i64.extend_i32_u", K::None),
+        0xAE => ("i64.trunc_f32_s", K::None), 0xAF => ("i64.trunc_f32_u", K::None),
+        0xB0 => ("i64.trunc_f64_s", K::None), 0xB1 => ("i64.trunc_f64_u", K::None),
+        0xB2 => ("f32.convert_i32_s", K::None), 0xB3 => ("f32.convert_i32_u", K::None),
+        0xB4 => ("f32.convert_i64_s", K::None), 0xB5 => ("f32.convert_i64_u", K::None),
+        0xB6 => ("f32.demote_f64", K::None),
+        0xB7 => ("f64.convert_i32_s", K::None), 0xB8 => ("f64.convert_i32_u", K::None),
+        0xB9 => ("f64.convert_i64_s", K::None), 0xBA => ("f64.convert_i64_u", K::None),
+        0xBB => ("f64.promote_f32", K::None),
+        0xBC => ("i32.reinterpret_f32", K::None), 0xBD => ("i64.reinterpret_f64", K::None),
+        0xBE => ("f32.reinterpret_i32", K::None), 0xBF => ("f64.reinterpret_i64", K::None),
+        0xC0 => ("i32.extend8_s", K::None), 0xC1 => ("i32.extend16_s", K::None),
+        0xC2 => ("i64.extend8_s", K::None), 0xC3 => ("i64.extend16_s", K::None), 0xC4 => ("i64.extend32_s", K::None),
+        0xFC00 => ("i32.trunc_sat_f32_s", K::None), 0xFC01 => ("i32.trunc_sat_f32_u", K::None),
+        0xFC02 => ("i32.trunc_sat_f64_s", K::None), 0xFC03 => ("i32.trunc_sat_f64_u", K::None),
+        0xFC04 => ("i64.trunc_sat_f32_s", K::None), 0xFC05 => ("i64.trunc_sat_f32_u", K::None),
+        0xFC06 => ("i64.trunc_sat_f64_s", K::None), 0xFC07 => ("i64.trunc_sat_f64_u", K::None),
+        _ => return None
+    };
+    Some(info)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.pos).ok_or_else(|| format!("Unexpected end of module at offset {}", self.pos))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err(format!("Unexpected end of module at offset {}, {} bytes missing", self.pos, len - (self.bytes.len() - self.pos)));
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        leb128::decode_u32(self.bytes, &mut self.pos)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        let offset = self.pos;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| format!("Invalid UTF-8 name at offset {}", offset))
+    }
+
+    fn value_type(&mut self) -> Result<u8, String> {
+        let offset = self.pos;
+        let kind = self.byte()?;
+        value_type_name(kind).map_err(|error| format!("{} at offset {}", error, offset))?;
+        Ok(kind)
+    }
+
+    fn vec<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let count = self.u32()?;
+        (0..count).map(|_| item(self)).collect()
+    }
+
+    fn limits(&mut self) -> Result<Limits, String> {
+        match self.byte()? {
+            0x00 => Ok(Limits { min: self.u32()?, max: None }),
+            0x01 => Ok(Limits { min: self.u32()?, max: Some(self.u32()?) }),
+            flag => Err(format!("Invalid limits flag 0x{:02X} at offset {}", flag, self.pos - 1))
+        }
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, String> {
+        let offset = self.pos;
+        let mut opcode = self.byte()? as u32;
+        if opcode == 0xFC {
+            opcode = 0xFC00 | self.u32()?;
+        }
+        let (_, kind) = opcode_info(opcode).ok_or_else(|| format!("Unknown opcode 0x{:02X} at offset {}", opcode, offset))?;
+        let immediate = match kind {
+            ImmediateKind::None => Immediate::None,
+            ImmediateKind::Block => match self.byte()? {
+                0x40 => Immediate::Block(None),
+                kind => Immediate::Block(Some(value_type_name(kind).map(|_| kind).map_err(|error| format!("{} at offset {}", error, self.pos - 1))?))
+            },
+            ImmediateKind::Index => Immediate::Index(self.u32()?),
+            ImmediateKind::BrTable => Immediate::BrTable(self.vec(|reader| reader.u32())?, self.u32()?),
+            ImmediateKind::Memarg => Immediate::Memarg { align: self.u32()?, offset: self.u32()? },
+            ImmediateKind::Memory => match self.byte()? {
+                0x00 => Immediate::None,
+                index => return Err(format!("Invalid memory index {} at offset {}", index, self.pos - 1))
+            },
+            ImmediateKind::I32 => Immediate::I32(leb128::decode_i32(self.bytes, &mut self.pos)?),
+            ImmediateKind::I64 => Immediate::I64(leb128::decode_i64(self.bytes, &mut self.pos)?),
+            ImmediateKind::F32 => Immediate::F32(f32::from_le_bytes(self.take(4)?.try_into().unwrap())),
+            ImmediateKind::F64 => Immediate::F64(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+        };
+        Ok(Instruction { opcode, immediate, offset })
+    }
+
+    // Reads instructions up to and including the `end` closing the expression
+    fn expression(&mut self) -> Result<Vec<Instruction>, String> {
+        let mut instructions = vec![];
+        let mut depth = 0;
+        loop {
+            let instruction = self.instruction()?;
+            match instruction.opcode {
+                0x02..=0x04 => depth += 1,
+                0x0B if depth == 0 => {instructions.push(instruction); return Ok(instructions)},
+                0x0B => depth -= 1,
+                _ => {}
+            }
+            instructions.push(instruction);
+        }
+    }
+}
+
+// Offsets in errors are relative to the start of the section content (after its name)
+pub fn decode_names(bytes: &[u8]) -> Result<Names, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut names = Names::default();
+    let mut last_id = None;
+    while reader.pos < bytes.len() {
+        let offset = reader.pos;
+        let id = reader.byte()?;
+        if last_id.is_some_and(|last_id| id <= last_id) {
+            return Err(format!("Name subsection {} at offset {} is out of order or duplicated", id, offset));
+        }
+        last_id = Some(id);
+        let size = reader.u32()? as usize;
+        let content = reader.take(size)?;
+        let mut subsection = Reader { bytes: &bytes[..reader.pos], pos: reader.pos - content.len() };
+        match id {
+            0x00 => names.module = Some(subsection.name()?),
+            0x01 => names.functions = subsection.vec(|reader| Ok((reader.u32()?, reader.name()?)))?,
+            0x02 => names.locals = subsection.vec(|reader| Ok((reader.u32()?, reader.vec(|reader| Ok((reader.u32()?, reader.name()?)))?)))?,
+            0x07 => names.globals = subsection.vec(|reader| Ok((reader.u32()?, reader.name()?)))?,
+            _ => subsection.pos = reader.pos // Other subsections are skipped
+        }
+        if subsection.pos != reader.pos {
+            return Err(format!("Name subsection {} at offset {} has size {} but its content is {} bytes", id, offset, size, subsection.pos - (reader.pos - size)));
+        }
+    }
+    Ok(names)
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Module, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4).map_err(|_| "Missing WASM magic".to_string())? != [0x00, 0x61, 0x73, 0x6D] {
+        return Err("Invalid WASM magic".to_string());
+    }
+    if reader.take(4).map_err(|_| "Missing WASM version".to_string())? != [0x01, 0x00, 0x00, 0x00] {
+        return Err("Unsupported WASM version".to_string());
+    }
+
+    let mut module = Module::default();
+    let mut last_id = 0;
+    while reader.pos < bytes.len() {
+        let section_offset = reader.pos;
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let content = reader.take(size)?;
+        if id != 0x00 {
+            if id <= last_id {
+                return Err(format!("Section {} at offset {} is out of order or duplicated", id, section_offset));
+            }
+            last_id = id;
+        }
+
+        let mut section = Reader { bytes: &bytes[..reader.pos], pos: reader.pos - content.len() };
+        match id {
+            0x00 => {
+                let name = section.name()?;
+                module.customs.push(Custom { name, bytes: section.take(reader.pos - section.pos)?.to_vec() });
+            },
+            0x01 => module.types = section.vec(|reader| {
+                let offset = reader.pos;
+                if reader.byte()? != 0x60 {
+                    return Err(format!("Invalid function type at offset {}", offset));
+                }
+                Ok(FuncType { params: reader.vec(|reader| reader.value_type())?, results: reader.vec(|reader| reader.value_type())? })
+            })?,
+            0x02 => module.imports = section.vec(|reader| {
+                let (module, name) = (reader.name()?, reader.name()?);
+                let offset = reader.pos;
+                match reader.byte()? {
+                    0x00 => Ok(Import { module, name, type_index: reader.u32()? }),
+                    kind => Err(format!("Unsupported import kind 0x{:02X} at offset {}", kind, offset))
+                }
+            })?,
+            0x03 => module.functions = section.vec(|reader| reader.u32())?,
+            0x05 => module.memories = section.vec(|reader| reader.limits())?,
+            0x06 => module.globals = section.vec(|reader| {
+                let kind = reader.value_type()?;
+                let offset = reader.pos;
+                let mutable = match reader.byte()? {
+                    0x00 => false,
+                    0x01 => true,
+                    flag => return Err(format!("Invalid global mutability 0x{:02X} at offset {}", flag, offset))
+                };
+                Ok(Global { kind, mutable, init: reader.expression()? })
+            })?,
+            0x07 => module.exports = section.vec(|reader| Ok(Export { name: reader.name()?, kind: reader.byte()?, index: reader.u32()? }))?,
+            0x0A => module.code = section.vec(|reader| {
+                let size = reader.u32()? as usize;
+                let offset = reader.pos;
+                let locals = reader.vec(|reader| Ok((reader.u32()?, reader.value_type()?)))?;
+                let instructions = reader.expression()?;
+                if reader.pos - offset != size {
+                    return Err(format!("Function body at offset {} has size {} but declares {}", offset, reader.pos - offset, size));
+                }
+                Ok(Body { locals, instructions, offset })
+            })?,
+            0x0B => module.data = section.vec(|reader| {
+                let offset = reader.pos;
+                match reader.u32()? {
+                    0x00 => Ok(Data { offset: reader.expression()?, bytes: { let len = reader.u32()? as usize; reader.take(len)?.to_vec() } }),
+                    kind => Err(format!("Unsupported data segment kind {} at offset {}", kind, offset))
+                }
+            })?,
+            _ => return Err(format!("Unsupported section {} at offset {}", id, section_offset))
+        }
+        if section.pos != reader.pos {
+            return Err(format!("Section {} at offset {} has size {} but its content is {} bytes", id, section_offset, size, section.pos - (reader.pos - size)));
+        }
+    }
+
+    Ok(module)
+}
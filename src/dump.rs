@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use crate::compiler::is_identifier;
+use crate::json::Json;
+use crate::lexer::{Comment, PlatToken, Span};
+use crate::parser::{Expr, FuncExpr, Pattern, PlatTypes, AST};
+
+// Machine-readable dumps of the tokens and the AST. The JSON forms are for tools and carry a
+// version that changes when their schema does, the S-expression form is for compact golden tests.
+// Types are written the way they are in source.
+
+pub const VERSION: u32 = 1;
+
+// Types the compiler resolved for expressions, by their span
+pub type Types = HashMap<Span, PlatTypes>;
+
+fn span(span: Span) -> Json {
+    Json::object([("line", span.line.into()), ("column", span.column.into())])
+}
+
+fn kind(kind: &PlatTypes) -> Json {
+    kind.to_string().into()
+}
+
+pub fn tokens_json(tokens: &[PlatToken], comments: &[Comment]) -> String {
+    let tokens = tokens.iter().map(|token| Json::object([
+        ("kind", format!("{:?}", token.kind).into()),
+        ("value", token.value.as_str().into()),
+        ("span", span(token.span))
+    ])).collect::<Vec<_>>();
+    let comments = comments.iter().map(|comment| Json::object([("text", comment.text.as_str().into()), ("span", span(comment.span))])).collect::<Vec<_>>();
+    Json::object([("version", VERSION.into()), ("tokens", tokens.into()), ("comments", comments.into())]).to_string()
+}
+
+pub fn ast_json(ast: &[AST], types: &Types) -> String {
+    let nodes = ast.iter().map(|node| Dump { types }.node(node)).collect::<Vec<_>>();
+    Json::object([("version", VERSION.into()), ("ast", nodes.into())]).to_string()
+}
+
+struct Dump<'a> {
+    types: &'a Types
+}
+
+impl Dump<'_> {
+    fn nodes(&self, nodes: &[AST]) -> Json {
+        nodes.iter().map(|node| self.node(node)).collect::<Vec<_>>().into()
+    }
+
+    fn exprs(&self, exprs: &[Expr]) -> Json {
+        exprs.iter().map(|expr| self.expr(expr)).collect::<Vec<_>>().into()
+    }
+
+    fn func(&self, func: &FuncExpr) -> Json {
+        let args = func.args.iter().map(|arg| Json::object([("name", arg.name.as_str().into()), ("type", kind(&arg.kind))])).collect::<Vec<_>>();
+        Json::object([
+            ("kind", "func".into()),
+            ("span", span(func.span)),
+            ("name", func.name.as_str().into()),
+            ("args", args.into()),
+            ("returns", kind(&func.ret_kind)),
+            ("module", func.module.as_deref().into()),
+            ("body", func.body.as_ref().as_ref().map(|body| self.nodes(body)).into())
+        ])
+    }
+
+    fn node(&self, node: &AST) -> Json {
+        match node {
+            AST::Func(func) => self.func(func),
+            AST::Struct(expr) => {
+                let fields = expr.fields.iter().map(|field| Json::object([("name", field.name.as_str().into()), ("type", kind(&field.kind))])).collect::<Vec<_>>();
+                Json::object([("kind", "struct".into()), ("span", span(expr.span)), ("name", expr.name.as_str().into()), ("fields", fields.into())])
+            },
+            AST::Enum(expr) => {
+                let variants = expr.variants.iter().map(|variant| Json::object([
+                    ("name", variant.name.as_str().into()),
+                    ("fields", variant.fields.iter().map(kind).collect::<Vec<_>>().into())
+                ])).collect::<Vec<_>>();
+                Json::object([("kind", "enum".into()), ("span", span(expr.span)), ("name", expr.name.as_str().into()), ("variants", variants.into())])
+            },
+            AST::Def(def) => Json::object([
+                ("kind", "let".into()),
+                ("span", span(def.span)),
+                ("name", def.name.as_str().into()),
+                ("type", kind(&def.kind)),
+                ("value", def.value.as_ref().map(|value| self.expr(value)).into())
+            ]),
+            AST::Assign(assign) => Json::object([("kind", "assign".into()), ("span", span(assign.span)), ("target", self.expr(&assign.target)), ("value", self.expr(&assign.value))]),
+            AST::Ret(value, at) => Json::object([("kind", "return".into()), ("span", span(*at)), ("value", value.as_ref().map(|value| self.expr(value)).into())]),
+            AST::Expr(expr, at) => Json::object([("kind", "expr".into()), ("span", span(*at)), ("expr", self.expr(expr))])
+        }
+    }
+
+    fn pattern(pattern: &Pattern) -> Json {
+        match pattern {
+            Pattern::Wildcard => Json::object([("kind", "wildcard".into())]),
+            Pattern::Literal(value) => Json::object([("kind", "literal".into()), ("value", value.as_str().into())]),
+            Pattern::Variant { enum_name, variant, bindings } => Json::object([
+                ("kind", "variant".into()),
+                ("enum", enum_name.as_str().into()),
+                ("variant", variant.as_str().into()),
+                ("bindings", bindings.iter().map(|binding| binding.as_str().into()).collect::<Vec<Json>>().into())
+            ])
+        }
+    }
+
+    // Every expression has a kind, a span, its fields and its type, which is null when the compiler
+    // didn't get to it
+    fn expr(&self, expr: &Expr) -> Json {
+        let (name, mut fields): (&str, Vec<(&str, Json)>) = match expr {
+            Expr::Literal(value, _) if is_identifier(value) => ("name", vec![("name", value.as_str().into())]),
+            Expr::Literal(value, _) => ("literal", vec![("value", value.as_str().into())]),
+            Expr::Str(value, _) => ("string", vec![("value", value.as_str().into())]),
+            Expr::Unary(expr) => ("unary", vec![("op", expr.operator.value.as_str().into()), ("expr", self.expr(&expr.expr))]),
+            Expr::Binary(expr) => ("binary", vec![
+                ("op", expr.operator.value.as_str().into()),
+                ("left", self.expr(&expr.left)),
+                ("right", self.expr(&expr.right))
+            ]),
+            Expr::Group(inner, _) => ("group", vec![("expr", self.expr(inner))]),
+            Expr::Call(expr) => ("call", vec![("name", expr.name.as_str().into()), ("args", self.exprs(&expr.args))]),
+            Expr::StructLit(expr) => {
+                let values = expr.fields.iter().map(|(name, value)| Json::object([("name", name.as_str().into()), ("value", self.expr(value))])).collect::<Vec<_>>();
+                ("struct", vec![("name", expr.name.as_str().into()), ("fields", values.into())])
+            },
+            Expr::Field(expr) => ("field", vec![("expr", self.expr(&expr.expr)), ("field", expr.field.as_str().into())]),
+            Expr::ArrayLit(expr) => ("array", vec![("elements", self.exprs(&expr.elements)), ("count", expr.count.into())]),
+            Expr::Index(expr) => ("index", vec![("expr", self.expr(&expr.expr)), ("index", self.expr(&expr.index))]),
+            Expr::Variant(expr) => ("variant", vec![
+                ("enum", expr.enum_name.as_str().into()),
+                ("variant", expr.variant.as_str().into()),
+                ("args", self.exprs(&expr.args))
+            ]),
+            Expr::Match(expr) => {
+                let arms = expr.arms.iter().map(|arm| Json::object([("span", span(arm.span)), ("pattern", Dump::pattern(&arm.pattern)), ("body", self.expr(&arm.body))])).collect::<Vec<_>>();
+                ("match", vec![("expr", self.expr(&expr.expr)), ("arms", arms.into())])
+            }
+        };
+        let mut entries = vec![("kind", name.into()), ("span", span(expr.span()))];
+        entries.append(&mut fields);
+        entries.push(("type", self.types.get(&expr.span()).map(kind).into()));
+        Json::Object(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+}
+
+// One line per node, `(+ a b)` for operators and `(call f a b)` for everything else. Groups are
+// left out, the nesting already shows them.
+pub fn ast_sexpr(ast: &[AST]) -> String {
+    ast.iter().map(|node| format!("{}\n", node_sexpr(node))).collect()
+}
+
+fn list(head: &str, items: impl IntoIterator<Item = String>) -> String {
+    let mut text = format!("({}", head);
+    for item in items {
+        text.push(' ');
+        text.push_str(&item);
+    }
+    text.push(')');
+    text
+}
+
+fn type_sexpr(kind: &PlatTypes) -> String {
+    match kind {
+        PlatTypes::Array(kind, count) => format!("(array {} {})", type_sexpr(kind), count),
+        kind => kind.to_string()
+    }
+}
+
+pub fn node_sexpr(node: &AST) -> String {
+    match node {
+        AST::Func(func) => {
+            let args: Vec<String> = func.args.iter().map(|arg| format!("({} {})", arg.name, type_sexpr(&arg.kind))).collect();
+            let mut head = match &func.module {
+                Some(module) => format!("extern {:?} {}", module, func.name),
+                None => format!("func {}", func.name)
+            };
+            head = format!("{} ({}) {}", head, args.join(" "), type_sexpr(&func.ret_kind));
+            list(&head, func.body.as_ref().iter().flatten().map(node_sexpr))
+        },
+        AST::Struct(expr) => list(&format!("struct {}", expr.name), expr.fields.iter().map(|field| format!("({} {})", field.name, type_sexpr(&field.kind)))),
+        AST::Enum(expr) => list(&format!("enum {}", expr.name), expr.variants.iter().map(|variant| match variant.fields.is_empty() {
+            true => variant.name.clone(),
+            false => list(&variant.name, variant.fields.iter().map(type_sexpr))
+        })),
+        AST::Def(def) => list(&format!("let {} {}", def.name, type_sexpr(&def.kind)), def.value.iter().map(expr_sexpr)),
+        AST::Assign(assign) => list("=", [expr_sexpr(&assign.target), expr_sexpr(&assign.value)]),
+        AST::Ret(value, _) => list("return", value.iter().map(expr_sexpr)),
+        AST::Expr(expr, _) => expr_sexpr(expr)
+    }
+}
+
+fn pattern_sexpr(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Variant { enum_name, variant, bindings } if !bindings.is_empty() => list(&format!("{}::{}", enum_name, variant), bindings.iter().cloned()),
+        pattern => pattern.to_string()
+    }
+}
+
+pub fn expr_sexpr(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal(value, _) if is_identifier(value) || value.parse::<f64>().is_ok() => value.clone(),
+        Expr::Literal(value, _) => format!("'{}'", value),
+        Expr::Str(value, _) => format!("{:?}", value),
+        Expr::Unary(expr) => list(&expr.operator.value, [expr_sexpr(&expr.expr)]),
+        Expr::Binary(expr) => list(&expr.operator.value, [expr_sexpr(&expr.left), expr_sexpr(&expr.right)]),
+        Expr::Group(expr, _) => expr_sexpr(expr),
+        Expr::Call(expr) => list(&format!("call {}", expr.name), expr.args.iter().map(expr_sexpr)),
+        Expr::StructLit(expr) => list(&format!("new {}", expr.name), expr.fields.iter().map(|(name, value)| format!("({} {})", name, expr_sexpr(value)))),
+        Expr::Field(expr) => format!("(. {} {})", expr_sexpr(&expr.expr), expr.field),
+        Expr::ArrayLit(expr) => match expr.count {
+            Some(count) => format!("(repeat {} {})", expr_sexpr(&expr.elements[0]), count),
+            None => list("array", expr.elements.iter().map(expr_sexpr))
+        },
+        Expr::Index(expr) => format!("(index {} {})", expr_sexpr(&expr.expr), expr_sexpr(&expr.index)),
+        Expr::Variant(expr) if expr.args.is_empty() => format!("{}::{}", expr.enum_name, expr.variant),
+        Expr::Variant(expr) => list(&format!("{}::{}", expr.enum_name, expr.variant), expr.args.iter().map(expr_sexpr)),
+        Expr::Match(expr) => list(&format!("match {}", expr_sexpr(&expr.expr)), expr.arms.iter().map(|arm| format!("({} {})", pattern_sexpr(&arm.pattern), expr_sexpr(&arm.body))))
+    }
+}
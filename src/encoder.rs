@@ -0,0 +1,370 @@
+use crate::ir::{self, Instr, ValType};
+use crate::leb128;
+use crate::lexer::Span;
+
+// Serializes an IR module into the WASM binary format
+
+pub fn val_type_byte(kind: ValType) -> u8 {
+    match kind {
+        ValType::I32 => 0x7F,
+        ValType::I64 => 0x7E,
+        ValType::F32 => 0x7D,
+        ValType::F64 => 0x7C,
+    }
+}
+
+fn block_type_byte(kind: ir::BlockType) -> u8 {
+    match kind {
+        ir::BlockType::Empty => 0x40,
+        ir::BlockType::Value(kind) => val_type_byte(kind)
+    }
+}
+
+fn type_offset(kind: ValType, i32: u32, i64: u32, f32: u32, f64: u32) -> u32 {
+    match kind {
+        ValType::I32 => i32,
+        ValType::I64 => i64,
+        ValType::F32 => f32,
+        ValType::F64 => f64,
+    }
+}
+
+// Opcode of an instruction, prefixed opcodes are returned as (prefix << 8) | sub-opcode
+pub fn opcode(instr: &Instr) -> u32 {
+    match instr {
+        Instr::Unreachable => 0x00,
+        Instr::Nop => 0x01,
+        Instr::Block(..) => 0x02,
+        Instr::Loop(..) => 0x03,
+        Instr::If(..) => 0x04,
+        Instr::Br(_) => 0x0C,
+        Instr::BrIf(_) => 0x0D,
+        Instr::BrTable(..) => 0x0E,
+        Instr::Return => 0x0F,
+        Instr::Call(_) => 0x10,
+        Instr::Drop => 0x1A,
+        Instr::Select => 0x1B,
+        Instr::LocalGet(_) => 0x20,
+        Instr::LocalSet(_) => 0x21,
+        Instr::LocalTee(_) => 0x22,
+        Instr::GlobalGet(_) => 0x23,
+        Instr::GlobalSet(_) => 0x24,
+        Instr::Load(kind, _) => match kind {
+            ir::LoadKind::I32 => 0x28, ir::LoadKind::I64 => 0x29, ir::LoadKind::F32 => 0x2A, ir::LoadKind::F64 => 0x2B,
+            ir::LoadKind::I32S8 => 0x2C, ir::LoadKind::I32U8 => 0x2D, ir::LoadKind::I32S16 => 0x2E, ir::LoadKind::I32U16 => 0x2F,
+            ir::LoadKind::I64S8 => 0x30, ir::LoadKind::I64U8 => 0x31, ir::LoadKind::I64S16 => 0x32, ir::LoadKind::I64U16 => 0x33,
+            ir::LoadKind::I64S32 => 0x34, ir::LoadKind::I64U32 => 0x35
+        },
+        Instr::Store(kind, _) => match kind {
+            ir::StoreKind::I32 => 0x36, ir::StoreKind::I64 => 0x37, ir::StoreKind::F32 => 0x38, ir::StoreKind::F64 => 0x39,
+            ir::StoreKind::I32U8 => 0x3A, ir::StoreKind::I32U16 => 0x3B,
+            ir::StoreKind::I64U8 => 0x3C, ir::StoreKind::I64U16 => 0x3D, ir::StoreKind::I64U32 => 0x3E
+        },
+        Instr::MemorySize => 0x3F,
+        Instr::MemoryGrow => 0x40,
+        Instr::I32Const(_) => 0x41,
+        Instr::I64Const(_) => 0x42,
+        Instr::F32Const(_) => 0x43,
+        Instr::F64Const(_) => 0x44,
+        Instr::Eqz(kind) => match kind {
+            ValType::I32 => 0x45,
+            ValType::I64 => 0x50,
+            _ => panic!("eqz is only defined for integers, got {:?}", kind)
+        },
+        Instr::Compare(kind, op) => {
+            let int = matches!(kind, ValType::I32 | ValType::I64);
+            let index = match (op, int) {
+                (ir::RelOp::Eq, _) => 0, (ir::RelOp::Ne, _) => 1,
+                (ir::RelOp::LtS, true) => 2, (ir::RelOp::LtU, true) => 3, (ir::RelOp::GtS, true) => 4, (ir::RelOp::GtU, true) => 5,
+                (ir::RelOp::LeS, true) => 6, (ir::RelOp::LeU, true) => 7, (ir::RelOp::GeS, true) => 8, (ir::RelOp::GeU, true) => 9,
+                (ir::RelOp::Lt, false) => 2, (ir::RelOp::Gt, false) => 3, (ir::RelOp::Le, false) => 4, (ir::RelOp::Ge, false) => 5,
+                _ => panic!("Invalid comparison {:?} for {:?}", op, kind)
+            };
+            type_offset(*kind, 0x46, 0x51, 0x5B, 0x61) + index
+        },
+        Instr::Unary(kind, op) => {
+            let int = matches!(kind, ValType::I32 | ValType::I64);
+            let index = match (op, int) {
+                (ir::UnOp::Clz, true) => 0, (ir::UnOp::Ctz, true) => 1, (ir::UnOp::Popcnt, true) => 2,
+                (ir::UnOp::Abs, false) => 0, (ir::UnOp::Neg, false) => 1, (ir::UnOp::Ceil, false) => 2, (ir::UnOp::Floor, false) => 3,
+                (ir::UnOp::Trunc, false) => 4, (ir::UnOp::Nearest, false) => 5, (ir::UnOp::Sqrt, false) => 6,
+                _ => panic!("Invalid unary operator {:?} for {:?}", op, kind)
+            };
+            type_offset(*kind, 0x67, 0x79, 0x8B, 0x99) + index
+        },
+        Instr::Binary(kind, op) => {
+            let int = matches!(kind, ValType::I32 | ValType::I64);
+            let index = match (op, int) {
+                (ir::BinOp::Add, _) => 0, (ir::BinOp::Sub, _) => 1, (ir::BinOp::Mul, _) => 2,
+                (ir::BinOp::DivS, true) => 3, (ir::BinOp::DivU, true) => 4, (ir::BinOp::RemS, true) => 5, (ir::BinOp::RemU, true) => 6,
+                (ir::BinOp::And, true) => 7, (ir::BinOp::Or, true) => 8, (ir::BinOp::Xor, true) => 9,
+                (ir::BinOp::Shl, true) => 10, (ir::BinOp::ShrS, true) => 11, (ir::BinOp::ShrU, true) => 12,
+                (ir::BinOp::Rotl, true) => 13, (ir::BinOp::Rotr, true) => 14,
+                (ir::BinOp::Div, false) => 3, (ir::BinOp::Min, false) => 4, (ir::BinOp::Max, false) => 5, (ir::BinOp::Copysign, false) => 6,
+                _ => panic!("Invalid binary operator {:?} for {:?}", op, kind)
+            };
+            type_offset(*kind, 0x6A, 0x7C, 0x92, 0xA0) + index
+        },
+        // ConvOp variants are declared in opcode order: 0xA7..=0xBF followed by 0xFC 0x00..=0x07
+        Instr::Convert(op) => {
+            let index = *op as u32;
+            if index <= 0xBF - 0xA7 {0xA7 + index} else {0xFC00 + index - (0xBF - 0xA7 + 1)}
+        },
+        Instr::Location(_) => panic!("Source locations don't have an opcode")
+    }
+}
+
+fn encode_opcode(opcode: u32, out: &mut Vec<u8>) {
+    if opcode > 0xFF {
+        out.push((opcode >> 8) as u8);
+        out.append(&mut leb128::encode_u32(opcode & 0xFF));
+    } else {
+        out.push(opcode as u8);
+    }
+}
+
+// Code offsets of source locations are collected into `locations`, relative to the start of `out`
+fn encode_instr(instr: &Instr, out: &mut Vec<u8>, locations: &mut Vec<(usize, Span)>) {
+    if let Instr::Location(span) = instr {
+        locations.push((out.len(), *span));
+        return;
+    }
+    encode_opcode(opcode(instr), out);
+    match instr {
+        Instr::Block(kind, body) | Instr::Loop(kind, body) => {
+            out.push(block_type_byte(*kind));
+            encode_body(body, out, locations);
+            out.push(0x0B);
+        },
+        Instr::If(kind, then, otherwise) => {
+            out.push(block_type_byte(*kind));
+            encode_body(then, out, locations);
+            if !otherwise.is_empty() {
+                out.push(0x05);
+                encode_body(otherwise, out, locations);
+            }
+            out.push(0x0B);
+        },
+        Instr::Br(index) | Instr::BrIf(index) | Instr::Call(index) |
+        Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) |
+        Instr::GlobalGet(index) | Instr::GlobalSet(index) => out.append(&mut leb128::encode_u32(*index)),
+        Instr::BrTable(labels, default) => {
+            out.append(&mut leb128::encode_u32(labels.len().try_into().unwrap()));
+            for label in labels.iter() {
+                out.append(&mut leb128::encode_u32(*label));
+            }
+            out.append(&mut leb128::encode_u32(*default));
+        },
+        Instr::Load(_, memarg) | Instr::Store(_, memarg) => {
+            out.append(&mut leb128::encode_u32(memarg.align));
+            out.append(&mut leb128::encode_u32(memarg.offset));
+        },
+        Instr::MemorySize | Instr::MemoryGrow => out.push(0x00),
+        Instr::I32Const(value) => out.append(&mut leb128::encode_i32(*value)),
+        Instr::I64Const(value) => out.append(&mut leb128::encode_i64(*value)),
+        Instr::F32Const(value) => out.extend_from_slice(&value.to_le_bytes()),
+        Instr::F64Const(value) => out.extend_from_slice(&value.to_le_bytes()),
+        _ => {}
+    }
+}
+
+fn encode_body(body: &[Instr], out: &mut Vec<u8>, locations: &mut Vec<(usize, Span)>) {
+    for instr in body.iter() {
+        encode_instr(instr, out, locations);
+    }
+}
+
+pub fn encode_name(name: &str, out: &mut Vec<u8>) {
+    out.append(&mut leb128::encode_u32(name.len().try_into().unwrap()));
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn encode_limits(limits: &ir::Limits, out: &mut Vec<u8>) {
+    match limits.max {
+        Some(max) => {
+            out.push(0x01);                                         // Limits: min + max
+            out.append(&mut leb128::encode_u32(limits.min));
+            out.append(&mut leb128::encode_u32(max));
+        },
+        None => {
+            out.push(0x00);                                         // Limits: min only
+            out.append(&mut leb128::encode_u32(limits.min));
+        }
+    }
+}
+
+// Function body with its local declarations, prefixed by its size
+fn encode_function(func: &ir::Function, locations: &mut Vec<(usize, Span)>) -> Vec<u8> {
+    // Consecutive locals of the same type share one (count, type) entry
+    let mut groups: Vec<(u32, ValType)> = vec![];
+    for local in func.locals.iter() {
+        match groups.last_mut() {
+            Some((count, kind)) if kind == local => *count += 1,
+            _ => groups.push((1, *local))
+        }
+    }
+    let mut code = leb128::encode_u32(groups.len().try_into().unwrap()); // Number of local decls
+    for (count, kind) in groups.iter() {
+        code.append(&mut leb128::encode_u32(*count));
+        code.push(val_type_byte(*kind));
+    }
+    let mut body_locations = vec![];
+    encode_body(&func.body, &mut code, &mut body_locations);
+    code.push(0x0B);                                                // End of function
+
+    let size = leb128::encode_u32(code.len().try_into().unwrap());
+    locations.extend(body_locations.into_iter().map(|(offset, span)| (size.len() + offset, span)));
+    [size, code].concat()
+}
+
+fn name_map(names: &[(u32, &str)], out: &mut Vec<u8>) {
+    out.append(&mut leb128::encode_u32(names.len().try_into().unwrap()));
+    for (index, name) in names.iter() {
+        out.append(&mut leb128::encode_u32(*index));
+        encode_name(name, out);
+    }
+}
+
+fn name_subsection(id: u8, content: Vec<u8>, out: &mut Vec<u8>) {
+    out.push(id);
+    out.append(&mut leb128::encode_u32(content.len().try_into().unwrap()));
+    out.extend_from_slice(&content);
+}
+
+// Content of the standard `name` custom section: module, function, local and global names
+pub fn name_section(module: &ir::Module) -> Vec<u8> {
+    let mut out = vec![];
+    if let Some(name) = &module.name {
+        let mut content = vec![];
+        encode_name(name, &mut content);
+        name_subsection(0x00, content, &mut out);
+    }
+
+    let n_imports: u32 = module.imports.len().try_into().unwrap();
+    let functions: Vec<(u32, &str)> = module.imports.iter().map(|import| import.name.as_str())
+        .chain(module.functions.iter().map(|func| func.name.as_str()))
+        .enumerate().map(|(index, name)| (index as u32, name)).collect();
+    let mut content = vec![];
+    name_map(&functions, &mut content);
+    name_subsection(0x01, content, &mut out);
+
+    let locals: Vec<(u32, &ir::Function)> = module.functions.iter().enumerate()
+        .filter(|(_, func)| !func.local_names.is_empty())
+        .map(|(index, func)| (n_imports + index as u32, func)).collect();
+    if !locals.is_empty() {
+        let mut content = leb128::encode_u32(locals.len().try_into().unwrap());
+        for (index, func) in locals {
+            let mut names: Vec<(u32, &str)> = func.local_names.iter().map(|(local, name)| (*local, name.as_str())).collect();
+            names.sort_by_key(|(local, _)| *local);
+            content.append(&mut leb128::encode_u32(index));
+            name_map(&names, &mut content);
+        }
+        name_subsection(0x02, content, &mut out);
+    }
+
+    if !module.globals.is_empty() {
+        let globals: Vec<(u32, &str)> = module.globals.iter().enumerate().map(|(index, global)| (index as u32, global.name.as_str())).collect();
+        let mut content = vec![];
+        name_map(&globals, &mut content);
+        name_subsection(0x07, content, &mut out);
+    }
+    out
+}
+
+fn section(id: u8, count: usize, body: &[u8]) -> Vec<u8> {
+    let content = [&leb128::encode_u32(count.try_into().unwrap())[..], body].concat();
+    [&[id][..], &leb128::encode_u32(content.len().try_into().unwrap())[..], &content[..]].concat()
+}
+
+// Also returns the source locations of the module, as offsets from the start of the module
+pub fn encode_with_locations(module: &ir::Module) -> (Vec<u8>, Vec<(u32, Span)>) {
+    let mut sec_type = vec![];
+    for kind in module.types.iter() {
+        sec_type.push(0x60);                                        // Function
+        for types in [&kind.params, &kind.results] {
+            sec_type.append(&mut leb128::encode_u32(types.len().try_into().unwrap()));
+            sec_type.extend(types.iter().map(|kind| val_type_byte(*kind)));
+        }
+    }
+
+    let mut sec_impo = vec![];
+    for import in module.imports.iter() {
+        encode_name(&import.module, &mut sec_impo);
+        encode_name(&import.name, &mut sec_impo);
+        sec_impo.push(0x00);                                        // Function import
+        sec_impo.append(&mut leb128::encode_u32(import.type_index));
+    }
+
+    let mut sec_func = vec![];
+    for func in module.functions.iter() {
+        sec_func.append(&mut leb128::encode_u32(func.type_index));
+    }
+
+    let mut sec_mem = vec![];
+    for memory in module.memories.iter() {
+        encode_limits(memory, &mut sec_mem);
+    }
+
+    let mut sec_glob = vec![];
+    for global in module.globals.iter() {
+        sec_glob.push(val_type_byte(global.kind));
+        sec_glob.push(global.mutable as u8);
+        encode_instr(&global.init, &mut sec_glob, &mut vec![]);
+        sec_glob.push(0x0B);                                        // End of init expression
+    }
+
+    let mut sec_expo = vec![];
+    for export in module.exports.iter() {
+        encode_name(&export.name, &mut sec_expo);
+        sec_expo.push(match export.kind {
+            ir::ExportKind::Func => 0x00,
+            ir::ExportKind::Memory => 0x02,
+            ir::ExportKind::Global => 0x03
+        });
+        sec_expo.append(&mut leb128::encode_u32(export.index));
+    }
+
+    let mut sec_code = vec![];
+    let mut locations = vec![];
+    for func in module.functions.iter() {
+        let mut func_locations = vec![];
+        let mut code = encode_function(func, &mut func_locations);
+        locations.extend(func_locations.into_iter().map(|(offset, span)| (sec_code.len() + offset, span)));
+        sec_code.append(&mut code);
+    }
+
+    let mut sec_data = vec![];
+    for data in module.data.iter() {
+        sec_data.append(&mut vec![0x00, 0x41]);                    // Active segment for memory 0, i32.const
+        sec_data.append(&mut leb128::encode_i32(data.offset as i32));
+        sec_data.push(0x0B);                                        // End of offset expression
+        sec_data.append(&mut leb128::encode_u32(data.bytes.len().try_into().unwrap()));
+        sec_data.extend_from_slice(&data.bytes);
+    }
+
+    let before_code = [
+        &[0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00][..],     // WASM Magic + Version
+        &section(0x01, module.types.len(), &sec_type)[..],
+        &if module.imports.is_empty() {vec![]} else {section(0x02, module.imports.len(), &sec_impo)}[..],
+        &section(0x03, module.functions.len(), &sec_func)[..],
+        &if module.memories.is_empty() {vec![]} else {section(0x05, module.memories.len(), &sec_mem)}[..],
+        &if module.globals.is_empty() {vec![]} else {section(0x06, module.globals.len(), &sec_glob)}[..],
+        &section(0x07, module.exports.len(), &sec_expo)[..]
+    ].concat();
+    let code = section(0x0A, module.functions.len(), &sec_code);
+    let code_start = before_code.len() + code.len() - sec_code.len();
+    let locations = locations.into_iter().map(|(offset, span)| ((code_start + offset).try_into().unwrap(), span)).collect();
+
+    let bytes = [
+        &before_code[..],
+        &code[..],
+        &if module.data.is_empty() {vec![]} else {section(0x0B, module.data.len(), &sec_data)}[..],
+        &module.customs.iter().flat_map(|custom| {
+            let mut content = vec![];
+            encode_name(&custom.name, &mut content);
+            content.extend_from_slice(&custom.bytes);
+            [&[0x00][..], &leb128::encode_u32(content.len().try_into().unwrap())[..], &content[..]].concat()
+        }).collect::<Vec<u8>>()[..]
+    ].concat();
+    (bytes, locations)
+}
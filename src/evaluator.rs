@@ -0,0 +1,922 @@
+use std::collections::HashMap;
+
+use crate::interpreter::{HostFn, Trap, Value};
+use crate::intrinsics::{self, Intrinsic};
+use crate::ir::{LoadKind, StoreKind};
+use crate::lexer::PlatTokenKinds;
+use crate::parser::{self, Expr, PlatTypes, AST};
+use crate::runtime::Allocator;
+use crate::{Options, Target};
+
+// Reference interpreter that evaluates the AST directly instead of compiling it, so its results can
+// be compared against the compiled module. It follows the semantics of the compiled code: integers
+// wrap, division by zero traps, and structs, enums, arrays and strings are i32 addresses into a linear
+// memory that uses the compiler's layouts and allocators.
+//
+// String literals are placed from address 1024 in source order and the heap starts after them. The
+// compiler also puts the data of the print runtime there, so heap addresses of programs that print
+// can differ from the compiled module.
+//
+// Layouts, types and the memory constants are written out again here instead of coming from the
+// compiler, so that a mistake in either shows up as a difference between the two.
+
+const MAX_DEPTH: usize = 1000;                                      // Nested calls before the evaluator traps
+const DATA_BASE: u32 = 1024;
+const PAGE_SIZE: usize = 65536;
+const MAX_PAGES: u32 = 4096;                                        // The built-in interpreter's limit
+const HEAP_ALIGN: u32 = 8;
+const HEADER_SIZE: u32 = 8;                                         // Size and next pointer of free list blocks
+const IN_USE: u32 = u32::MAX;                                       // Next pointer of allocated free list blocks
+
+struct Struct {
+    fields: Vec<(String, PlatTypes, u32)>,
+    size: u32,
+    align: u32
+}
+
+// Variants hold the types and offsets of their payload, the tag is the index of the variant
+struct Enum {
+    variants: Vec<(String, Vec<(PlatTypes, u32)>)>,
+    size: u32,
+    align: u32
+}
+
+struct Var {
+    name: String,
+    kind: PlatTypes,
+    value: Value
+}
+
+// Arguments and variables of a call, and the match bindings in scope, innermost last
+struct Frame {
+    vars: Vec<Var>,
+    bindings: Vec<Var>
+}
+
+impl Frame {
+    fn find(&self, name: &str) -> &Var {
+        self.bindings.iter().rev().chain(self.vars.iter()).find(|var| var.name == name).unwrap_or_else(|| panic!("The variable {} does not exist...", name))
+    }
+}
+
+fn zero(kind: &PlatTypes) -> Value {
+    match kind {
+        PlatTypes::I64 => Value::I64(0),
+        PlatTypes::F32 => Value::F32(0.0),
+        PlatTypes::F64 => Value::F64(0.0),
+        PlatTypes::Void => panic!("Void has no value"),
+        _ => Value::I32(0)
+    }
+}
+
+// Bytes a value takes in memory, which is also its alignment. Structs, enums, arrays and strings
+// are stored as addresses.
+fn size(kind: &PlatTypes) -> u32 {
+    match kind {
+        PlatTypes::I64 | PlatTypes::F64 => 8,
+        _ => 4
+    }
+}
+
+fn load_kind(kind: &PlatTypes) -> LoadKind {
+    match kind {
+        PlatTypes::I64 => LoadKind::I64,
+        PlatTypes::F32 => LoadKind::F32,
+        PlatTypes::F64 => LoadKind::F64,
+        _ => LoadKind::I32
+    }
+}
+
+fn store_kind(kind: &PlatTypes) -> StoreKind {
+    match kind {
+        PlatTypes::I64 => StoreKind::I64,
+        PlatTypes::F32 => StoreKind::F32,
+        PlatTypes::F64 => StoreKind::F64,
+        _ => StoreKind::I32
+    }
+}
+
+// Offsets of values stored one after another from `start`, each at its alignment, and the size and
+// alignment of them all. The size is a multiple of the alignment.
+fn layout<'a>(kinds: impl Iterator<Item = &'a PlatTypes>, start: u32) -> (Vec<u32>, u32, u32) {
+    let (mut offsets, mut end, mut align) = (vec![], start, start.max(1));
+    for kind in kinds {
+        let offset = end.next_multiple_of(size(kind));
+        offsets.push(offset);
+        end = offset + size(kind);
+        align = align.max(size(kind));
+    }
+    (offsets, end.next_multiple_of(align), align)
+}
+
+fn is_name(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_alphabetic() || c == '_')
+}
+
+fn is_comparison(operator: &PlatTokenKinds) -> bool {
+    matches!(operator, PlatTokenKinds::Equals | PlatTokenKinds::BangEquals | PlatTokenKinds::Smaller |
+        PlatTokenKinds::SmallerEquals | PlatTokenKinds::Greater | PlatTokenKinds::GreaterEquals)
+}
+
+// Untyped literals are f64 with a decimal point and i32 without
+fn literal_type(value: &str) -> PlatTypes {
+    if value.contains('.') {PlatTypes::F64} else {PlatTypes::I32}
+}
+
+// Type of an expression that has none to infer, made of untyped literals: the type of its first one
+fn default_type(expr: &Expr) -> PlatTypes {
+    match expr {
+        Expr::Literal(value, _) => literal_type(value),
+        Expr::Unary(expr) => default_type(&expr.expr),
+        Expr::Binary(expr) => default_type(&expr.left),
+        Expr::Group(expr, _) => default_type(expr),
+        _ => PlatTypes::I32
+    }
+}
+
+fn trap<T>(message: &str) -> Result<T, Trap> {
+    Err(Trap::Error(message.to_string()))
+}
+
+fn address(value: Value) -> i32 {
+    match value {
+        Value::I32(address) => address,
+        value => panic!("Expected an address, got {:?}", value)
+    }
+}
+
+// Memory offsets are constants, the checker has made sure of that
+fn offset(expr: &Expr) -> u32 {
+    match expr {
+        Expr::Literal(value, _) => value.parse().unwrap_or_else(|_| panic!("Invalid memory offset {}", value)),
+        _ => panic!("Memory offsets have to be constant! Got {}", expr)
+    }
+}
+
+fn compare<T: PartialOrd>(operator: &PlatTokenKinds, a: T, b: T) -> Value {
+    Value::I32(match operator {
+        PlatTokenKinds::Equals => a == b,
+        PlatTokenKinds::BangEquals => a != b,
+        PlatTokenKinds::Smaller => a < b,
+        PlatTokenKinds::Greater => a > b,
+        PlatTokenKinds::SmallerEquals => a <= b,
+        PlatTokenKinds::GreaterEquals => a >= b,
+        _ => panic!("Invalid binary operator {:?}", operator)
+    } as i32)
+}
+
+// Signed division traps on a zero divisor and on MIN / -1
+fn divide<T: Default + PartialEq>(b: T, quotient: Option<T>) -> Result<T, Trap> {
+    if b == T::default() {
+        return trap("Integer divide by zero");
+    }
+    quotient.map_or_else(|| trap("Integer overflow"), Ok)
+}
+
+fn arithmetic(operator: &PlatTokenKinds, a: Value, b: Value) -> Result<Value, Trap> {
+    Ok(match (a, b, operator) {
+        (Value::I32(a), Value::I32(b), PlatTokenKinds::Plus) => Value::I32(a.wrapping_add(b)),
+        (Value::I32(a), Value::I32(b), PlatTokenKinds::Minus) => Value::I32(a.wrapping_sub(b)),
+        (Value::I32(a), Value::I32(b), PlatTokenKinds::Asterisk) => Value::I32(a.wrapping_mul(b)),
+        (Value::I32(a), Value::I32(b), PlatTokenKinds::Slash) => Value::I32(divide(b, a.checked_div(b))?),
+        (Value::I32(a), Value::I32(b), _) => compare(operator, a, b),
+        (Value::I64(a), Value::I64(b), PlatTokenKinds::Plus) => Value::I64(a.wrapping_add(b)),
+        (Value::I64(a), Value::I64(b), PlatTokenKinds::Minus) => Value::I64(a.wrapping_sub(b)),
+        (Value::I64(a), Value::I64(b), PlatTokenKinds::Asterisk) => Value::I64(a.wrapping_mul(b)),
+        (Value::I64(a), Value::I64(b), PlatTokenKinds::Slash) => Value::I64(divide(b, a.checked_div(b))?),
+        (Value::I64(a), Value::I64(b), _) => compare(operator, a, b),
+        (Value::F32(a), Value::F32(b), PlatTokenKinds::Plus) => Value::F32(a + b),
+        (Value::F32(a), Value::F32(b), PlatTokenKinds::Minus) => Value::F32(a - b),
+        (Value::F32(a), Value::F32(b), PlatTokenKinds::Asterisk) => Value::F32(a * b),
+        (Value::F32(a), Value::F32(b), PlatTokenKinds::Slash) => Value::F32(a / b),
+        (Value::F32(a), Value::F32(b), _) => compare(operator, a, b),
+        (Value::F64(a), Value::F64(b), PlatTokenKinds::Plus) => Value::F64(a + b),
+        (Value::F64(a), Value::F64(b), PlatTokenKinds::Minus) => Value::F64(a - b),
+        (Value::F64(a), Value::F64(b), PlatTokenKinds::Asterisk) => Value::F64(a * b),
+        (Value::F64(a), Value::F64(b), PlatTokenKinds::Slash) => Value::F64(a / b),
+        (Value::F64(a), Value::F64(b), _) => compare(operator, a, b),
+        (a, b, _) => panic!("Invalid operands for {:?}: {:?} and {:?}", operator, a, b)
+    })
+}
+
+// Same output as the __print_f64 runtime function: up to six fractional digits, nan or inf
+fn format_f64(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    let (sign, value) = if value < 0.0 {("-", -value)} else {("", value)};
+    if value == f64::INFINITY {
+        return format!("{}inf", sign);
+    }
+    let (mut int, mut frac) = (value.floor() as u64, ((value - value.floor()) * 1e6).round_ties_even() as u64);
+    if frac >= 1_000_000 {
+        int = int.wrapping_add(1);
+        frac -= 1_000_000;
+    }
+    let digits = format!("{:06}", frac);
+    let digits = digits.trim_end_matches('0');
+    format!("{}{}.{}", sign, int, if digits.is_empty() {"0"} else {digits})
+}
+
+// String literals in the order the compiler interns them
+fn strings<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Str(value, _) => out.push(value),
+        Expr::Literal(_, _) => {},
+        Expr::Unary(expr) => strings(&expr.expr, out),
+        Expr::Binary(expr) => {
+            strings(&expr.left, out);
+            strings(&expr.right, out);
+        },
+        Expr::Group(expr, _) => strings(expr, out),
+        Expr::Call(expr) => expr.args.iter().for_each(|arg| strings(arg, out)),
+        Expr::StructLit(expr) => expr.fields.iter().for_each(|(_, value)| strings(value, out)),
+        Expr::Field(expr) => strings(&expr.expr, out),
+        Expr::ArrayLit(expr) => expr.elements.iter().for_each(|element| strings(element, out)),
+        Expr::Index(expr) => {
+            strings(&expr.expr, out);
+            strings(&expr.index, out);
+        },
+        Expr::Variant(expr) => expr.args.iter().for_each(|arg| strings(arg, out)),
+        Expr::Match(expr) => {
+            strings(&expr.expr, out);
+            expr.arms.iter().for_each(|arm| strings(&arm.body, out));
+        }
+    }
+}
+
+pub struct Evaluator<'a> {
+    functions: HashMap<&'a str, &'a parser::FuncExpr>,
+    structs: HashMap<&'a str, Struct>,
+    enums: HashMap<&'a str, Enum>,
+    imports: HashMap<(String, String), HostFn>,
+    strings: HashMap<&'a str, u32>,
+    memory: Vec<u8>,
+    heap_top: u32,
+    free_list: u32,
+    options: Options,
+    output: Vec<u8>,
+    depth: usize
+}
+
+impl<'a> Evaluator<'a> {
+    // Extern functions are looked up in `imports` by module and name when they are called. Fails
+    // when the initial memory is above the limit.
+    pub fn new(ast: &'a [AST], options: &Options, imports: HashMap<(String, String), HostFn>) -> Result<Self, String> {
+        let mut evaluator = Evaluator { functions: HashMap::new(), structs: HashMap::new(), enums: HashMap::new(), imports, strings: HashMap::new(), memory: vec![],
+            heap_top: 0, free_list: 0, options: options.clone(), output: vec![], depth: 0 };
+
+        let mut literals = vec![];
+        for node in ast.iter() {
+            match node {
+                AST::Func(func) => {
+                    assert!(evaluator.functions.insert(&func.name, func).is_none(), "The function {} is defined more than once", func.name);
+                    for node in func.body.iter().flatten() {
+                        match node {
+                            AST::Def(parser::DefExpr { value: Some(expr), .. }) | AST::Ret(Some(expr), _) | AST::Expr(expr, _) => strings(expr, &mut literals),
+                            AST::Assign(expr) => {
+                                strings(&expr.target, &mut literals);
+                                strings(&expr.value, &mut literals);
+                            },
+                            _ => {}
+                        }
+                    }
+                },
+                AST::Struct(expr) => {
+                    let (offsets, size, align) = layout(expr.fields.iter().map(|field| &field.kind), 0);
+                    let fields = expr.fields.iter().zip(offsets).map(|(field, offset)| (field.name.clone(), field.kind.clone(), offset)).collect();
+                    evaluator.structs.insert(&expr.name, Struct { fields, size, align });
+                },
+                AST::Enum(expr) => {
+                    let (mut variants, mut size, mut align) = (vec![], 0, 4);
+                    for variant in expr.variants.iter() {
+                        let (offsets, variant_size, variant_align) = layout(variant.fields.iter(), 4);
+                        variants.push((variant.name.clone(), variant.fields.iter().cloned().zip(offsets).collect()));
+                        size = size.max(variant_size);
+                        align = align.max(variant_align);
+                    }
+                    evaluator.enums.insert(&expr.name, Enum { variants, size, align });
+                },
+                _ => {}
+            }
+        }
+
+        let mut data = vec![];
+        for literal in literals {
+            if evaluator.strings.contains_key(literal) {
+                continue;
+            }
+            data.resize(data.len().next_multiple_of(4), 0);
+            evaluator.strings.insert(literal, DATA_BASE + data.len() as u32);
+            data.extend_from_slice(&(literal.len() as u32).to_le_bytes());
+            data.extend_from_slice(literal.as_bytes());
+        }
+        let data_end = DATA_BASE + data.len() as u32;
+        let pages_needed = (data_end as usize).div_ceil(PAGE_SIZE);
+        assert!(options.memory.initial as usize >= pages_needed, "The data segment needs {} pages of memory but only {} are configured", pages_needed, options.memory.initial);
+        if options.memory.initial > MAX_PAGES {
+            return Err(format!("The program needs {} pages of memory, the limit is {}", options.memory.initial, MAX_PAGES));
+        }
+        evaluator.memory = vec![0; options.memory.initial as usize * PAGE_SIZE];
+        evaluator.memory[DATA_BASE as usize..data_end as usize].copy_from_slice(&data);
+        evaluator.heap_top = data_end.next_multiple_of(HEAP_ALIGN);
+        Ok(evaluator)
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Everything print and println wrote so far
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>, Trap> {
+        let func = *self.functions.get(name).ok_or_else(|| Trap::Error(format!("There is no function {}", name)))?;
+        if args.len() != func.args.len() || func.args.iter().zip(args).any(|(arg, value)| zero(&arg.kind).kind() != value.kind()) {
+            return Err(Trap::Error(format!("{} expects {} arguments of types ({})", name, func.args.len(), func.args.iter().map(|arg| arg.kind.to_string()).collect::<Vec<_>>().join(", "))));
+        }
+        self.depth = 0;
+        self.invoke(func, args.to_vec())
+    }
+
+    fn invoke(&mut self, func: &'a parser::FuncExpr, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        if let Some(module) = &func.module {
+            let Some(host) = self.imports.get_mut(&(module.clone(), func.name.clone())) else {
+                return Err(Trap::Error(format!("Call to the unresolved import {}.{}", module, func.name)));
+            };
+            return host(&mut self.memory, &args);
+        }
+        if self.depth == MAX_DEPTH {
+            return trap("Call stack exhausted");
+        }
+
+        self.depth += 1;
+        let vars = func.args.iter().zip(args).map(|(arg, value)| Var { name: arg.name.clone(), kind: arg.kind.clone(), value }).collect();
+        let mut frame = Frame { vars, bindings: vec![] };
+        let mut result = None;
+        for node in func.body.iter().flatten() {
+            if let AST::Ret(expr, _) = node {
+                result = Some(match expr {
+                    Some(expr) => {
+                        let (value, kind) = self.eval(expr, Some(func.ret_kind.clone()), &mut frame)?;
+                        assert!(kind == func.ret_kind, "Invalid return type, expected {:?} got {:?}", func.ret_kind, kind);
+                        Some(value)
+                    },
+                    None => {
+                        assert!(func.ret_kind == PlatTypes::Void, "Invalid return type, expected {:?} got Void", func.ret_kind);
+                        None
+                    }
+                });
+                break;
+            }
+            self.exec(node, &mut frame)?;
+        }
+        self.depth -= 1;
+
+        match result {
+            Some(value) => Ok(value),
+            None if func.ret_kind == PlatTypes::Void => Ok(None),
+            None => panic!("The function {} ends without returning a value", func.name)
+        }
+    }
+
+    fn exec(&mut self, node: &'a AST, frame: &mut Frame) -> Result<(), Trap> {
+        match node {
+            AST::Func(_) => panic!("Nested functions aren't supported..."),
+            AST::Struct(_) | AST::Enum(_) => panic!("Types have to be defined at the top level"),
+            AST::Def(def) => {
+                assert!(def.kind != PlatTypes::Void, "Invalid definition type! Type: {:?}", def.kind);
+                if frame.vars.iter().all(|var| var.name != def.name) {
+                    frame.vars.push(Var { name: def.name.clone(), kind: def.kind.clone(), value: zero(&def.kind) });
+                }
+                let expr = def.value.as_ref().unwrap_or_else(|| panic!("The variable {} has no value...", def.name));
+                let (value, kind) = self.eval(expr, Some(def.kind.clone()), frame)?;
+                assert!(kind == def.kind, "Invalid definition value, expected {:?} got {:?}", def.kind, kind);
+                frame.vars.iter_mut().find(|var| var.name == def.name).unwrap().value = value;
+            },
+            AST::Assign(assign) => match &assign.target {
+                Expr::Literal(name, _) if is_name(name) => {
+                    let kind = frame.find(name).kind.clone();
+                    let value = self.operand(&assign.value, kind, frame)?;
+                    frame.vars.iter_mut().find(|var| var.name == *name).unwrap().value = value;
+                },
+                Expr::Field(field) => {
+                    let (kind, address, offset) = self.field(field, frame)?;
+                    let value = self.operand(&assign.value, kind.clone(), frame)?;
+                    self.store(store_kind(&kind), address, offset, value)?;
+                },
+                Expr::Index(index) => {
+                    let (kind, address, offset) = self.index(index, frame)?;
+                    let value = self.operand(&assign.value, kind.clone(), frame)?;
+                    self.store(store_kind(&kind), address, offset, value)?;
+                },
+                target => panic!("Can't assign to {}", target)
+            },
+            AST::Ret(..) => unreachable!(),
+            AST::Expr(expr, _) => {self.eval(expr, None, frame)?;}
+        }
+        Ok(())
+    }
+
+    fn find_struct(&self, name: &str) -> &Struct {
+        self.structs.get(name).unwrap_or_else(|| panic!("The struct {} does not exist...", name))
+    }
+
+    fn find_enum(&self, name: &str) -> &Enum {
+        self.enums.get(name).unwrap_or_else(|| panic!("The enum {} does not exist...", name))
+    }
+
+    fn struct_field(&self, name: &str, field: &str) -> (PlatTypes, u32) {
+        let (_, kind, offset) = self.find_struct(name).fields.iter().find(|(other, ..)| other == field).unwrap_or_else(|| panic!("The struct {} has no field {}", name, field));
+        (kind.clone(), *offset)
+    }
+
+    // The static type of an expression, following the compiler's inference rules
+    fn infer(&self, expr: &Expr, frame: &Frame, bindings: &[(String, PlatTypes)]) -> Option<PlatTypes> {
+        match expr {
+            Expr::Literal(value, _) if is_name(value) => match bindings.iter().rev().find(|(name, _)| name == value) {
+                Some((_, kind)) => Some(kind.clone()),
+                None => Some(frame.find(value).kind.clone())
+            },
+            Expr::Literal(_, _) => None,
+            Expr::Str(_, _) => Some(PlatTypes::Str),
+            Expr::Unary(expr) if expr.operator.kind == PlatTokenKinds::Bang => Some(PlatTypes::I32),
+            Expr::Unary(expr) => self.infer(&expr.expr, frame, bindings),
+            Expr::Binary(expr) if is_comparison(&expr.operator.kind) => Some(PlatTypes::I32),
+            Expr::Binary(expr) => self.infer(&expr.left, frame, bindings).or_else(|| self.infer(&expr.right, frame, bindings)),
+            Expr::Group(expr, _) => self.infer(expr, frame, bindings),
+            Expr::Call(expr) => match intrinsics::lookup(&expr.name) {
+                Some(Intrinsic::Load { kind, .. }) => Some(kind),
+                Some(Intrinsic::Store { .. }) | Some(Intrinsic::Print { .. }) | Some(Intrinsic::Free) => Some(PlatTypes::Void),
+                Some(_) => Some(PlatTypes::I32),
+                None => Some(self.functions.get(expr.name.as_str()).unwrap_or_else(|| panic!("The function {} does not exist...", expr.name)).ret_kind.clone())
+            },
+            Expr::StructLit(expr) => Some(PlatTypes::Named(expr.name.clone())),
+            Expr::Field(expr) => match self.infer(&expr.expr, frame, bindings) {
+                Some(PlatTypes::Named(name)) => Some(self.struct_field(&name, &expr.field).0),
+                kind => panic!("Can't access the field {} of {:?}", expr.field, kind)
+            },
+            Expr::ArrayLit(expr) => {
+                let count = expr.count.unwrap_or(expr.elements.len() as u32);
+                self.infer(&expr.elements[0], frame, bindings).map(|elem| PlatTypes::Array(Box::new(elem), count))
+            },
+            Expr::Index(expr) => match self.infer(&expr.expr, frame, bindings) {
+                Some(PlatTypes::Array(elem, _)) => Some(*elem),
+                kind => panic!("Can't index {:?}", kind)
+            },
+            Expr::Variant(expr) => Some(PlatTypes::Named(expr.enum_name.clone())),
+            Expr::Match(expr) => {
+                let kind = self.infer(&expr.expr, frame, bindings).unwrap_or_else(|| default_type(&expr.expr));
+                expr.arms.iter().find_map(|arm| {
+                    let mut bindings = bindings.to_vec();
+                    bindings.extend(self.pattern_bindings(&kind, &arm.pattern).into_iter().map(|(name, kind, _)| (name, kind)));
+                    self.infer(&arm.body, frame, &bindings)
+                })
+            }
+        }
+    }
+
+    // Names, types and payload offsets of the values a pattern binds, `_` bindings are left out
+    fn pattern_bindings(&self, kind: &PlatTypes, pattern: &parser::Pattern) -> Vec<(String, PlatTypes, u32)> {
+        let parser::Pattern::Variant { enum_name, variant, bindings } = pattern else {return vec![]};
+        assert!(*kind == PlatTypes::Named(enum_name.clone()), "Can't match {:?} against {}", kind, pattern);
+        let (_, fields) = self.find_enum(enum_name).variants.iter().find(|(name, _)| name == variant).unwrap_or_else(|| panic!("The enum {} has no variant {}", enum_name, variant));
+        assert!(bindings.len() == fields.len(), "The variant {}::{} has {} values, got {} bindings", enum_name, variant, fields.len(), bindings.len());
+        bindings.iter().zip(fields.iter()).filter(|(name, _)| *name != "_").map(|(name, (kind, offset))| (name.clone(), kind.clone(), *offset)).collect()
+    }
+
+    fn effective_address(&self, address: i32, offset: u32, size: usize) -> Result<usize, Trap> {
+        let address = address as u32 as usize + offset as usize;
+        if address + size > self.memory.len() {trap("Out of bounds memory access")} else {Ok(address)}
+    }
+
+    fn bytes<const N: usize>(&self, address: i32, offset: u32) -> Result<[u8; N], Trap> {
+        let address = self.effective_address(address, offset, N)?;
+        Ok(self.memory[address..address + N].try_into().unwrap())
+    }
+
+    fn load(&self, op: LoadKind, address: i32, offset: u32) -> Result<Value, Trap> {
+        Ok(match op {
+            LoadKind::I32 => Value::I32(i32::from_le_bytes(self.bytes(address, offset)?)),
+            LoadKind::I64 => Value::I64(i64::from_le_bytes(self.bytes(address, offset)?)),
+            LoadKind::F32 => Value::F32(f32::from_le_bytes(self.bytes(address, offset)?)),
+            LoadKind::F64 => Value::F64(f64::from_le_bytes(self.bytes(address, offset)?)),
+            LoadKind::I32U8 => Value::I32(u8::from_le_bytes(self.bytes(address, offset)?) as i32),
+            LoadKind::I32S8 => Value::I32(i8::from_le_bytes(self.bytes(address, offset)?) as i32),
+            LoadKind::I32U16 => Value::I32(u16::from_le_bytes(self.bytes(address, offset)?) as i32),
+            LoadKind::I32S16 => Value::I32(i16::from_le_bytes(self.bytes(address, offset)?) as i32),
+            LoadKind::I64U8 => Value::I64(u8::from_le_bytes(self.bytes(address, offset)?) as i64),
+            LoadKind::I64S8 => Value::I64(i8::from_le_bytes(self.bytes(address, offset)?) as i64),
+            LoadKind::I64U16 => Value::I64(u16::from_le_bytes(self.bytes(address, offset)?) as i64),
+            LoadKind::I64S16 => Value::I64(i16::from_le_bytes(self.bytes(address, offset)?) as i64),
+            LoadKind::I64U32 => Value::I64(u32::from_le_bytes(self.bytes(address, offset)?) as i64),
+            LoadKind::I64S32 => Value::I64(i32::from_le_bytes(self.bytes(address, offset)?) as i64)
+        })
+    }
+
+    fn store(&mut self, op: StoreKind, address: i32, offset: u32, value: Value) -> Result<(), Trap> {
+        let bytes = match (op, value) {
+            (StoreKind::I32, Value::I32(value)) => value.to_le_bytes().to_vec(),
+            (StoreKind::I64, Value::I64(value)) => value.to_le_bytes().to_vec(),
+            (StoreKind::F32, Value::F32(value)) => value.to_le_bytes().to_vec(),
+            (StoreKind::F64, Value::F64(value)) => value.to_le_bytes().to_vec(),
+            (StoreKind::I32U8, Value::I32(value)) => vec![value as u8],
+            (StoreKind::I32U16, Value::I32(value)) => (value as u16).to_le_bytes().to_vec(),
+            (StoreKind::I64U8, Value::I64(value)) => vec![value as u8],
+            (StoreKind::I64U16, Value::I64(value)) => (value as u16).to_le_bytes().to_vec(),
+            (StoreKind::I64U32, Value::I64(value)) => (value as u32).to_le_bytes().to_vec(),
+            (op, value) => panic!("Can't store {:?} with {:?}", value, op)
+        };
+        let address = self.effective_address(address, offset, bytes.len())?;
+        self.memory[address..address + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn pages(&self) -> u32 {
+        (self.memory.len() / PAGE_SIZE) as u32
+    }
+
+    fn memory_grow(&mut self, delta: u32) -> i32 {
+        let (pages, maximum) = (self.pages(), self.options.memory.maximum.unwrap_or(MAX_PAGES).min(MAX_PAGES));
+        if pages.checked_add(delta).is_some_and(|total| total <= maximum) {
+            self.memory.resize((pages + delta) as usize * PAGE_SIZE, 0);
+            pages as i32
+        } else {
+            -1
+        }
+    }
+
+    // Moves the heap top past `size` bytes at `align` after a `header`, like the runtime's bump()
+    fn bump(&mut self, header: u32, size: u32, align: u32) -> Result<u32, Trap> {
+        let ptr = self.heap_top.wrapping_add(header).wrapping_add(align.wrapping_sub(1)) & 0u32.wrapping_sub(align);
+        let end = ptr.wrapping_add(size);
+        if end < ptr {
+            return trap("Out of memory");
+        }
+        let pages = (end.wrapping_add(0xFFFF) >> 16).wrapping_sub(self.pages()) as i32;
+        if pages > 0 && self.memory_grow(pages as u32) == -1 {
+            return trap("Out of memory");
+        }
+        self.heap_top = end;
+        Ok(ptr)
+    }
+
+    fn alloc(&mut self, size: i32, align: i32) -> Result<i32, Trap> {
+        let (size, align) = (size as u32, align as u32);
+        if !align.is_power_of_two() {
+            return trap("The alignment has to be a power of two");
+        }
+        if self.options.allocator == Allocator::Bump {
+            return self.bump(0, size, align).map(|ptr| ptr as i32);
+        }
+
+        let align = align.max(HEADER_SIZE);
+        let size = size.wrapping_add(HEAP_ALIGN - 1) & !(HEAP_ALIGN - 1);
+        let (mut ptr, mut prev) = (self.free_list, 0u32);
+        while ptr != 0 {
+            let block = address(self.load(LoadKind::I32, ptr.wrapping_sub(8) as i32, 0)?) as u32;
+            let next = address(self.load(LoadKind::I32, ptr.wrapping_sub(4) as i32, 0)?);
+            if block >= size && ptr & align.wrapping_sub(1) == 0 {
+                if prev == 0 {
+                    self.free_list = next as u32;
+                } else {
+                    self.store(StoreKind::I32, prev.wrapping_sub(4) as i32, 0, Value::I32(next))?;
+                }
+                self.store(StoreKind::I32, ptr.wrapping_sub(4) as i32, 0, Value::I32(IN_USE as i32))?;
+                return Ok(ptr as i32);
+            }
+            prev = ptr;
+            ptr = next as u32;
+        }
+        let ptr = self.bump(HEADER_SIZE, size, align)?;
+        self.store(StoreKind::I32, ptr.wrapping_sub(8) as i32, 0, Value::I32(size as i32))?;
+        self.store(StoreKind::I32, ptr.wrapping_sub(4) as i32, 0, Value::I32(IN_USE as i32))?;
+        Ok(ptr as i32)
+    }
+
+    fn free(&mut self, ptr: i32) -> Result<(), Trap> {
+        if self.options.allocator == Allocator::FreeList && ptr != 0 {
+            if address(self.load(LoadKind::I32, ptr.wrapping_sub(4), 0)?) as u32 != IN_USE {
+                return trap("The block is not in use, it was freed before");
+            }
+            self.store(StoreKind::I32, ptr.wrapping_sub(4), 0, Value::I32(self.free_list as i32))?;
+            self.free_list = ptr as u32;
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self, expr: &'a Expr, kind: PlatTypes, frame: &mut Frame) -> Result<Value, Trap> {
+        let (value, got) = self.eval(expr, Some(kind.clone()), frame)?;
+        assert!(got == kind, "Invalid operand type, expected {:?} got {:?}", kind, got);
+        Ok(value)
+    }
+
+    fn constant(value: &str, kind: &PlatTypes) -> Value {
+        match kind {
+            PlatTypes::I32 => Value::I32(value.parse().unwrap_or_else(|_| panic!("Invalid i32 literal {}", value))),
+            PlatTypes::I64 => Value::I64(value.parse().unwrap_or_else(|_| panic!("Invalid i64 literal {}", value))),
+            PlatTypes::F32 => Value::F32(value.parse().unwrap_or_else(|_| panic!("Invalid f32 literal {}", value))),
+            PlatTypes::F64 => Value::F64(value.parse().unwrap_or_else(|_| panic!("Invalid f64 literal {}", value))),
+            _ => panic!("Invalid literal type! Type: {:?}", kind)
+        }
+    }
+
+    // Evaluates an expression, returning its value and type. Void expressions return a dummy value.
+    fn eval(&mut self, expr: &'a Expr, expected: Option<PlatTypes>, frame: &mut Frame) -> Result<(Value, PlatTypes), Trap> {
+        let expected = expected.filter(|kind| *kind != PlatTypes::Void);
+        Ok(match expr {
+            Expr::Literal(value, _) if is_name(value) => {
+                let var = frame.find(value);
+                (var.value, var.kind.clone())
+            },
+            Expr::Literal(value, _) => {
+                let kind = expected.unwrap_or(literal_type(value));
+                (Evaluator::constant(value, &kind), kind)
+            },
+            Expr::Unary(unary) if unary.operator.kind == PlatTokenKinds::Bang => {
+                let kind = self.infer(&unary.expr, frame, &[]).unwrap_or(PlatTypes::I32);
+                let value = match self.operand(&unary.expr, kind.clone(), frame)? {
+                    Value::I32(value) => value == 0,
+                    Value::I64(value) => value == 0,
+                    _ => panic!("Invalid operand type for \"!\": {:?}", kind)
+                };
+                (Value::I32(value as i32), PlatTypes::I32)
+            },
+            Expr::Unary(unary) if unary.operator.kind == PlatTokenKinds::Minus => {
+                let kind = self.infer(&unary.expr, frame, &[]).or(expected).unwrap_or_else(|| default_type(&unary.expr));
+                let value = match &unary.expr {
+                    Expr::Literal(value, _) if !is_name(value) => Evaluator::constant(&format!("-{}", value), &kind),
+                    _ => match self.operand(&unary.expr, kind.clone(), frame)? {
+                        Value::I32(value) => Value::I32(0i32.wrapping_sub(value)),
+                        Value::I64(value) => Value::I64(0i64.wrapping_sub(value)),
+                        Value::F32(value) => Value::F32(-value),
+                        Value::F64(value) => Value::F64(-value)
+                    }
+                };
+                assert!(matches!(kind, PlatTypes::I32 | PlatTypes::I64 | PlatTypes::F32 | PlatTypes::F64), "Invalid operand type for \"-\": {:?}", kind);
+                (value, kind)
+            },
+            Expr::Unary(unary) => panic!("Invalid unary operator {:?}", unary.operator),
+            Expr::Binary(binary) => {
+                let comparison = is_comparison(&binary.operator.kind);
+                let kind = self.infer(&binary.left, frame, &[])
+                    .or_else(|| self.infer(&binary.right, frame, &[]))
+                    .or(if comparison {None} else {expected})
+                    .unwrap_or_else(|| default_type(&binary.left));
+                assert!(matches!(kind, PlatTypes::I32 | PlatTypes::I64 | PlatTypes::F32 | PlatTypes::F64), "Invalid operand type for {:?}: {:?}", binary.operator.kind, kind);
+                let left = self.operand(&binary.left, kind.clone(), frame)?;
+                let right = self.operand(&binary.right, kind.clone(), frame)?;
+                (arithmetic(&binary.operator.kind, left, right)?, if comparison {PlatTypes::I32} else {kind})
+            },
+            Expr::Str(value, _) => (Value::I32(self.strings[value.as_str()] as i32), PlatTypes::Str),
+            Expr::Group(expr, _) => self.eval(expr, expected, frame)?,
+            Expr::Call(call) => match intrinsics::lookup(&call.name) {
+                Some(intrinsic) => self.intrinsic(intrinsic, call, frame)?,
+                None => {
+                    let func = *self.functions.get(call.name.as_str()).unwrap_or_else(|| panic!("The function {} does not exist...", call.name));
+                    assert!(call.args.len() == func.args.len(), "The function {} expects {} arguments, got {}", call.name, func.args.len(), call.args.len());
+                    let mut args = vec![];
+                    for (arg, param) in call.args.iter().zip(func.args.iter()) {
+                        args.push(self.operand(arg, param.kind.clone(), frame)?);
+                    }
+                    let result = self.invoke(func, args)?;
+                    (result.unwrap_or(Value::I32(0)), func.ret_kind.clone())
+                }
+            },
+            Expr::StructLit(lit) => {
+                let layout = self.find_struct(&lit.name);
+                let (size, align) = (layout.size, layout.align);
+                let mut fields: Vec<(&String, PlatTypes, u32, &Expr)> = vec![];
+                for (name, value) in lit.fields.iter() {
+                    let (kind, offset) = self.struct_field(&lit.name, name);
+                    assert!(fields.iter().all(|(other, ..)| *other != name), "The field {}.{} is initialized more than once", lit.name, name);
+                    fields.push((name, kind, offset, value));
+                }
+                if let Some((missing, ..)) = self.find_struct(&lit.name).fields.iter().find(|(field, ..)| fields.iter().all(|(name, ..)| *name != field)) {
+                    panic!("The field {}.{} is not initialized", lit.name, missing);
+                }
+
+                let instance = self.alloc(size as i32, align as i32)?;
+                for (_, kind, offset, value) in fields {
+                    let value = self.operand(value, kind.clone(), frame)?;
+                    self.store(store_kind(&kind), instance, offset, value)?;
+                }
+                (Value::I32(instance), PlatTypes::Named(lit.name.clone()))
+            },
+            Expr::Field(field) => {
+                let (kind, address, offset) = self.field(field, frame)?;
+                (self.load(load_kind(&kind), address, offset)?, kind)
+            },
+            Expr::ArrayLit(lit) => {
+                let elem = match expected {
+                    Some(PlatTypes::Array(elem, _)) => *elem,
+                    _ => self.infer(&lit.elements[0], frame, &[]).unwrap_or_else(|| default_type(&lit.elements[0]))
+                };
+                assert!(elem != PlatTypes::Void, "Arrays can't hold void values");
+                let count = lit.count.unwrap_or(lit.elements.len() as u32);
+                let (size, store) = (size(&elem), store_kind(&elem));
+                let array = self.alloc((count * size) as i32, size as i32)?;
+                if lit.count.is_none() {
+                    for (i, element) in lit.elements.iter().enumerate() {
+                        let value = self.operand(element, elem.clone(), frame)?;
+                        self.store(store, array, i as u32 * size, value)?;
+                    }
+                } else {
+                    let value = self.operand(&lit.elements[0], elem.clone(), frame)?;
+                    for i in 0..count {
+                        self.store(store, array.wrapping_add((i * size) as i32), 0, value)?;
+                    }
+                }
+                (Value::I32(array), PlatTypes::Array(Box::new(elem), count))
+            },
+            Expr::Index(index) => {
+                let (kind, address, offset) = self.index(index, frame)?;
+                (self.load(load_kind(&kind), address, offset)?, kind)
+            },
+            Expr::Variant(variant) => {
+                let layout = self.find_enum(&variant.enum_name);
+                let (size, align) = (layout.size, layout.align);
+                let tag = layout.variants.iter().position(|(name, _)| *name == variant.variant).unwrap_or_else(|| panic!("The enum {} has no variant {}", variant.enum_name, variant.variant));
+                let fields = layout.variants[tag].1.clone();
+                assert!(variant.args.len() == fields.len(), "The variant {}::{} expects {} values, got {}", variant.enum_name, variant.variant, fields.len(), variant.args.len());
+
+                let instance = self.alloc(size as i32, align as i32)?;
+                self.store(StoreKind::I32, instance, 0, Value::I32(tag as i32))?;
+                for (arg, (kind, offset)) in variant.args.iter().zip(fields) {
+                    let value = self.operand(arg, kind.clone(), frame)?;
+                    self.store(store_kind(&kind), instance, offset, value)?;
+                }
+                (Value::I32(instance), PlatTypes::Named(variant.enum_name.clone()))
+            },
+            Expr::Match(expr) => self.eval_match(expr, expected, frame)?
+        })
+    }
+
+    // Evaluates the struct, returning the field's type, the address of the instance and the field's offset
+    fn field(&mut self, expr: &'a parser::FieldExpr, frame: &mut Frame) -> Result<(PlatTypes, i32, u32), Trap> {
+        match self.eval(&expr.expr, None, frame)? {
+            (value, PlatTypes::Named(name)) => {
+                let (kind, offset) = self.struct_field(&name, &expr.field);
+                Ok((kind, address(value), offset))
+            },
+            (_, kind) => panic!("Can't access the field {} of {:?}", expr.field, kind)
+        }
+    }
+
+    // Returns the element's type, address and offset. Dynamic indices trap when they are out of
+    // bounds, unless bounds checks are off.
+    fn index(&mut self, expr: &'a parser::IndexExpr, frame: &mut Frame) -> Result<(PlatTypes, i32, u32), Trap> {
+        let (array, elem, count) = match self.eval(&expr.expr, None, frame)? {
+            (value, PlatTypes::Array(elem, count)) => (address(value), *elem, count),
+            (_, kind) => panic!("Can't index {:?}", kind)
+        };
+        let size = size(&elem);
+        if let Expr::Literal(value, _) = &expr.index {
+            if !is_name(value) {
+                let index = value.parse::<u32>().unwrap_or_else(|_| panic!("Invalid index {}", value));
+                assert!(index < count, "The index {} is out of bounds for an array of length {}", index, count);
+                return Ok((elem, array, index * size));
+            }
+        }
+
+        let index = address(self.operand(&expr.index, PlatTypes::I32, frame)?);
+        if self.options.bounds_checks && index as u32 >= count {
+            return trap("Array index out of bounds");
+        }
+        Ok((elem, array.wrapping_add(index.wrapping_mul(size as i32)), 0))
+    }
+
+    fn eval_match(&mut self, expr: &'a parser::MatchExpr, expected: Option<PlatTypes>, frame: &mut Frame) -> Result<(Value, PlatTypes), Trap> {
+        let scrutinee = self.infer(&expr.expr, frame, &[]).unwrap_or_else(|| default_type(&expr.expr));
+        let wildcard = expr.arms.iter().position(|arm| matches!(arm.pattern, parser::Pattern::Wildcard));
+        if let Some(position) = wildcard {
+            assert!(position == expr.arms.len() - 1, "The match arms after `_` are unreachable");
+        }
+        let value = self.operand(&expr.expr, scrutinee.clone(), frame)?;
+
+        let arm = match (&scrutinee, value) {
+            (PlatTypes::Named(name), Value::I32(instance)) if self.enums.contains_key(name.as_str()) => {
+                // A tag outside of the enum goes to the default arm, like the br_table of the compiled code
+                let labels: Vec<usize> = self.find_enum(name).variants.iter().map(|(variant, _)| {
+                    expr.arms.iter().position(|arm| matches!(&arm.pattern, parser::Pattern::Variant { variant: other, .. } if other == variant)).or(wildcard)
+                        .unwrap_or_else(|| panic!("The match on {} doesn't cover {}::{}", name, name, variant))
+                }).collect();
+                let tag = address(self.load(LoadKind::I32, instance, 0)?);
+                labels.get(tag as u32 as usize).copied().unwrap_or(wildcard.unwrap_or(*labels.last().unwrap()))
+            },
+            (PlatTypes::I32 | PlatTypes::I64, value) => {
+                let wildcard = wildcard.unwrap_or_else(|| panic!("The match on {:?} needs a `_` arm", scrutinee));
+                expr.arms.iter().position(|arm| match &arm.pattern {
+                    parser::Pattern::Literal(literal) => Evaluator::constant(literal, &scrutinee) == value,
+                    _ => false
+                }).unwrap_or(wildcard)
+            },
+            (kind, _) => panic!("Can't match on {:?}", kind)
+        };
+
+        let arm_bindings: Vec<_> = expr.arms.iter().map(|arm| self.pattern_bindings(&scrutinee, &arm.pattern)).collect();
+        let kind = expr.arms.iter().zip(arm_bindings.iter())
+            .find_map(|(arm, bindings)| self.infer(&arm.body, frame, &bindings.iter().map(|(name, kind, _)| (name.clone(), kind.clone())).collect::<Vec<_>>()))
+            .or(expected)
+            .unwrap_or_else(|| default_type(&expr.arms[0].body));
+
+        let scope = frame.bindings.len();
+        for (name, binding_kind, offset) in arm_bindings[arm].iter() {
+            let value = self.load(load_kind(binding_kind), address(value), *offset)?;
+            frame.bindings.push(Var { name: name.clone(), kind: binding_kind.clone(), value });
+        }
+        let result = self.eval(&expr.arms[arm].body, Some(kind.clone()), frame);
+        frame.bindings.truncate(scope);
+        let (result, got) = result?;
+        assert!(got == kind, "Invalid operand type, expected {:?} got {:?}", kind, got);
+        Ok((result, kind))
+    }
+
+    fn intrinsic(&mut self, intrinsic: Intrinsic, expr: &'a parser::CallExpr, frame: &mut Frame) -> Result<(Value, PlatTypes), Trap> {
+        let void = (Value::I32(0), PlatTypes::Void);
+        Ok(match intrinsic {
+            Intrinsic::Load { op, kind } => {
+                assert!(expr.args.len() == 1 || expr.args.len() == 2, "{} expects an address and an optional offset", expr.name);
+                let address = address(self.operand(&expr.args[0], PlatTypes::I32, frame)?);
+                let offset = expr.args.get(1).map(offset).unwrap_or(0);
+                (self.load(op, address, offset)?, kind)
+            },
+            Intrinsic::Store { op, kind } => {
+                assert!(expr.args.len() == 2 || expr.args.len() == 3, "{} expects an address, a value and an optional offset", expr.name);
+                let address = address(self.operand(&expr.args[0], PlatTypes::I32, frame)?);
+                let value = self.operand(&expr.args[1], kind, frame)?;
+                let offset = expr.args.get(2).map(offset).unwrap_or(0);
+                self.store(op, address, offset, value)?;
+                void
+            },
+            Intrinsic::MemorySize => {
+                assert!(expr.args.is_empty(), "{} doesn't take any arguments", expr.name);
+                (Value::I32(self.pages() as i32), PlatTypes::I32)
+            },
+            Intrinsic::MemoryGrow => {
+                assert!(expr.args.len() == 1, "{} expects the number of pages", expr.name);
+                let pages = address(self.operand(&expr.args[0], PlatTypes::I32, frame)?);
+                (Value::I32(self.memory_grow(pages as u32)), PlatTypes::I32)
+            },
+            Intrinsic::StrLen => {
+                assert!(expr.args.len() == 1, "{} expects a string", expr.name);
+                let string = address(self.operand(&expr.args[0], PlatTypes::Str, frame)?);
+                (self.load(LoadKind::I32, string, 0)?, PlatTypes::I32)
+            },
+            Intrinsic::StrPtr => {
+                assert!(expr.args.len() == 1, "{} expects a string", expr.name);
+                let string = address(self.operand(&expr.args[0], PlatTypes::Str, frame)?);
+                (Value::I32(string.wrapping_add(4)), PlatTypes::I32)
+            },
+            Intrinsic::Len => {
+                assert!(expr.args.len() == 1, "{} expects an array or a string", expr.name);
+                match self.eval(&expr.args[0], None, frame)? {
+                    (_, PlatTypes::Array(_, count)) => (Value::I32(count as i32), PlatTypes::I32),
+                    (string, PlatTypes::Str) => (self.load(LoadKind::I32, address(string), 0)?, PlatTypes::I32),
+                    (_, kind) => panic!("{} expects an array or a string, got {:?}", expr.name, kind)
+                }
+            },
+            Intrinsic::Alloc => {
+                assert!(expr.args.len() == 2, "{} expects a size and an alignment", expr.name);
+                let size = address(self.operand(&expr.args[0], PlatTypes::I32, frame)?);
+                let align = address(self.operand(&expr.args[1], PlatTypes::I32, frame)?);
+                (Value::I32(self.alloc(size, align)?), PlatTypes::I32)
+            },
+            Intrinsic::Free => {
+                assert!(expr.args.len() == 1, "{} expects an address", expr.name);
+                let kind = self.infer(&expr.args[0], frame, &[]).unwrap_or_else(|| default_type(&expr.args[0]));
+                assert!(matches!(kind, PlatTypes::I32 | PlatTypes::Named(_) | PlatTypes::Array(..)), "{} expects an address or a heap value, got {:?}", expr.name, kind);
+                let ptr = address(self.operand(&expr.args[0], kind, frame)?);
+                self.free(ptr)?;
+                void
+            },
+            Intrinsic::Print { newline } => {
+                assert!(expr.args.len() == 1, "{} expects a single value", expr.name);
+                assert!(self.options.target == Target::Wasi, "{} is only available with the wasi target", expr.name);
+                let kind = self.infer(&expr.args[0], frame, &[]).unwrap_or_else(|| default_type(&expr.args[0]));
+                let value = self.operand(&expr.args[0], kind.clone(), frame)?;
+                let text = match (kind, value) {
+                    (PlatTypes::Str, Value::I32(string)) => {
+                        let len = address(self.load(LoadKind::I32, string, 0)?) as u32 as usize;
+                        let start = self.effective_address(string, 4, len)?;
+                        self.memory[start..start + len].to_vec()
+                    },
+                    (PlatTypes::Void, _) => panic!("Can't print a void value"),
+                    (PlatTypes::Named(name), _) => panic!("Can't print the struct {}", name),
+                    (PlatTypes::Array(..), _) => panic!("Can't print an array"),
+                    (_, Value::I32(value)) => value.to_string().into_bytes(),
+                    (_, Value::I64(value)) => value.to_string().into_bytes(),
+                    (_, Value::F32(value)) => format_f64(value as f64).into_bytes(),
+                    (_, Value::F64(value)) => format_f64(value).into_bytes()
+                };
+                self.output.extend(text);
+                if newline {
+                    self.output.push(b'\n');
+                }
+                void
+            }
+        })
+    }
+}
@@ -0,0 +1,391 @@
+use crate::lexer::{Comment, PlatToken, PlatTokenKinds, Span};
+use crate::parser::{AssignExpr, DefExpr, EnumExpr, Expr, FuncExpr, MatchExpr, StructExpr, AST};
+
+// Prints the AST back as source in the canonical layout: four spaces of indentation, one statement
+// per line ending in `;`, spaces around binary operators and opening braces on the line of their
+// item. Matches, structs and enums stay on one line when they fit. `platinum fmt` also puts the
+// comments back and keeps single blank lines between statements, the Display impls of the AST
+// print without them.
+
+const WIDTH: usize = 80;                                            // Columns a match, struct or enum has to fit in to stay on one line
+const INDENT: &str = "    ";
+
+type Pos = (u32, u32);
+
+fn pos(span: Span) -> Pos {
+    (span.line, span.column)
+}
+
+fn quote(value: &str) -> String {
+    let mut text = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            '\r' => text.push_str("\\r"),
+            '\0' => text.push_str("\\0"),
+            c => text.push(c)
+        }
+    }
+    text.push('"');
+    text
+}
+
+pub(crate) struct Printer<'a> {
+    out: String,
+    indent: usize,
+    // Everything stays on one line
+    flat: bool,
+    // Source of `fmt`, empty for the Display impls
+    tokens: &'a [PlatToken],
+    comments: &'a [Comment],
+    lines: Vec<&'a str>,
+    // Comments that are printed already
+    printed: usize,
+    // The next line is separated by a blank line
+    blank: bool,
+    // Nothing was printed since the last opening brace
+    block_start: bool
+}
+
+// Prints a node without comments, for the Display impls
+pub(crate) fn print(print: impl FnOnce(&mut Printer)) -> String {
+    let mut printer = Printer::new(&[], &[], "");
+    print(&mut printer);
+    printer.out
+}
+
+pub(crate) fn format(ast: &[AST], tokens: &[PlatToken], comments: &[Comment], src: &str) -> String {
+    let mut printer = Printer::new(tokens, comments, src);
+    for node in ast.iter() {
+        let start = pos(node.span());
+        printer.comments_before(start);
+        printer.line(Some(start));
+        printer.node(node);
+        printer.out.push('\n');
+        printer.blank = true;
+    }
+    printer.comments_before((u32::MAX, u32::MAX));
+    printer.out
+}
+
+impl<'a> Printer<'a> {
+    fn new(tokens: &'a [PlatToken], comments: &'a [Comment], src: &'a str) -> Printer<'a> {
+        Printer { out: String::new(), indent: 0, flat: false, tokens, comments, lines: src.split('\n').collect(), printed: 0, blank: false, block_start: false }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn column(&self) -> usize {
+        self.out[self.out.rfind('\n').map_or(0, |newline| newline + 1)..].chars().count()
+    }
+
+    // Starts a line, after a blank line if one comes before `at` in the source
+    fn line(&mut self, at: Option<Pos>) {
+        let blank_above = at.is_some_and(|(line, _)| line >= 2 && self.lines.get(line as usize - 2).is_some_and(|text| text.trim().is_empty()));
+        if !self.out.is_empty() && !self.block_start && (self.blank || blank_above) {
+            self.out.push('\n');
+        }
+        self.blank = false;
+        self.block_start = false;
+        self.write(&INDENT.repeat(self.indent));
+    }
+
+    fn open(&mut self) {
+        self.write("{\n");
+        self.indent += 1;
+        self.block_start = true;
+    }
+
+    // Prints the comments that come before `end`, `end` being the closing brace of the block
+    fn close(&mut self, end: Option<Pos>) {
+        if let Some(end) = end {
+            self.comments_before(end);
+        }
+        self.indent -= 1;
+        self.line(None);
+        self.write("}");
+    }
+
+    fn has_comments_before(&self, end: Option<Pos>) -> bool {
+        end.zip(self.comments.get(self.printed)).is_some_and(|(end, comment)| pos(comment.span) < end)
+    }
+
+    // Comments after code on their line stay at the end of the line that was printed last
+    fn comments_before(&mut self, until: Pos) {
+        while let Some(comment) = self.comments.get(self.printed).filter(|comment| pos(comment.span) < until) {
+            self.printed += 1;
+            let (line, column) = pos(comment.span);
+            let before: String = self.lines[line as usize - 1].chars().take(column as usize - 1).collect();
+            if !before.trim().is_empty() && self.out.ends_with('\n') {
+                self.out.pop();
+                self.write(" ");
+            } else {
+                self.line(Some((line, column)));
+            }
+            self.write(&comment.text);
+            self.out.push('\n');
+        }
+    }
+
+    // Closing brace of the first block at or after `span`
+    fn closing(&self, span: Span) -> Option<Pos> {
+        let start = self.tokens.partition_point(|token| pos(token.span) < pos(span));
+        let mut depth = 0;
+        for token in self.tokens.iter().skip(start).skip_while(|token| token.kind != PlatTokenKinds::OpenCurly) {
+            match token.kind {
+                PlatTokenKinds::OpenParen | PlatTokenKinds::OpenBracket | PlatTokenKinds::OpenCurly => depth += 1,
+                PlatTokenKinds::CloseParen | PlatTokenKinds::CloseBracket | PlatTokenKinds::CloseCurly => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(pos(token.span));
+                    }
+                },
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // Starts of the fields or variants in the braces of the struct or enum at `span`
+    fn members(&self, span: Span) -> Vec<Pos> {
+        let start = self.tokens.partition_point(|token| pos(token.span) < pos(span));
+        let mut depth = 0;
+        let mut members = vec![];
+        let mut previous: Option<&PlatTokenKinds> = None;
+        for token in self.tokens.iter().skip(start).skip_while(|token| token.kind != PlatTokenKinds::OpenCurly) {
+            match token.kind {
+                PlatTokenKinds::OpenParen | PlatTokenKinds::OpenBracket | PlatTokenKinds::OpenCurly => depth += 1,
+                PlatTokenKinds::CloseParen | PlatTokenKinds::CloseBracket | PlatTokenKinds::CloseCurly => depth -= 1,
+                PlatTokenKinds::Identifier if depth == 1 && matches!(previous, Some(PlatTokenKinds::OpenCurly | PlatTokenKinds::Comma)) => members.push(pos(token.span)),
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            previous = Some(&token.kind);
+        }
+        members
+    }
+
+    // The text of `print` if it was printed on one line
+    fn flat_text(print: impl FnOnce(&mut Printer)) -> String {
+        let mut printer = Printer::new(&[], &[], "");
+        printer.flat = true;
+        print(&mut printer);
+        printer.out
+    }
+
+    fn fits(&self, text: &str, end: Option<Pos>) -> bool {
+        self.flat || self.column() + text.chars().count() < WIDTH && !self.has_comments_before(end)
+    }
+
+    pub(crate) fn node(&mut self, node: &AST) {
+        match node {
+            AST::Func(func) => self.func(func),
+            AST::Struct(expr) => self.structure(expr),
+            AST::Enum(expr) => self.enumeration(expr),
+            AST::Def(def) => {
+                self.def(def);
+                self.write(";");
+            },
+            AST::Assign(assign) => {
+                self.assign(assign);
+                self.write(";");
+            },
+            AST::Ret(Some(expr), _) => {
+                self.write("return ");
+                self.expr(expr);
+                self.write(";");
+            },
+            AST::Ret(None, _) => self.write("return;"),
+            AST::Expr(expr, _) => {
+                self.expr(expr);
+                self.write(";");
+            }
+        }
+    }
+
+    pub(crate) fn def(&mut self, def: &DefExpr) {
+        self.write(&format!("let {}: {} =", def.name, def.kind));
+        if let Some(value) = &def.value {
+            self.write(" ");
+            self.expr(value);
+        }
+    }
+
+    pub(crate) fn assign(&mut self, assign: &AssignExpr) {
+        self.expr(&assign.target);
+        self.write(" = ");
+        self.expr(&assign.value);
+    }
+
+    pub(crate) fn func(&mut self, func: &FuncExpr) {
+        if let Some(module) = &func.module {
+            self.write(&format!("extern {} ", quote(module)));
+        }
+        let args: Vec<String> = func.args.iter().map(|arg| format!("{}: {}", arg.name, arg.kind)).collect();
+        self.write(&format!("func {}({}) {}", func.name, args.join(", "), func.ret_kind));
+        let Some(body) = func.body.as_ref() else {return self.write(";")};
+
+        let end = self.closing(func.span);
+        if body.is_empty() && !self.has_comments_before(end) {
+            return self.write(" {}");
+        }
+        self.write(" ");
+        self.open();
+        for node in body.iter() {
+            let start = pos(node.span());
+            self.comments_before(start);
+            self.line(Some(start));
+            self.node(node);
+            self.out.push('\n');
+        }
+        self.close(end);
+    }
+
+    // Fields or variants, on one line when they fit
+    fn members_of(&mut self, members: &[String], span: Span) {
+        let end = self.closing(span);
+        let flat = if members.is_empty() {"{}".to_string()} else {format!("{{ {} }}", members.join(", "))};
+        if self.fits(&flat, end) {
+            return self.write(&flat);
+        }
+        let starts = self.members(span);
+        self.open();
+        for (index, member) in members.iter().enumerate() {
+            let start = starts.get(index).copied();
+            if let Some(start) = start {
+                self.comments_before(start);
+            }
+            self.line(start);
+            self.write(&format!("{},\n", member));
+        }
+        self.close(end);
+    }
+
+    pub(crate) fn structure(&mut self, expr: &StructExpr) {
+        self.write(&format!("struct {} ", expr.name));
+        let fields: Vec<String> = expr.fields.iter().map(|field| format!("{}: {}", field.name, field.kind)).collect();
+        self.members_of(&fields, expr.span);
+    }
+
+    pub(crate) fn enumeration(&mut self, expr: &EnumExpr) {
+        self.write(&format!("enum {} ", expr.name));
+        let variants: Vec<String> = expr.variants.iter().map(|variant| {
+            let fields: Vec<String> = variant.fields.iter().map(|kind| kind.to_string()).collect();
+            if fields.is_empty() {variant.name.clone()} else {format!("{}({})", variant.name, fields.join(", "))}
+        }).collect();
+        self.members_of(&variants, expr.span);
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for (index, expr) in exprs.iter().enumerate() {
+            if index != 0 {
+                self.write(", ");
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn match_expr(&mut self, expr: &MatchExpr) {
+        let end = self.closing(expr.span);
+        let flat = self.flat || self.fits(&Printer::flat_text(|printer| printer.match_expr(expr)), end);
+        self.write("match ");
+        self.expr(&expr.expr);
+        if expr.arms.is_empty() && !self.has_comments_before(end) {
+            return self.write(" {}");
+        }
+        if flat {
+            self.write(" { ");
+            for (index, arm) in expr.arms.iter().enumerate() {
+                if index != 0 {
+                    self.write(", ");
+                }
+                self.write(&format!("{} => ", arm.pattern));
+                self.expr(&arm.body);
+            }
+            return self.write(" }");
+        }
+
+        self.write(" ");
+        self.open();
+        for arm in expr.arms.iter() {
+            let start = pos(arm.span);
+            self.comments_before(start);
+            self.line(Some(start));
+            self.write(&format!("{} => ", arm.pattern));
+            self.expr(&arm.body);
+            self.write(",\n");
+        }
+        self.close(end);
+    }
+
+    pub(crate) fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Unary(expr) => {
+                self.write(&expr.operator.value);
+                self.expr(&expr.expr);
+            },
+            Expr::Binary(expr) => {
+                self.expr(&expr.left);
+                self.write(&format!(" {} ", expr.operator.value));
+                self.expr(&expr.right);
+            },
+            // Character literals that don't look like a number or a name keep their quotes
+            Expr::Literal(value, _) if value.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '-') => self.write(value),
+            Expr::Literal(value, _) => self.write(&format!("'{}'", value)),
+            Expr::Str(value, _) => self.write(&quote(value)),
+            Expr::Group(expr, _) => {
+                self.write("(");
+                self.expr(expr);
+                self.write(")");
+            },
+            Expr::Call(expr) => {
+                self.write(&format!("{}(", expr.name));
+                self.exprs(&expr.args);
+                self.write(")");
+            },
+            Expr::StructLit(expr) if expr.fields.is_empty() => self.write(&format!("{} {{}}", expr.name)),
+            Expr::StructLit(expr) => {
+                self.write(&format!("{} {{ ", expr.name));
+                for (index, (name, value)) in expr.fields.iter().enumerate() {
+                    self.write(&format!("{}{}: ", if index == 0 {""} else {", "}, name));
+                    self.expr(value);
+                }
+                self.write(" }");
+            },
+            Expr::Field(expr) => {
+                self.expr(&expr.expr);
+                self.write(&format!(".{}", expr.field));
+            },
+            Expr::ArrayLit(expr) => {
+                self.write("[");
+                self.exprs(&expr.elements);
+                if let Some(count) = expr.count {
+                    self.write(&format!("; {}", count));
+                }
+                self.write("]");
+            },
+            Expr::Index(expr) => {
+                self.expr(&expr.expr);
+                self.write("[");
+                self.expr(&expr.index);
+                self.write("]");
+            },
+            Expr::Variant(expr) => {
+                self.write(&format!("{}::{}", expr.enum_name, expr.variant));
+                if !expr.args.is_empty() {
+                    self.write("(");
+                    self.exprs(&expr.args);
+                    self.write(")");
+                }
+            },
+            Expr::Match(expr) => self.match_expr(expr)
+        }
+    }
+}
@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::decoder::{self, Immediate, Module, F32, F64, I32, I64};
+use crate::validator;
+
+// Interpreter for the WASM subset the compiler emits, used by `platinum run`. Modules are validated
+// before they are instantiated, so operands are assumed to have the right types.
+
+const PAGE_SIZE: usize = 65536;
+// Memories are limited to 256 MiB, memory.grow fails beyond that or the declared maximum
+const MAX_PAGES: u32 = 4096;
+// Nested calls before the interpreter traps. Each is a Rust call, so this needs the 8 MiB stack of a
+// main thread in debug builds.
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64)
+}
+
+impl Value {
+    pub fn zero(kind: u8) -> Value {
+        match kind {
+            I32 => Value::I32(0),
+            I64 => Value::I64(0),
+            F32 => Value::F32(0.0),
+            _ => Value::F64(0.0)
+        }
+    }
+
+    pub fn kind(&self) -> u8 {
+        match self {
+            Value::I32(_) => I32,
+            Value::I64(_) => I64,
+            Value::F32(_) => F32,
+            Value::F64(_) => F64
+        }
+    }
+
+    pub fn parse(kind: u8, text: &str) -> Result<Value, String> {
+        let value = match kind {
+            I32 => text.parse().map(Value::I32).ok(),
+            I64 => text.parse().map(Value::I64).ok(),
+            F32 => text.parse().map(Value::F32).ok(),
+            _ => text.parse().map(Value::F64).ok()
+        };
+        value.ok_or_else(|| format!("Invalid {} argument {}", decoder::value_type_name(kind).unwrap_or("?"), text))
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::I32(value) => write!(f, "{}: i32", value),
+            Value::I64(value) => write!(f, "{}: i64", value),
+            Value::F32(value) => write!(f, "{:?}: f32", value),
+            Value::F64(value) => write!(f, "{:?}: f64", value)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Trap {
+    Error(String),
+    // proc_exit was called with an exit code
+    Exit(i32)
+}
+
+impl std::fmt::Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Trap::Error(message) => write!(f, "{}", message),
+            Trap::Exit(code) => write!(f, "Exited with code {}", code)
+        }
+    }
+}
+
+fn trap<T>(message: &str) -> Result<T, Trap> {
+    Err(Trap::Error(message.to_string()))
+}
+
+// Host functions get the linear memory and the arguments of the call
+pub type HostFn = Box<dyn FnMut(&mut [u8], &[Value]) -> Result<Option<Value>, Trap>>;
+
+// fd_write and proc_exit of wasi_snapshot_preview1. `write` gets what is written to stdout (1) and stderr (2).
+pub fn wasi(mut write: impl FnMut(i32, &[u8]) -> std::io::Result<()> + 'static) -> HashMap<(String, String), HostFn> {
+    let mut imports: HashMap<(String, String), HostFn> = HashMap::new();
+    imports.insert(("wasi_snapshot_preview1".to_string(), "fd_write".to_string()), Box::new(move |memory, args| {
+        let [Value::I32(fd), Value::I32(iovs), Value::I32(len), Value::I32(nwritten)] = *args else {return trap("fd_write expects 4 i32 arguments")};
+        let mut bytes = vec![];
+        for iov in 0..len as u32 {
+            let base = read_u32(memory, iovs as u32 as usize + iov as usize * 8)? as usize;
+            let size = read_u32(memory, iovs as u32 as usize + iov as usize * 8 + 4)? as usize;
+            bytes.extend_from_slice(memory.get(base..base + size).ok_or(Trap::Error("Out of bounds memory access".to_string()))?);
+        }
+        if fd != 1 && fd != 2 {
+            return Ok(Some(Value::I32(8)));                         // EBADF
+        }
+        if write(fd, &bytes).is_err() {
+            return Ok(Some(Value::I32(29)));                        // EIO
+        }
+        let address = nwritten as u32 as usize;
+        memory.get_mut(address..address + 4).ok_or(Trap::Error("Out of bounds memory access".to_string()))?.copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+        Ok(Some(Value::I32(0)))
+    }));
+    imports.insert(("wasi_snapshot_preview1".to_string(), "proc_exit".to_string()), Box::new(|_, args| match args {
+        [Value::I32(code)] => Err(Trap::Exit(*code)),
+        _ => trap("proc_exit expects an i32 argument")
+    }));
+    imports
+}
+
+fn read_u32(memory: &[u8], address: usize) -> Result<u32, Trap> {
+    let bytes = memory.get(address..address + 4).ok_or(Trap::Error("Out of bounds memory access".to_string()))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+// Positions of the matching `else` and `end` of every block, loop and if in a function, by position of its start
+type Blocks = HashMap<usize, (Option<usize>, usize)>;
+
+// Block, loop or if whose body is executing
+struct Label {
+    start: usize,
+    arity: usize,
+    height: usize,
+    is_loop: bool
+}
+
+// Conversions between stack values and the Rust types the numeric instructions work on
+trait Num: Sized {
+    fn from_value(value: Value) -> Self;
+    fn into_value(self) -> Value;
+}
+
+impl Num for i32 {
+    fn from_value(value: Value) -> Self {if let Value::I32(value) = value {value} else {unreachable!()}}
+    fn into_value(self) -> Value {Value::I32(self)}
+}
+
+impl Num for i64 {
+    fn from_value(value: Value) -> Self {if let Value::I64(value) = value {value} else {unreachable!()}}
+    fn into_value(self) -> Value {Value::I64(self)}
+}
+
+impl Num for f32 {
+    fn from_value(value: Value) -> Self {if let Value::F32(value) = value {value} else {unreachable!()}}
+    fn into_value(self) -> Value {Value::F32(self)}
+}
+
+impl Num for f64 {
+    fn from_value(value: Value) -> Self {if let Value::F64(value) = value {value} else {unreachable!()}}
+    fn into_value(self) -> Value {Value::F64(self)}
+}
+
+impl Num for bool {
+    fn from_value(value: Value) -> Self {i32::from_value(value) != 0}
+    fn into_value(self) -> Value {Value::I32(self as i32)}
+}
+
+fn pop<T: Num>(stack: &mut Vec<Value>) -> T {
+    T::from_value(stack.pop().unwrap())
+}
+
+fn unary<T: Num, R: Num>(stack: &mut Vec<Value>, f: impl Fn(T) -> R) {
+    let a = pop(stack);
+    stack.push(f(a).into_value());
+}
+
+fn binary<T: Num, R: Num>(stack: &mut Vec<Value>, f: impl Fn(T, T) -> R) {
+    let b = pop(stack);
+    let a = pop(stack);
+    stack.push(f(a, b).into_value());
+}
+
+fn try_unary<T: Num, R: Num>(stack: &mut Vec<Value>, f: impl Fn(T) -> Result<R, Trap>) -> Result<(), Trap> {
+    let a = pop(stack);
+    stack.push(f(a)?.into_value());
+    Ok(())
+}
+
+fn try_binary<T: Num, R: Num>(stack: &mut Vec<Value>, f: impl Fn(T, T) -> Result<R, Trap>) -> Result<(), Trap> {
+    let b = pop(stack);
+    let a = pop(stack);
+    stack.push(f(a, b)?.into_value());
+    Ok(())
+}
+
+fn check_divisor<T: PartialEq + Default>(b: T) -> Result<(), Trap> {
+    if b == T::default() {trap("Integer divide by zero")} else {Ok(())}
+}
+
+// Truncates a float towards zero, trapping unless the result is in [min, max)
+fn truncate(value: f64, min: f64, max: f64) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return trap("Invalid conversion to integer");
+    }
+    let value = value.trunc();
+    if value < min || value >= max {trap("Integer overflow")} else {Ok(value)}
+}
+
+// min and max return NaN if either operand is NaN and order -0 below +0
+fn min_f32(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {f32::NAN} else if a == b {if a.is_sign_negative() {a} else {b}} else {a.min(b)}
+}
+
+fn max_f32(a: f32, b: f32) -> f32 {
+    if a.is_nan() || b.is_nan() {f32::NAN} else if a == b {if a.is_sign_positive() {a} else {b}} else {a.max(b)}
+}
+
+fn min_f64(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {f64::NAN} else if a == b {if a.is_sign_negative() {a} else {b}} else {a.min(b)}
+}
+
+fn max_f64(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {f64::NAN} else if a == b {if a.is_sign_positive() {a} else {b}} else {a.max(b)}
+}
+
+pub struct Instance {
+    module: Rc<Module>,
+    memory: Vec<u8>,
+    max_pages: u32,
+    globals: Vec<Value>,
+    imports: Vec<Option<HostFn>>,
+    blocks: Rc<Vec<Blocks>>,
+    depth: usize
+}
+
+impl Instance {
+    // Imports are looked up by module and name. Missing ones only trap when they are called.
+    pub fn new(bytes: &[u8], mut host: HashMap<(String, String), HostFn>) -> Result<Instance, String> {
+        let module = decoder::decode(bytes)?;
+        let errors = validator::validate_module(&module);
+        if !errors.is_empty() {
+            return Err(format!("The module is invalid:\n{}", errors.join("\n")));
+        }
+
+        let (pages, max_pages) = module.memories.first().map_or((0, 0), |memory| (memory.min, memory.max.unwrap_or(MAX_PAGES).min(MAX_PAGES)));
+        if pages > MAX_PAGES {
+            return Err(format!("The module needs {} pages of memory, the limit is {}", pages, MAX_PAGES));
+        }
+        let mut memory = vec![0; pages as usize * PAGE_SIZE];
+        for data in module.data.iter() {
+            let Immediate::I32(offset) = data.offset[0].immediate else {unreachable!()};
+            let start = offset as u32 as usize;
+            memory.get_mut(start..start + data.bytes.len()).ok_or("A data segment doesn't fit into the memory")?.copy_from_slice(&data.bytes);
+        }
+        let globals = module.globals.iter().map(|global| match global.init[0].immediate {
+            Immediate::I32(value) => Value::I32(value),
+            Immediate::I64(value) => Value::I64(value),
+            Immediate::F32(value) => Value::F32(value),
+            Immediate::F64(value) => Value::F64(value),
+            _ => unreachable!()
+        }).collect();
+        let imports = module.imports.iter().map(|import| host.remove(&(import.module.clone(), import.name.clone()))).collect();
+
+        let blocks = module.code.iter().map(|body| {
+            let mut blocks = HashMap::new();
+            let mut open: Vec<(usize, Option<usize>)> = vec![];
+            for (pc, instruction) in body.instructions.iter().enumerate() {
+                match instruction.opcode {
+                    0x02..=0x04 => open.push((pc, None)),
+                    0x05 => open.last_mut().unwrap().1 = Some(pc),
+                    0x0B => if let Some((start, otherwise)) = open.pop() {
+                        blocks.insert(start, (otherwise, pc));
+                    },
+                    _ => {}
+                }
+            }
+            blocks
+        }).collect();
+
+        Ok(Instance { module: Rc::new(module), memory, max_pages, globals, imports, blocks: Rc::new(blocks), depth: 0 })
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Index of an exported function
+    pub fn export(&self, name: &str) -> Option<u32> {
+        self.module.exports.iter().find(|export| export.kind == 0x00 && export.name == name).map(|export| export.index)
+    }
+
+    pub fn func_type(&self, index: u32) -> &decoder::FuncType {
+        let n_imports = self.module.imports.len();
+        let type_index = match self.module.imports.get(index as usize) {
+            Some(import) => import.type_index,
+            None => self.module.functions[index as usize - n_imports]
+        };
+        &self.module.types[type_index as usize]
+    }
+
+    pub fn invoke(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>, Trap> {
+        let index = self.export(name).ok_or_else(|| Trap::Error(format!("There is no exported function {}", name)))?;
+        let params: Vec<u8> = args.iter().map(Value::kind).collect();
+        if params != self.func_type(index).params {
+            return Err(Trap::Error(format!("{} expects {} arguments of types {:?}", name, self.func_type(index).params.len(),
+                self.func_type(index).params.iter().map(|kind| decoder::value_type_name(*kind).unwrap()).collect::<Vec<_>>())));
+        }
+        self.depth = 0;
+        self.call(index, args.to_vec())
+    }
+
+    fn call(&mut self, index: u32, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        let n_imports = self.module.imports.len();
+        if (index as usize) < n_imports {
+            let import = &self.module.imports[index as usize];
+            let Some(host) = self.imports[index as usize].as_mut() else {
+                return Err(Trap::Error(format!("Call to the unresolved import {}.{}", import.module, import.name)));
+            };
+            return host(&mut self.memory, &args);
+        }
+        if self.depth == MAX_DEPTH {
+            return trap("Call stack exhausted");
+        }
+        self.depth += 1;
+        let result = self.execute(index as usize - n_imports, args);
+        self.depth -= 1;
+        result
+    }
+
+    fn address(&self, stack: &mut Vec<Value>, offset: u32, size: usize) -> Result<usize, Trap> {
+        let address = pop::<i32>(stack) as u32 as usize + offset as usize;
+        if address + size > self.memory.len() {trap("Out of bounds memory access")} else {Ok(address)}
+    }
+
+    fn load<const N: usize>(&self, stack: &mut Vec<Value>, offset: u32) -> Result<[u8; N], Trap> {
+        let address = self.address(stack, offset, N)?;
+        Ok(self.memory[address..address + N].try_into().unwrap())
+    }
+
+    fn store(&mut self, stack: &mut Vec<Value>, offset: u32, bytes: &[u8]) -> Result<(), Trap> {
+        let address = self.address(stack, offset, bytes.len())?;
+        self.memory[address..address + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn execute(&mut self, position: usize, args: Vec<Value>) -> Result<Option<Value>, Trap> {
+        let module = Rc::clone(&self.module);
+        let blocks = Rc::clone(&self.blocks);
+        let (body, blocks) = (&module.code[position], &blocks[position]);
+        let results = &module.types[module.functions[position] as usize].results;
+
+        let mut locals = args;
+        for (count, kind) in body.locals.iter() {
+            locals.extend(std::iter::repeat_n(Value::zero(*kind), *count as usize));
+        }
+        let mut stack: Vec<Value> = vec![];
+        let mut labels: Vec<Label> = vec![];
+        let mut pc = 0;
+
+        // Unwinds to the label `depth` levels up and returns where execution continues,
+        // or None when the branch leaves the function
+        let branch = |depth: u32, stack: &mut Vec<Value>, labels: &mut Vec<Label>| -> Option<usize> {
+            let position = labels.len().checked_sub(depth as usize + 1)?;
+            let label = &labels[position];
+            let arity = if label.is_loop {0} else {label.arity};
+            let values = stack.split_off(stack.len() - arity);
+            stack.truncate(label.height);
+            stack.extend(values);
+            if label.is_loop {
+                let start = label.start;
+                labels.truncate(position + 1);
+                Some(start + 1)
+            } else {
+                let end = blocks[&label.start].1;
+                labels.truncate(position);
+                Some(end + 1)
+            }
+        };
+
+        loop {
+            let instruction = &body.instructions[pc];
+            pc += 1;
+            match (instruction.opcode, &instruction.immediate) {
+                (0x00, _) => return trap("Unreachable executed"),
+                (0x01, _) => {},
+                (opcode @ 0x02..=0x04, Immediate::Block(kind)) => {
+                    let condition = opcode != 0x04 || pop::<i32>(&mut stack) != 0;
+                    labels.push(Label { start: pc - 1, arity: kind.is_some() as usize, height: stack.len(), is_loop: opcode == 0x03 });
+                    if !condition {
+                        match blocks[&(pc - 1)] {
+                            (Some(otherwise), _) => pc = otherwise + 1,
+                            (None, end) => {
+                                labels.pop();
+                                pc = end + 1;
+                            }
+                        }
+                    }
+                },
+                (0x05, _) => {                                      // End of the then branch
+                    let label = labels.pop().unwrap();
+                    pc = blocks[&label.start].1 + 1;
+                },
+                (0x0B, _) => if labels.pop().is_none() {break},
+                (0x0C, Immediate::Index(depth)) => match branch(*depth, &mut stack, &mut labels) {
+                    Some(target) => pc = target,
+                    None => break
+                },
+                (0x0D, Immediate::Index(depth)) => if pop::<i32>(&mut stack) != 0 {
+                    match branch(*depth, &mut stack, &mut labels) {
+                        Some(target) => pc = target,
+                        None => break
+                    }
+                },
+                (0x0E, Immediate::BrTable(depths, default)) => {
+                    let index = pop::<i32>(&mut stack) as u32 as usize;
+                    match branch(*depths.get(index).unwrap_or(default), &mut stack, &mut labels) {
+                        Some(target) => pc = target,
+                        None => break
+                    }
+                },
+                (0x0F, _) => break,
+                (0x10, Immediate::Index(index)) => {
+                    let n_params = self.func_type(*index).params.len();
+                    let args = stack.split_off(stack.len() - n_params);
+                    if let Some(result) = self.call(*index, args)? {
+                        stack.push(result);
+                    }
+                },
+                (0x1A, _) => {stack.pop();},
+                (0x1B, _) => {
+                    let condition = pop::<i32>(&mut stack);
+                    let (b, a) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    stack.push(if condition != 0 {a} else {b});
+                },
+                (0x20, Immediate::Index(index)) => stack.push(locals[*index as usize]),
+                (0x21, Immediate::Index(index)) => locals[*index as usize] = stack.pop().unwrap(),
+                (0x22, Immediate::Index(index)) => locals[*index as usize] = *stack.last().unwrap(),
+                (0x23, Immediate::Index(index)) => stack.push(self.globals[*index as usize]),
+                (0x24, Immediate::Index(index)) => self.globals[*index as usize] = stack.pop().unwrap(),
+                (opcode @ 0x28..=0x35, Immediate::Memarg { offset, .. }) => {
+                    let value = match opcode {
+                        0x28 => Value::I32(i32::from_le_bytes(self.load(&mut stack, *offset)?)),
+                        0x29 => Value::I64(i64::from_le_bytes(self.load(&mut stack, *offset)?)),
+                        0x2A => Value::F32(f32::from_le_bytes(self.load(&mut stack, *offset)?)),
+                        0x2B => Value::F64(f64::from_le_bytes(self.load(&mut stack, *offset)?)),
+                        0x2C => Value::I32(i8::from_le_bytes(self.load(&mut stack, *offset)?) as i32),
+                        0x2D => Value::I32(u8::from_le_bytes(self.load(&mut stack, *offset)?) as i32),
+                        0x2E => Value::I32(i16::from_le_bytes(self.load(&mut stack, *offset)?) as i32),
+                        0x2F => Value::I32(u16::from_le_bytes(self.load(&mut stack, *offset)?) as i32),
+                        0x30 => Value::I64(i8::from_le_bytes(self.load(&mut stack, *offset)?) as i64),
+                        0x31 => Value::I64(u8::from_le_bytes(self.load(&mut stack, *offset)?) as i64),
+                        0x32 => Value::I64(i16::from_le_bytes(self.load(&mut stack, *offset)?) as i64),
+                        0x33 => Value::I64(u16::from_le_bytes(self.load(&mut stack, *offset)?) as i64),
+                        0x34 => Value::I64(i32::from_le_bytes(self.load(&mut stack, *offset)?) as i64),
+                        _ => Value::I64(u32::from_le_bytes(self.load(&mut stack, *offset)?) as i64)
+                    };
+                    stack.push(value);
+                },
+                (opcode @ 0x36..=0x3E, Immediate::Memarg { offset, .. }) => {
+                    let value = stack.pop().unwrap();
+                    let bytes = match (opcode, value) {
+                        (0x36, Value::I32(value)) => value.to_le_bytes().to_vec(),
+                        (0x37, Value::I64(value)) => value.to_le_bytes().to_vec(),
+                        (0x38, Value::F32(value)) => value.to_le_bytes().to_vec(),
+                        (0x39, Value::F64(value)) => value.to_le_bytes().to_vec(),
+                        (0x3A, Value::I32(value)) => vec![value as u8],
+                        (0x3B, Value::I32(value)) => (value as u16).to_le_bytes().to_vec(),
+                        (0x3C, Value::I64(value)) => vec![value as u8],
+                        (0x3D, Value::I64(value)) => (value as u16).to_le_bytes().to_vec(),
+                        (_, Value::I64(value)) => (value as u32).to_le_bytes().to_vec(),
+                        _ => unreachable!()
+                    };
+                    self.store(&mut stack, *offset, &bytes)?;
+                },
+                (0x3F, _) => stack.push(Value::I32((self.memory.len() / PAGE_SIZE) as i32)),
+                (0x40, _) => {
+                    let pages = (self.memory.len() / PAGE_SIZE) as u32;
+                    let delta = pop::<i32>(&mut stack) as u32;
+                    if pages.checked_add(delta).is_some_and(|total| total <= self.max_pages) {
+                        self.memory.resize((pages + delta) as usize * PAGE_SIZE, 0);
+                        stack.push(Value::I32(pages as i32));
+                    } else {
+                        stack.push(Value::I32(-1));
+                    }
+                },
+                (0x41, Immediate::I32(value)) => stack.push(Value::I32(*value)),
+                (0x42, Immediate::I64(value)) => stack.push(Value::I64(*value)),
+                (0x43, Immediate::F32(value)) => stack.push(Value::F32(*value)),
+                (0x44, Immediate::F64(value)) => stack.push(Value::F64(*value)),
+                (opcode, _) => numeric(opcode, &mut stack)?
+            }
+        }
+
+        Ok(if results.is_empty() {None} else {stack.pop()})
+    }
+}
+
+fn numeric(opcode: u32, stack: &mut Vec<Value>) -> Result<(), Trap> {
+    let s = stack;
+    match opcode {
+        0x45 => unary(s, |a: i32| a == 0),
+        0x46 => binary(s, |a: i32, b| a == b),
+        0x47 => binary(s, |a: i32, b| a != b),
+        0x48 => binary(s, |a: i32, b| a < b),
+        0x49 => binary(s, |a: i32, b| (a as u32) < (b as u32)),
+        0x4A => binary(s, |a: i32, b| a > b),
+        0x4B => binary(s, |a: i32, b| (a as u32) > (b as u32)),
+        0x4C => binary(s, |a: i32, b| a <= b),
+        0x4D => binary(s, |a: i32, b| (a as u32) <= (b as u32)),
+        0x4E => binary(s, |a: i32, b| a >= b),
+        0x4F => binary(s, |a: i32, b| (a as u32) >= (b as u32)),
+        0x50 => unary(s, |a: i64| a == 0),
+        0x51 => binary(s, |a: i64, b| a == b),
+        0x52 => binary(s, |a: i64, b| a != b),
+        0x53 => binary(s, |a: i64, b| a < b),
+        0x54 => binary(s, |a: i64, b| (a as u64) < (b as u64)),
+        0x55 => binary(s, |a: i64, b| a > b),
+        0x56 => binary(s, |a: i64, b| (a as u64) > (b as u64)),
+        0x57 => binary(s, |a: i64, b| a <= b),
+        0x58 => binary(s, |a: i64, b| (a as u64) <= (b as u64)),
+        0x59 => binary(s, |a: i64, b| a >= b),
+        0x5A => binary(s, |a: i64, b| (a as u64) >= (b as u64)),
+        0x5B => binary(s, |a: f32, b| a == b),
+        0x5C => binary(s, |a: f32, b| a != b),
+        0x5D => binary(s, |a: f32, b| a < b),
+        0x5E => binary(s, |a: f32, b| a > b),
+        0x5F => binary(s, |a: f32, b| a <= b),
+        0x60 => binary(s, |a: f32, b| a >= b),
+        0x61 => binary(s, |a: f64, b| a == b),
+        0x62 => binary(s, |a: f64, b| a != b),
+        0x63 => binary(s, |a: f64, b| a < b),
+        0x64 => binary(s, |a: f64, b| a > b),
+        0x65 => binary(s, |a: f64, b| a <= b),
+        0x66 => binary(s, |a: f64, b| a >= b),
+
+        0x67 => unary(s, |a: i32| a.leading_zeros() as i32),
+        0x68 => unary(s, |a: i32| a.trailing_zeros() as i32),
+        0x69 => unary(s, |a: i32| a.count_ones() as i32),
+        0x6A => binary(s, |a: i32, b| a.wrapping_add(b)),
+        0x6B => binary(s, |a: i32, b| a.wrapping_sub(b)),
+        0x6C => binary(s, |a: i32, b| a.wrapping_mul(b)),
+        0x6D => try_binary(s, |a: i32, b| {
+            check_divisor(b)?;
+            a.checked_div(b).ok_or(Trap::Error("Integer overflow".to_string()))
+        })?,
+        0x6E => try_binary(s, |a: i32, b| {check_divisor(b)?; Ok(((a as u32) / (b as u32)) as i32)})?,
+        0x6F => try_binary(s, |a: i32, b| {check_divisor(b)?; Ok(a.wrapping_rem(b))})?,
+        0x70 => try_binary(s, |a: i32, b| {check_divisor(b)?; Ok(((a as u32) % (b as u32)) as i32)})?,
+        0x71 => binary(s, |a: i32, b| a & b),
+        0x72 => binary(s, |a: i32, b| a | b),
+        0x73 => binary(s, |a: i32, b| a ^ b),
+        0x74 => binary(s, |a: i32, b| a.wrapping_shl(b as u32)),
+        0x75 => binary(s, |a: i32, b| a.wrapping_shr(b as u32)),
+        0x76 => binary(s, |a: i32, b| (a as u32).wrapping_shr(b as u32) as i32),
+        0x77 => binary(s, |a: i32, b| a.rotate_left(b as u32 % 32)),
+        0x78 => binary(s, |a: i32, b| a.rotate_right(b as u32 % 32)),
+
+        0x79 => unary(s, |a: i64| a.leading_zeros() as i64),
+        0x7A => unary(s, |a: i64| a.trailing_zeros() as i64),
+        0x7B => unary(s, |a: i64| a.count_ones() as i64),
+        0x7C => binary(s, |a: i64, b| a.wrapping_add(b)),
+        0x7D => binary(s, |a: i64, b| a.wrapping_sub(b)),
+        0x7E => binary(s, |a: i64, b| a.wrapping_mul(b)),
+        0x7F => try_binary(s, |a: i64, b| {
+            check_divisor(b)?;
+            a.checked_div(b).ok_or(Trap::Error("Integer overflow".to_string()))
+        })?,
+        0x80 => try_binary(s, |a: i64, b| {check_divisor(b)?; Ok(((a as u64) / (b as u64)) as i64)})?,
+        0x81 => try_binary(s, |a: i64, b| {check_divisor(b)?; Ok(a.wrapping_rem(b))})?,
+        0x82 => try_binary(s, |a: i64, b| {check_divisor(b)?; Ok(((a as u64) % (b as u64)) as i64)})?,
+        0x83 => binary(s, |a: i64, b| a & b),
+        0x84 => binary(s, |a: i64, b| a | b),
+        0x85 => binary(s, |a: i64, b| a ^ b),
+        0x86 => binary(s, |a: i64, b| a.wrapping_shl(b as u32)),
+        0x87 => binary(s, |a: i64, b| a.wrapping_shr(b as u32)),
+        0x88 => binary(s, |a: i64, b| (a as u64).wrapping_shr(b as u32) as i64),
+        0x89 => binary(s, |a: i64, b| a.rotate_left((b as u64 % 64) as u32)),
+        0x8A => binary(s, |a: i64, b| a.rotate_right((b as u64 % 64) as u32)),
+
+        0x8B => unary(s, |a: f32| a.abs()),
+        0x8C => unary(s, |a: f32| -a),
+        0x8D => unary(s, |a: f32| a.ceil()),
+        0x8E => unary(s, |a: f32| a.floor()),
+        0x8F => unary(s, |a: f32| a.trunc()),
+        0x90 => unary(s, |a: f32| a.round_ties_even()),
+        0x91 => unary(s, |a: f32| a.sqrt()),
+        0x92 => binary(s, |a: f32, b| a + b),
+        0x93 => binary(s, |a: f32, b| a - b),
+        0x94 => binary(s, |a: f32, b| a * b),
+        0x95 => binary(s, |a: f32, b| a / b),
+        0x96 => binary(s, min_f32),
+        0x97 => binary(s, max_f32),
+        0x98 => binary(s, |a: f32, b| a.copysign(b)),
+
+        0x99 => unary(s, |a: f64| a.abs()),
+        0x9A => unary(s, |a: f64| -a),
+        0x9B => unary(s, |a: f64| a.ceil()),
+        0x9C => unary(s, |a: f64| a.floor()),
+        0x9D => unary(s, |a: f64| a.trunc()),
+        0x9E => unary(s, |a: f64| a.round_ties_even()),
+        0x9F => unary(s, |a: f64| a.sqrt()),
+        0xA0 => binary(s, |a: f64, b| a + b),
+        0xA1 => binary(s, |a: f64, b| a - b),
+        0xA2 => binary(s, |a: f64, b| a * b),
+        0xA3 => binary(s, |a: f64, b| a / b),
+        0xA4 => binary(s, min_f64),
+        0xA5 => binary(s, max_f64),
+        0xA6 => binary(s, |a: f64, b| a.copysign(b)),
+
+        0xA7 => unary(s, |a: i64| a as i32),
+        0xA8 => try_unary(s, |a: f32| Ok(truncate(a as f64, -2147483648.0, 2147483648.0)? as i32))?,
+        0xA9 => try_unary(s, |a: f32| Ok(truncate(a as f64, 0.0, 4294967296.0)? as u32 as i32))?,
+        0xAA => try_unary(s, |a: f64| Ok(truncate(a, -2147483648.0, 2147483648.0)? as i32))?,
+        0xAB => try_unary(s, |a: f64| Ok(truncate(a, 0.0, 4294967296.0)? as u32 as i32))?,
+        0xAC => unary(s, |a: i32| a as i64),
+        0xAD => unary(s, |a: i32| a as u32 as i64),
+        0xAE => try_unary(s, |a: f32| Ok(truncate(a as f64, -9223372036854775808.0, 9223372036854775808.0)? as i64))?,
+        0xAF => try_unary(s, |a: f32| Ok(truncate(a as f64, 0.0, 18446744073709551616.0)? as u64 as i64))?,
+        0xB0 => try_unary(s, |a: f64| Ok(truncate(a, -9223372036854775808.0, 9223372036854775808.0)? as i64))?,
+        0xB1 => try_unary(s, |a: f64| Ok(truncate(a, 0.0, 18446744073709551616.0)? as u64 as i64))?,
+        0xB2 => unary(s, |a: i32| a as f32),
+        0xB3 => unary(s, |a: i32| a as u32 as f32),
+        0xB4 => unary(s, |a: i64| a as f32),
+        0xB5 => unary(s, |a: i64| a as u64 as f32),
+        0xB6 => unary(s, |a: f64| a as f32),
+        0xB7 => unary(s, |a: i32| a as f64),
+        0xB8 => unary(s, |a: i32| a as u32 as f64),
+        0xB9 => unary(s, |a: i64| a as f64),
+        0xBA => unary(s, |a: i64| a as u64 as f64),
+        0xBB => unary(s, |a: f32| a as f64),
+        0xBC => unary(s, |a: f32| a.to_bits() as i32),
+        0xBD => unary(s, |a: f64| a.to_bits() as i64),
+        0xBE => unary(s, |a: i32| f32::from_bits(a as u32)),
+        0xBF => unary(s, |a: i64| f64::from_bits(a as u64)),
+        0xC0 => unary(s, |a: i32| a as i8 as i32),
+        0xC1 => unary(s, |a: i32| a as i16 as i32),
+        0xC2 => unary(s, |a: i64| a as i8 as i64),
+        0xC3 => unary(s, |a: i64| a as i16 as i64),
+        0xC4 => unary(s, |a: i64| a as i32 as i64),
+
+        // Rust's float to int casts saturate and turn NaN into 0, like the trunc_sat instructions
+        0xFC00 => unary(s, |a: f32| a as i32),
+        0xFC01 => unary(s, |a: f32| a as u32 as i32),
+        0xFC02 => unary(s, |a: f64| a as i32),
+        0xFC03 => unary(s, |a: f64| a as u32 as i32),
+        0xFC04 => unary(s, |a: f32| a as i64),
+        0xFC05 => unary(s, |a: f32| a as u64 as i64),
+        0xFC06 => unary(s, |a: f64| a as i64),
+        0xFC07 => unary(s, |a: f64| a as u64 as i64),
+        _ => return Err(Trap::Error(format!("Unsupported instruction 0x{:02X}", opcode)))
+    }
+    Ok(())
+}
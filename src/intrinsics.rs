@@ -0,0 +1,61 @@
+use crate::ir::{LoadKind, StoreKind};
+use crate::parser::PlatTypes;
+
+// Built-in functions that map directly onto WASM memory instructions.
+//
+// load_T(addr) / load_T(addr, offset)            -> T
+// store_T(addr, value) / store_T(addr, value, offset)
+// memory_size()                                  -> i32 (pages)
+// memory_grow(pages)                             -> i32 (previous size in pages or -1)
+// str_len(s)                                     -> i32 (length in bytes)
+// str_ptr(s)                                     -> i32 (address of the first byte)
+// len(v)                                         -> i32 (elements of an array or bytes of a str)
+// alloc(size, align)                             -> i32 (address of size bytes on the heap, align is a power of two)
+// free(ptr)                                      returns an allocation or a struct, array or enum value to the heap
+// print(v) / println(v)                          writes a str or number to stdout (wasi target only)
+//
+// `offset` has to be a constant and is encoded as the memarg offset immediate.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Intrinsic {
+    Load { op: LoadKind, kind: PlatTypes },
+    Store { op: StoreKind, kind: PlatTypes },
+    MemorySize,
+    MemoryGrow,
+    StrLen,
+    StrPtr,
+    Len,
+    Alloc,
+    Free,
+    Print { newline: bool }
+}
+
+// Every name that lookup knows, for editor completion
+pub const NAMES: [&str; 19] = [
+    "load_i32", "load_i64", "load_f32", "load_f64", "load_u8", "store_i32", "store_i64", "store_f32", "store_f64", "store_u8",
+    "memory_size", "memory_grow", "str_len", "str_ptr", "len", "alloc", "free", "print", "println"
+];
+
+pub fn lookup(name: &str) -> Option<Intrinsic> {
+    match name {
+        "load_i32"    => Some(Intrinsic::Load { op: LoadKind::I32, kind: PlatTypes::I32 }),
+        "load_i64"    => Some(Intrinsic::Load { op: LoadKind::I64, kind: PlatTypes::I64 }),
+        "load_f32"    => Some(Intrinsic::Load { op: LoadKind::F32, kind: PlatTypes::F32 }),
+        "load_f64"    => Some(Intrinsic::Load { op: LoadKind::F64, kind: PlatTypes::F64 }),
+        "load_u8"     => Some(Intrinsic::Load { op: LoadKind::I32U8, kind: PlatTypes::I32 }),
+        "store_i32"   => Some(Intrinsic::Store { op: StoreKind::I32, kind: PlatTypes::I32 }),
+        "store_i64"   => Some(Intrinsic::Store { op: StoreKind::I64, kind: PlatTypes::I64 }),
+        "store_f32"   => Some(Intrinsic::Store { op: StoreKind::F32, kind: PlatTypes::F32 }),
+        "store_f64"   => Some(Intrinsic::Store { op: StoreKind::F64, kind: PlatTypes::F64 }),
+        "store_u8"    => Some(Intrinsic::Store { op: StoreKind::I32U8, kind: PlatTypes::I32 }),
+        "memory_size" => Some(Intrinsic::MemorySize),
+        "memory_grow" => Some(Intrinsic::MemoryGrow),
+        "str_len"     => Some(Intrinsic::StrLen),
+        "str_ptr"     => Some(Intrinsic::StrPtr),
+        "len"         => Some(Intrinsic::Len),
+        "alloc"       => Some(Intrinsic::Alloc),
+        "free"        => Some(Intrinsic::Free),
+        "print"       => Some(Intrinsic::Print { newline: false }),
+        "println"     => Some(Intrinsic::Print { newline: true }),
+        _ => None
+    }
+}
@@ -0,0 +1,227 @@
+// Typed instruction IR between the AST and the WASM binary.
+// The compiler lowers functions into `Instr` trees and `encoder` serializes the finished module.
+
+use crate::lexer::Span;
+
+pub type FuncIdx = u32;
+pub type LocalIdx = u32;
+pub type TypeIdx = u32;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BlockType {
+    Empty,
+    Value(ValType)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MemArg {
+    pub align: u32,
+    pub offset: u32
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoadKind {
+    I32, I64, F32, F64,
+    I32U8, I32S8, I32U16, I32S16,
+    I64U8, I64S8, I64U16, I64S16, I64U32, I64S32
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StoreKind {
+    I32, I64, F32, F64,
+    I32U8, I32U16,
+    I64U8, I64U16, I64U32
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnOp {
+    Clz, Ctz, Popcnt,
+    Abs, Neg, Ceil, Floor, Trunc, Nearest, Sqrt
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinOp {
+    Add, Sub, Mul,
+    DivS, DivU, RemS, RemU,
+    And, Or, Xor, Shl, ShrS, ShrU, Rotl, Rotr,
+    Div, Min, Max, Copysign
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RelOp {
+    Eq, Ne,
+    LtS, LtU, GtS, GtU, LeS, LeU, GeS, GeU,
+    Lt, Gt, Le, Ge
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConvOp {
+    I32WrapI64,
+    I32TruncF32S, I32TruncF32U, I32TruncF64S, I32TruncF64U,
+    I64ExtendI32S, I64ExtendI32U,
+    I64TruncF32S, I64TruncF32U, I64TruncF64S, I64TruncF64U,
+    F32ConvertI32S, F32ConvertI32U, F32ConvertI64S, F32ConvertI64U, F32DemoteF64,
+    F64ConvertI32S, F64ConvertI32U, F64ConvertI64S, F64ConvertI64U, F64PromoteF32,
+    I32ReinterpretF32, I64ReinterpretF64, F32ReinterpretI32, F64ReinterpretI64,
+    I32TruncSatF32S, I32TruncSatF32U, I32TruncSatF64S, I32TruncSatF64U,
+    I64TruncSatF32S, I64TruncSatF32U, I64TruncSatF64S, I64TruncSatF64U
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instr {
+    Unreachable,
+    Nop,
+    Block(BlockType, Vec<Instr>),
+    Loop(BlockType, Vec<Instr>),
+    If(BlockType, Vec<Instr>, Vec<Instr>),
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(FuncIdx),
+    Drop,
+    Select,
+    LocalGet(LocalIdx),
+    LocalSet(LocalIdx),
+    LocalTee(LocalIdx),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    Load(LoadKind, MemArg),
+    Store(StoreKind, MemArg),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    F32Const(f32),
+    F64Const(f64),
+    Eqz(ValType),
+    Compare(ValType, RelOp),
+    Unary(ValType, UnOp),
+    Binary(ValType, BinOp),
+    Convert(ConvOp),
+    // Source position of the instructions that follow it, only used for source maps and not encoded
+    Location(Span)
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub type_index: TypeIdx
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    pub name: String,
+    pub type_index: TypeIdx,
+    pub locals: Vec<ValType>,
+    pub local_names: Vec<(LocalIdx, String)>,
+    pub body: Vec<Instr>
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Limits {
+    pub min: u32,
+    pub max: Option<u32>
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExportKind {
+    Func,
+    Memory,
+    Global
+}
+
+// A global initialized by a constant instruction
+#[derive(Debug, PartialEq, Clone)]
+pub struct Global {
+    pub name: String,
+    pub kind: ValType,
+    pub mutable: bool,
+    pub init: Instr
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    pub index: u32
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Data {
+    pub offset: u32,
+    pub bytes: Vec<u8>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Custom {
+    pub name: String,
+    pub bytes: Vec<u8>
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Module {
+    pub name: Option<String>,
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub memories: Vec<Limits>,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    pub data: Vec<Data>,
+    pub customs: Vec<Custom>
+}
+
+impl Module {
+    pub fn func_name(&self, index: FuncIdx) -> Option<&str> {
+        let n_imports = self.imports.len() as u32;
+        if index < n_imports {
+            Some(&self.imports[index as usize].name)
+        } else {
+            self.functions.get((index - n_imports) as usize).map(|func| func.name.as_str())
+        }
+    }
+}
+
+impl LoadKind {
+    pub fn natural_align(&self) -> u32 {
+        match self {
+            LoadKind::I32U8 | LoadKind::I32S8 | LoadKind::I64U8 | LoadKind::I64S8 => 0,
+            LoadKind::I32U16 | LoadKind::I32S16 | LoadKind::I64U16 | LoadKind::I64S16 => 1,
+            LoadKind::I32 | LoadKind::F32 | LoadKind::I64U32 | LoadKind::I64S32 => 2,
+            LoadKind::I64 | LoadKind::F64 => 3
+        }
+    }
+}
+
+impl StoreKind {
+    pub fn natural_align(&self) -> u32 {
+        match self {
+            StoreKind::I32U8 | StoreKind::I64U8 => 0,
+            StoreKind::I32U16 | StoreKind::I64U16 => 1,
+            StoreKind::I32 | StoreKind::F32 | StoreKind::I64U32 => 2,
+            StoreKind::I64 | StoreKind::F64 => 3
+        }
+    }
+}
+
+impl MemArg {
+    pub fn natural(align: u32) -> Self {
+        MemArg { align, offset: 0 }
+    }
+}
@@ -0,0 +1,312 @@
+use std::fmt::Write;
+
+// A small JSON reader and writer for the language server, the dumps and source maps. Objects keep their keys in order.
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn object<const N: usize>(entries: [(&str, Json); N]) -> Json {
+        Json::Object(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    // Missing keys and non-objects give null
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(name, _)| name == key).map_or(&NULL, |(_, value)| value),
+            _ => &NULL
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as u32),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(values) => values,
+            _ => &[]
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut reader = Reader { chars: text.chars().collect(), cursor: 0 };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        match reader.chars.get(reader.cursor) {
+            None => Ok(value),
+            Some(c) => Err(format!("Unexpected {:?} after the JSON value", c))
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        Json::String(value)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Json {
+        Json::Number(value.into())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json {
+        Json::Array(values)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+fn quote(out: &mut std::fmt::Formatter, value: &str) -> std::fmt::Result {
+    out.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            '\r' => out.write_str("\\r")?,
+            '\t' => out.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?
+        }
+    }
+    out.write_char('"')
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(value) => quote(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {write!(f, ",")?;}
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i != 0 {write!(f, ",")?;}
+                    quote(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    cursor: usize
+}
+
+impl Reader {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.cursor).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.cursor += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.chars.get(self.cursor).copied().ok_or("Unexpected end of JSON")?;
+        self.cursor += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        for expected in word.chars() {
+            let c = self.next()?;
+            if c != expected {
+                return Err(format!("Unexpected {:?} in JSON, expected {}", c, word));
+            }
+        }
+        Ok(())
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).map(|_| self.next()).collect::<Result<_, _>>()?;
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("Invalid escape \\u{}", digits))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(value),
+                '\\' => value.push(match self.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let mut code = self.hex()?;
+                        // Characters outside the BMP are escaped as surrogate pairs
+                        if (0xD800..0xDC00).contains(&code) && self.chars.get(self.cursor..self.cursor + 2) == Some(&['\\', 'u']) {
+                            self.cursor += 2;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (self.hex()? - 0xDC00);
+                        }
+                        char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                    },
+                    c => c
+                }),
+                c => value.push(c)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.cursor).copied().ok_or("Unexpected end of JSON")? {
+            'n' => self.expect("null").map(|_| Json::Null),
+            't' => self.expect("true").map(|_| Json::Bool(true)),
+            'f' => self.expect("false").map(|_| Json::Bool(false)),
+            '"' => {
+                self.cursor += 1;
+                self.string().map(Json::String)
+            },
+            '[' => {
+                self.cursor += 1;
+                let mut values = vec![];
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.get(self.cursor) == Some(&']') && values.is_empty() {
+                        self.cursor += 1;
+                        return Ok(Json::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        ']' => return Ok(Json::Array(values)),
+                        c => return Err(format!("Unexpected {:?} in a JSON array", c))
+                    }
+                }
+            },
+            '{' => {
+                self.cursor += 1;
+                let mut entries = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.next()? {
+                        '}' if entries.is_empty() => return Ok(Json::Object(entries)),
+                        '"' => {},
+                        c => return Err(format!("Unexpected {:?} in a JSON object", c))
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    entries.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        '}' => return Ok(Json::Object(entries)),
+                        c => return Err(format!("Unexpected {:?} in a JSON object", c))
+                    }
+                }
+            },
+            _ => {
+                let start = self.cursor;
+                while self.chars.get(self.cursor).is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                    self.cursor += 1;
+                }
+                let text: String = self.chars[start..self.cursor].iter().collect();
+                text.parse().map(Json::Number).map_err(|_| format!("Invalid JSON value {:?}", text))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_values() {
+        let text = r#"{"null":null,"bools":[true,false],"number":-1.5,"string":"a\"b\\c\nd","empty":{},"list":[]}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("number"), &Json::Number(-1.5));
+        assert_eq!(json.get("string").as_str(), Some("a\"b\\c\nd"));
+        assert_eq!(json.get("bools").as_array(), [Json::Bool(true), Json::Bool(false)]);
+        assert_eq!(json.to_string(), text);
+    }
+
+    #[test]
+    fn keeps_the_order_of_keys() {
+        let json = Json::object([("b", 1.into()), ("a", "x".into()), ("c", None::<u32>.into())]);
+        assert_eq!(json.to_string(), r#"{"b":1,"a":"x","c":null}"#);
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+    }
+
+    #[test]
+    fn reads_escapes_and_whitespace() {
+        let json = Json::parse(" [ \"\\u00e9\\t\" , \"\\ud83d\\ude00\" , 1e3 ] ").unwrap();
+        assert_eq!(json, Json::Array(vec!["é\t".into(), "😀".into(), Json::Number(1000.0)]));
+        assert_eq!(Json::String("\u{1}".to_string()).to_string(), r#""\u0001""#);
+    }
+
+    #[test]
+    fn missing_values_are_null() {
+        let json = Json::parse(r#"{"id":3,"text":"x"}"#).unwrap();
+        assert_eq!(json.get("missing"), &Json::Null);
+        assert_eq!(json.get("id").get("nested"), &Json::Null);
+        assert_eq!(json.get("id").as_u32(), Some(3));
+        assert_eq!(json.get("text").as_u32(), None);
+        assert_eq!(json.get("text").as_array(), []);
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert_eq!(Json::parse(""), Err("Unexpected end of JSON".to_string()));
+        assert_eq!(Json::parse("[1,]"), Err("Invalid JSON value \"\"".to_string()));
+        assert_eq!(Json::parse(r#"{"a" 1}"#), Err("Unexpected '1' in JSON, expected :".to_string()));
+        assert_eq!(Json::parse(r#"{"a":1"#), Err("Unexpected end of JSON".to_string()));
+        assert_eq!(Json::parse("nul"), Err("Unexpected end of JSON".to_string()));
+        assert_eq!(Json::parse("1 2"), Err("Unexpected '2' after the JSON value".to_string()));
+    }
+}
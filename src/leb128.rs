@@ -0,0 +1,129 @@
+pub fn encode_u32(mut value: u32) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub fn encode_i32(value: i32) -> Vec<u8> {
+    encode_i64(value as i64)
+}
+
+pub fn encode_i64(mut value: i64) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Decoders advance `pos` past the value and reject encodings that are too long or
+// set bits outside of the value's range
+pub fn decode_u32(bytes: &[u8], pos: &mut usize) -> Result<u32, String> {
+    let start = *pos;
+    let mut result: u64 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*pos).ok_or_else(|| format!("Unexpected end of LEB128 at offset {}", start))?;
+        *pos += 1;
+        result |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return u32::try_from(result).map_err(|_| format!("LEB128 u32 out of range at offset {}", start));
+        }
+    }
+    Err(format!("LEB128 u32 longer than 5 bytes at offset {}", start))
+}
+
+pub fn decode_i32(bytes: &[u8], pos: &mut usize) -> Result<i32, String> {
+    let start = *pos;
+    let value = decode_signed(bytes, pos, 5)?;
+    i32::try_from(value).map_err(|_| format!("LEB128 i32 out of range at offset {}", start))
+}
+
+pub fn decode_i64(bytes: &[u8], pos: &mut usize) -> Result<i64, String> {
+    decode_signed(bytes, pos, 10)
+}
+
+fn decode_signed(bytes: &[u8], pos: &mut usize, max_bytes: u32) -> Result<i64, String> {
+    let start = *pos;
+    let mut result: i128 = 0;
+    for index in 0..max_bytes {
+        let byte = *bytes.get(*pos).ok_or_else(|| format!("Unexpected end of LEB128 at offset {}", start))?;
+        *pos += 1;
+        result |= ((byte & 0x7F) as i128) << (index * 7);
+        if byte & 0x80 == 0 {
+            let bits = (index + 1) * 7;
+            if byte & 0x40 != 0 {
+                result -= 1 << bits;
+            }
+            return i64::try_from(result).map_err(|_| format!("LEB128 i64 out of range at offset {}", start));
+        }
+    }
+    Err(format!("Signed LEB128 longer than {} bytes at offset {}", max_bytes, start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode<T>(decode: fn(&[u8], &mut usize) -> Result<T, String>, bytes: &[u8]) -> Result<T, String> {
+        let mut pos = 0;
+        let value = decode(bytes, &mut pos)?;
+        assert_eq!(pos, bytes.len(), "{:?} wasn't read to the end", bytes);
+        Ok(value)
+    }
+
+    #[test]
+    fn encodes_known_values() {
+        assert_eq!(encode_u32(0), [0x00]);
+        assert_eq!(encode_u32(127), [0x7F]);
+        assert_eq!(encode_u32(128), [0x80, 0x01]);
+        assert_eq!(encode_u32(624485), [0xE5, 0x8E, 0x26]);
+        assert_eq!(encode_u32(u32::MAX), [0xFF, 0xFF, 0xFF, 0xFF, 0x0F]);
+        assert_eq!(encode_i32(-1), [0x7F]);
+        assert_eq!(encode_i32(63), [0x3F]);
+        assert_eq!(encode_i32(64), [0xC0, 0x00]);
+        assert_eq!(encode_i32(-64), [0x40]);
+        assert_eq!(encode_i32(-65), [0xBF, 0x7F]);
+        assert_eq!(encode_i64(-123456), [0xC0, 0xBB, 0x78]);
+    }
+
+    #[test]
+    fn round_trips_at_the_boundaries() {
+        for value in [0, 1, 127, 128, 16383, 16384, u32::MAX >> 1, u32::MAX] {
+            assert_eq!(decode(decode_u32, &encode_u32(value)), Ok(value));
+        }
+        for value in [0, -1, 63, 64, -64, -65, i32::MIN, i32::MAX] {
+            assert_eq!(decode(decode_i32, &encode_i32(value)), Ok(value));
+        }
+        for value in [0, -1, i32::MIN as i64 - 1, i64::MIN, i64::MAX] {
+            assert_eq!(decode(decode_i64, &encode_i64(value)), Ok(value));
+        }
+    }
+
+    #[test]
+    fn accepts_padded_encodings() {
+        assert_eq!(decode(decode_u32, &[0x80, 0x80, 0x00]), Ok(0));
+        assert_eq!(decode(decode_i32, &[0xFF, 0x7F]), Ok(-1));
+    }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        assert_eq!(decode(decode_u32, &[0x80, 0x80]), Err("Unexpected end of LEB128 at offset 0".to_string()));
+        assert_eq!(decode(decode_u32, &[0x80; 6]), Err("LEB128 u32 longer than 5 bytes at offset 0".to_string()));
+        assert_eq!(decode(decode_u32, &[0xFF, 0xFF, 0xFF, 0xFF, 0x1F]), Err("LEB128 u32 out of range at offset 0".to_string()));
+        assert_eq!(decode(decode_i32, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]), Err("LEB128 i32 out of range at offset 0".to_string()));
+        assert_eq!(decode(decode_i32, &[0x80; 6]), Err("Signed LEB128 longer than 5 bytes at offset 0".to_string()));
+        assert_eq!(decode(decode_i64, &[0x80; 11]), Err("Signed LEB128 longer than 10 bytes at offset 0".to_string()));
+    }
+}
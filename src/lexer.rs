@@ -1,6 +1,13 @@
+use crate::Diagnostic;
+
 pub struct Lexer {
-    pub file: String,
-    pub cursor: usize
+    pub cursor: usize,
+    // Start of the last token, errors are reported there
+    pub last: Span,
+    // Comments skipped so far, kept for the formatter
+    pub comments: Vec<Comment>,
+    chars: Vec<char>,
+    line_starts: Vec<usize>
 }
 
 #[derive(Debug)]
@@ -12,6 +19,7 @@ pub enum PlatTokenKinds {
     Keyword,
     Identifier,
     Literal,
+    StringLiteral,
 
     // Bracket Kinds
     OpenParen,
@@ -28,6 +36,7 @@ pub enum PlatTokenKinds {
     Slash,
 
     Assign,
+    FatArrow,
     Equals,
     Bang,
     BangEquals,
@@ -39,56 +48,85 @@ pub enum PlatTokenKinds {
     // Seperators
     Comma,
     Semicolon,
-    Colon
+    Colon,
+    DoubleColon,
+    Dot
+}
+
+// 1-based line and column of the first character of a token
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32
+}
+
+// A `//` comment running to the end of its line, `text` includes the slashes
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String
 }
 
 #[derive(Debug)]
 pub struct PlatToken {
     pub kind: PlatTokenKinds,
     pub value: String,
+    pub span: Span
 }
 
 impl Lexer {
     pub fn new(file: String) -> Lexer {
-        Lexer {file: file, cursor: 0}
+        let chars: Vec<char> = file.chars().collect();
+        let line_starts = [0].into_iter().chain(chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i + 1)).collect();
+        Lexer {cursor: 0, last: Span::default(), comments: vec![], chars, line_starts}
     }
 
     fn next_char(&mut self) -> char {
         self.cursor += 1;
-        self.file.chars().nth(self.cursor).unwrap_or_default()
+        self.chars.get(self.cursor).copied().unwrap_or_default()
     }
 
     fn peek_char(&mut self) -> char {
-        self.file.chars().nth(self.cursor + 1).unwrap_or_default()
+        self.chars.get(self.cursor + 1).copied().unwrap_or_default()
     }
 
     fn is_keyword(value: &str) -> bool {
-        match value {
-            "func"   => true,
-            "let"    => true,
-            "return" => true,
-            _ => false
-        }
+        matches!(value, "func" | "let" | "return" | "extern" | "struct" | "enum" | "match")
+    }
+
+    fn span(&self) -> Span {
+        let line = self.line_starts.partition_point(|start| *start <= self.cursor);
+        Span { line: line as u32, column: (self.cursor - self.line_starts[line - 1] + 1) as u32 }
+    }
+
+    pub fn peek(&mut self) -> Result<PlatToken, Diagnostic> {
+        let cursor = self.cursor;
+        let token = self.next();
+        self.cursor = cursor;
+        token
     }
 
-    pub fn next(&mut self) -> PlatToken {
+    pub fn next(&mut self) -> Result<PlatToken, Diagnostic> {
         let mut token = PlatToken{
             kind: PlatTokenKinds::None,
-            value: "".to_string()
+            value: "".to_string(),
+            span: self.span()
         };
+        self.last = token.span;
 
-        if self.cursor > self.file.len() - 1 {
-            return PlatToken {
+        if self.cursor >= self.chars.len() {
+            return Ok(PlatToken {
                 kind: PlatTokenKinds::EOF,
-                value: "".to_string()
-            } 
+                value: "".to_string(),
+                span: token.span
+            })
         }
 
-        let mut current = self.file.chars().nth(self.cursor).unwrap_or_default();
-        if current.is_alphabetic() {
+        let mut current = self.chars[self.cursor];
+        if current.is_alphabetic() || current == '_' {
             token.kind = PlatTokenKinds::Identifier;
             token.value = current.to_string();
-            while {current = self.next_char(); current.is_alphanumeric()} {
+            while {current = self.next_char(); current.is_alphanumeric() || current == '_'} {
                 token.value.push(current);
             }
             if Lexer::is_keyword(&token.value) {token.kind = PlatTokenKinds::Keyword};
@@ -99,20 +137,32 @@ impl Lexer {
             token.value = current.to_string();
             while {current = self.next_char(); current.is_numeric() || current == '.'} {
                 if current == '.' && !float {float = true;}
-                else if current == '.' && float {assert!(false, "Invalid Number Literal!");}
+                else if current == '.' && float {bail!(token.span, "Invalid Number Literal!");}
                 token.value.push(current);
             }
             self.cursor -= 1;
-            assert!(!self.peek_char().is_alphabetic(), "Invalid Number Literal!")
+            ensure!(!self.peek_char().is_alphabetic(), token.span, "Invalid Number Literal!")
         } else if current == '\'' {
             token.kind = PlatTokenKinds::Literal;
             token.value = self.next_char().to_string();
             
-            assert_eq!(self.next_char(), '\'', "Invalid End Of Character Notation!");
+            ensure!(self.next_char() == '\'', token.span, "Invalid End Of Character Notation!");
         } else if current == '"' {
-            token.kind = PlatTokenKinds::Literal;
+            token.kind = PlatTokenKinds::StringLiteral;
             token.value = String::new();
             while {current = self.next_char(); current != '"'} {
+                ensure!(self.cursor < self.chars.len(), token.span, "Unterminated String Literal!");
+                if current == '\\' {
+                    current = match self.next_char() {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        '\\' => '\\',
+                        '"' => '"',
+                        other => bail!(token.span, "Invalid Escape Sequence \"\\{}\"!", other)
+                    };
+                }
                 token.value.push(current)
             }
         } else if current == '(' {
@@ -142,16 +192,29 @@ impl Lexer {
         } else if current == '*' {
             token.kind = PlatTokenKinds::Asterisk;
             token.value = current.to_string();
+        } else if current == '/' && self.peek_char() == '/' {
+            let end = (self.cursor..self.chars.len()).find(|i| self.chars[*i] == '\n').unwrap_or(self.chars.len());
+            let text: String = self.chars[self.cursor..end].iter().collect();
+            // Peeking reads a comment again
+            if self.comments.last().is_none_or(|comment| (comment.span.line, comment.span.column) < (token.span.line, token.span.column)) {
+                self.comments.push(Comment { span: token.span, text: text.trim_end().to_string() });
+            }
+            self.cursor = end;
+            return self.next()
         } else if current == '/' {
             token.kind = PlatTokenKinds::Slash;
             token.value = current.to_string();
-        } else if current == '=' {  // = or ==
+        } else if current == '=' {  // =, == or =>
             token.kind = PlatTokenKinds::Assign;
             token.value = current.to_string();
             if self.peek_char() == '=' {
                 token.kind = PlatTokenKinds::Equals;
                 current = self.next_char();
                 token.value.push(current);
+            } else if self.peek_char() == '>' {
+                token.kind = PlatTokenKinds::FatArrow;
+                current = self.next_char();
+                token.value.push(current);
             }
         } else if current == '!' {  // ! or !=
             token.kind = PlatTokenKinds::Bang;
@@ -183,15 +246,26 @@ impl Lexer {
         } else if current == ';' {
             token.kind = PlatTokenKinds::Semicolon;
             token.value = current.to_string();
-        } else if current == ':' {
+        } else if current == ':' {  // : or ::
             token.kind = PlatTokenKinds::Colon;
             token.value = current.to_string();
+            if self.peek_char() == ':' {
+                token.kind = PlatTokenKinds::DoubleColon;
+                current = self.next_char();
+                token.value.push(current);
+            }
+        } else if current == '.' {
+            token.kind = PlatTokenKinds::Dot;
+            token.value = current.to_string();
         } else if current.is_whitespace() {
             self.cursor += 1;
             return self.next()
+        } else {
+            // Characters that aren't part of the language, the parser rejects them
+            token.value = current.to_string();
         }
         self.cursor += 1;
 
-        return token;
+        Ok(token)
     }
 }
\ No newline at end of file
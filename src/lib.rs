@@ -0,0 +1,175 @@
+#![allow(clippy::upper_case_acronyms)]
+#![allow(clippy::should_implement_trait)]
+
+// Errors in the program being compiled are returned as a Diagnostic at the span they were found
+// at, a panic is an internal compiler error
+macro_rules! bail {
+    ($span:expr, $($message:tt)+) => {
+        return Err($crate::Diagnostic::new(format!($($message)+), $span))
+    };
+}
+
+macro_rules! ensure {
+    ($condition:expr, $span:expr, $($message:tt)+) => {
+        if !$condition {
+            bail!($span, $($message)+)
+        }
+    };
+}
+
+pub mod lexer;
+pub mod parser;
+pub mod ir;
+pub mod wat;
+pub mod decoder;
+pub mod validator;
+pub mod interpreter;
+pub mod evaluator;
+pub mod repl;
+pub mod lsp;
+pub mod dump;
+mod compiler;
+mod formatter;
+mod intrinsics;
+mod json;
+mod leb128;
+mod runtime;
+mod encoder;
+mod optimizer;
+mod sourcemap;
+
+pub use compiler::{Memory, Target};
+pub use lexer::{PlatToken, PlatTokenKinds, Span};
+pub use parser::{PlatTypes, AST};
+pub use runtime::Allocator;
+
+// Embedding API. Compiling stops at the first error, which is returned as a diagnostic.
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    // Names the module and the source of source maps
+    pub file_name: Option<String>,
+    pub target: Target,
+    pub memory: Memory,
+    pub opt_level: u8,
+    pub validate: bool,
+    pub bounds_checks: bool,
+    pub allocator: Allocator,
+    pub names: bool,
+    pub source_map: bool,
+    // Where the module finds its source map, the source name with a .wasm.map extension by default
+    pub source_map_url: Option<String>
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            file_name: None,
+            target: Target::Wasm,
+            memory: Memory::default(),
+            opt_level: 0,
+            validate: cfg!(debug_assertions),
+            bounds_checks: true,
+            allocator: Allocator::Bump,
+            names: cfg!(debug_assertions),
+            source_map: false,
+            source_map_url: None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    // Start of the token or statement the error was found at
+    pub span: Option<Span>
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self { message: message.into(), span: Some(span) }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}:{}: {}", span.line, span.column, self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+pub struct Output {
+    pub wasm: Vec<u8>,
+    pub module: ir::Module,
+    pub source_map: Option<String>
+}
+
+// Tokens without the EOF token, and the comments between them
+fn lex(src: &str) -> Result<(Vec<PlatToken>, Vec<lexer::Comment>), Diagnostic> {
+    let mut lexer = lexer::Lexer::new(src.to_string());
+    let mut tokens = vec![];
+    while {tokens.push(lexer.next()?); tokens.last().unwrap().kind != PlatTokenKinds::EOF} {}
+    tokens.pop();
+    Ok((tokens, lexer.comments))
+}
+
+pub fn tokenize(src: &str) -> Result<Vec<PlatToken>, Diagnostic> {
+    lex(src).map(|(tokens, _)| tokens)
+}
+
+pub fn parse(src: &str) -> Result<Vec<AST>, Diagnostic> {
+    parser::Parser::new(lexer::Lexer::new(src.to_string())).parse(None)
+}
+
+// Source in the canonical layout, with its comments
+pub fn format(src: &str) -> Result<String, Diagnostic> {
+    let (tokens, comments) = lex(src)?;
+    let ast = parse(src)?;
+    Ok(formatter::format(&ast, &tokens, &comments, src))
+}
+
+// Types the compiler resolved for the expressions of `ast`. Compiling stops at the first error, the
+// expressions after it have no type.
+pub fn resolve_types(src: &str, ast: &[AST], options: &Options) -> dump::Types {
+    let mut compiler = new_compiler(src, &Options { validate: false, source_map: false, ..options.clone() });
+    compiler.types = Some(dump::Types::new());
+    let _ = compiler.compile(ast);
+    compiler.types.take().unwrap()
+}
+
+pub fn tokens_json(src: &str) -> Result<String, Diagnostic> {
+    lex(src).map(|(tokens, comments)| dump::tokens_json(&tokens, &comments))
+}
+
+pub fn ast_json(src: &str, options: &Options) -> Result<String, Diagnostic> {
+    let ast = parse(src)?;
+    Ok(dump::ast_json(&ast, &resolve_types(src, &ast, options)))
+}
+
+fn new_compiler(src: &str, options: &Options) -> compiler::Compiler {
+    let mut compiler = compiler::Compiler::new(src.to_string(), options.file_name.as_deref());
+    compiler.memory = options.memory.clone();
+    compiler.target = options.target;
+    compiler.validate = options.validate;
+    compiler.opt_level = options.opt_level;
+    compiler.bounds_checks = options.bounds_checks;
+    compiler.allocator = options.allocator;
+    compiler.names = options.names;
+    compiler.source_map = options.source_map;
+    compiler.source_map_url = options.source_map_url.clone();
+    compiler
+}
+
+pub fn compile(src: &str, options: &Options) -> Result<Output, Diagnostic> {
+    let ast = parse(src)?;
+    let mut compiler = new_compiler(src, options);
+    let wasm = compiler.compile(&ast)?;
+    Ok(Output { wasm, module: compiler.module().clone(), source_map: compiler.source_map_json().map(str::to_string) })
+}
+
+// The errors are a list so that reporting more than the first one doesn't change the signature
+pub fn compile_str(src: &str, options: &Options) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    compile(src, options).map(|output| output.wasm).map_err(|diagnostic| vec![diagnostic])
+}
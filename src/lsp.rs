@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::intrinsics;
+use crate::json::Json;
+use crate::lexer::{PlatToken, PlatTokenKinds, Span};
+use crate::parser::{EnumExpr, Expr, FuncExpr, MatchArm, Pattern, PlatTypes, StructExpr, AST};
+use crate::{Diagnostic, Options, Target};
+
+// Language server for editors, speaking the Language Server Protocol over stdin and stdout. Files
+// are checked like the wasi target without a _start function, so print and files without main
+// don't give errors. Hover, definitions, symbols and completion come from the last version of a
+// file that parsed, with the positions of its names taken from the tokens.
+
+const KEYWORDS: [&str; 7] = ["func", "extern", "struct", "enum", "let", "return", "match"];
+const TYPES: [&str; 6] = ["i32", "i64", "f32", "f64", "str", "void"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+// Line and column, both 1-based like `Span`
+type Pos = (u32, u32);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Function,
+    Struct,
+    Enum,
+    Field,
+    Variant,
+    Variable,
+    Parameter,
+    Binding
+}
+
+impl Kind {
+    fn symbol_kind(self) -> u32 {
+        match self {
+            Kind::Function => 12,
+            Kind::Struct => 23,
+            Kind::Enum => 10,
+            Kind::Field => 8,
+            Kind::Variant => 22,
+            Kind::Variable | Kind::Parameter | Kind::Binding => 13
+        }
+    }
+
+    fn completion_kind(self) -> u32 {
+        match self {
+            Kind::Function => 3,
+            Kind::Struct => 22,
+            Kind::Enum => 13,
+            Kind::Field => 5,
+            Kind::Variant => 20,
+            Kind::Variable | Kind::Parameter | Kind::Binding => 6
+        }
+    }
+
+    fn is_local(self) -> bool {
+        matches!(self, Kind::Variable | Kind::Parameter | Kind::Binding)
+    }
+}
+
+struct Symbol {
+    name: String,
+    kind: Kind,
+    // Shown on hover, in source syntax
+    detail: String,
+    // Type of variables and fields, to resolve field accesses
+    value: Option<PlatTypes>,
+    // Struct or enum of a field or variant
+    parent: Option<String>,
+    // Where the name is written
+    selection: (Pos, Pos),
+    // The whole definition
+    range: (Pos, Pos),
+    // Where a local can be used
+    scope: Option<(Pos, Pos)>
+}
+
+fn pos(span: Span) -> Pos {
+    (span.line, span.column)
+}
+
+// Position after the last character of a token
+fn end(token: &PlatToken) -> Pos {
+    (token.span.line, token.span.column + token.value.chars().count() as u32)
+}
+
+fn is_open(kind: &PlatTokenKinds) -> bool {
+    matches!(kind, PlatTokenKinds::OpenParen | PlatTokenKinds::OpenBracket | PlatTokenKinds::OpenCurly)
+}
+
+fn is_close(kind: &PlatTokenKinds) -> bool {
+    matches!(kind, PlatTokenKinds::CloseParen | PlatTokenKinds::CloseBracket | PlatTokenKinds::CloseCurly)
+}
+
+struct Analysis {
+    tokens: Vec<PlatToken>,
+    symbols: Vec<Symbol>
+}
+
+impl Analysis {
+    fn new(text: &str) -> Option<Analysis> {
+        let tokens = crate::tokenize(text).ok()?;
+        let ast = crate::parse(text).ok()?;
+        let mut analysis = Analysis { tokens, symbols: vec![] };
+        for node in ast.iter() {
+            match node {
+                AST::Func(func) => analysis.function(func, &ast),
+                AST::Struct(expr) => analysis.structure(expr),
+                AST::Enum(expr) => analysis.enumeration(expr),
+                _ => {}
+            }
+        }
+        Some(analysis)
+    }
+
+    // Token that starts at `span`
+    fn index(&self, span: Span) -> usize {
+        self.tokens.partition_point(|token| pos(token.span) < pos(span))
+    }
+
+    fn find(&self, from: usize, kind: PlatTokenKinds) -> usize {
+        (from..self.tokens.len()).find(|index| self.tokens[*index].kind == kind).unwrap_or(self.tokens.len() - 1)
+    }
+
+    // Bracket that closes the one at `open`
+    fn closing(&self, open: usize) -> usize {
+        let mut depth = 0;
+        for index in open..self.tokens.len() {
+            if is_open(&self.tokens[index].kind) {
+                depth += 1;
+            } else if is_close(&self.tokens[index].kind) {
+                depth -= 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+        }
+        self.tokens.len() - 1
+    }
+
+    // Names of the parameters, fields or variants in the brackets at `open`
+    fn members(&self, open: usize) -> Vec<usize> {
+        let mut depth = 0;
+        let mut members = vec![];
+        for index in open..self.closing(open) {
+            let token = &self.tokens[index];
+            if is_open(&token.kind) {
+                depth += 1;
+            } else if is_close(&token.kind) {
+                depth -= 1;
+            } else if depth == 1 && token.kind == PlatTokenKinds::Identifier && (self.tokens[index - 1].kind == PlatTokenKinds::Comma || is_open(&self.tokens[index - 1].kind)) {
+                members.push(index);
+            }
+        }
+        members
+    }
+
+    fn range(&self, first: usize, last: usize) -> (Pos, Pos) {
+        (pos(self.tokens[first].span), end(&self.tokens[last]))
+    }
+
+    fn symbol(&self, kind: Kind, token: usize, detail: String) -> Symbol {
+        let selection = self.range(token, token);
+        Symbol { name: self.tokens[token].value.clone(), kind, detail, value: None, parent: None, selection, range: selection, scope: None }
+    }
+
+    fn function(&mut self, func: &FuncExpr, ast: &[AST]) {
+        let start = self.index(func.span);
+        let name = (start..self.tokens.len()).find(|index| self.tokens[*index].value == func.name).unwrap();
+        let params = self.closing(name + 1);
+        let last = match func.module {
+            Some(_) => self.find(params, PlatTokenKinds::Semicolon),
+            None => self.closing(self.find(params, PlatTokenKinds::OpenCurly))
+        };
+        let range = self.range(start, last);
+
+        let args: Vec<String> = func.args.iter().map(|arg| format!("{}: {}", arg.name, arg.kind)).collect();
+        let mut detail = format!("func {}({}) {}", func.name, args.join(", "), func.ret_kind);
+        if let Some(module) = &func.module {
+            detail = format!("extern {:?} {}", module, detail);
+        }
+        self.symbols.push(Symbol { range, ..self.symbol(Kind::Function, name, detail) });
+
+        for (arg, token) in func.args.iter().zip(self.members(name + 1)) {
+            let detail = format!("{}: {}", arg.name, arg.kind);
+            self.symbols.push(Symbol { value: Some(arg.kind.clone()), scope: Some(range), ..self.symbol(Kind::Parameter, token, detail) });
+        }
+        for node in func.body.iter().flatten() {
+            self.statement(node, ast, range.1);
+        }
+    }
+
+    fn structure(&mut self, expr: &StructExpr) {
+        let start = self.index(expr.span);
+        let range = self.range(start, self.closing(start + 2));
+        let fields: Vec<String> = expr.fields.iter().map(|field| format!("\n    {}: {}", field.name, field.kind)).collect();
+        let detail = format!("struct {} {{{}\n}}", expr.name, fields.join(","));
+        self.symbols.push(Symbol { range, ..self.symbol(Kind::Struct, start + 1, detail) });
+
+        for (field, token) in expr.fields.iter().zip(self.members(start + 2)) {
+            let detail = format!("{}.{}: {}", expr.name, field.name, field.kind);
+            self.symbols.push(Symbol { value: Some(field.kind.clone()), parent: Some(expr.name.clone()), ..self.symbol(Kind::Field, token, detail) });
+        }
+    }
+
+    fn enumeration(&mut self, expr: &EnumExpr) {
+        let start = self.index(expr.span);
+        let range = self.range(start, self.closing(start + 2));
+        let variants: Vec<String> = expr.variants.iter().map(|variant| {
+            let fields: Vec<String> = variant.fields.iter().map(PlatTypes::to_string).collect();
+            if fields.is_empty() {variant.name.clone()} else {format!("{}({})", variant.name, fields.join(", "))}
+        }).collect();
+        let detail = format!("enum {} {{\n    {}\n}}", expr.name, variants.join(",\n    "));
+        self.symbols.push(Symbol { range, ..self.symbol(Kind::Enum, start + 1, detail) });
+
+        for (variant, token) in variants.iter().zip(self.members(start + 2)) {
+            let detail = format!("{}::{}", expr.name, variant);
+            self.symbols.push(Symbol { parent: Some(expr.name.clone()), ..self.symbol(Kind::Variant, token, detail) });
+        }
+    }
+
+    // `end` is the end of the function, where the variables go out of scope
+    fn statement(&mut self, node: &AST, ast: &[AST], end: Pos) {
+        match node {
+            AST::Def(def) => {
+                let token = self.index(def.span) + 1;
+                let scope = (pos(self.tokens[token].span), end);
+                let detail = format!("let {}: {}", def.name, def.kind);
+                self.symbols.push(Symbol { value: Some(def.kind.clone()), scope: Some(scope), ..self.symbol(Kind::Variable, token, detail) });
+                if let Some(value) = &def.value {
+                    self.expr(value, ast);
+                }
+            },
+            AST::Assign(expr) => {
+                self.expr(&expr.target, ast);
+                self.expr(&expr.value, ast);
+            },
+            AST::Ret(Some(expr), _) | AST::Expr(expr, _) => self.expr(expr, ast),
+            _ => {}
+        }
+    }
+
+    // Collects the bindings of match arms
+    fn expr(&mut self, expr: &Expr, ast: &[AST]) {
+        match expr {
+            Expr::Unary(expr) => self.expr(&expr.expr, ast),
+            Expr::Binary(expr) => {
+                self.expr(&expr.left, ast);
+                self.expr(&expr.right, ast);
+            },
+            Expr::Group(expr, _) => self.expr(expr, ast),
+            Expr::Call(expr) => expr.args.iter().for_each(|arg| self.expr(arg, ast)),
+            Expr::StructLit(expr) => expr.fields.iter().for_each(|(_, value)| self.expr(value, ast)),
+            Expr::Field(expr) => self.expr(&expr.expr, ast),
+            Expr::ArrayLit(expr) => expr.elements.iter().for_each(|element| self.expr(element, ast)),
+            Expr::Index(expr) => {
+                self.expr(&expr.expr, ast);
+                self.expr(&expr.index, ast);
+            },
+            Expr::Variant(expr) => expr.args.iter().for_each(|arg| self.expr(arg, ast)),
+            Expr::Match(expr) => {
+                self.expr(&expr.expr, ast);
+                for arm in expr.arms.iter() {
+                    self.arm(arm, ast);
+                    self.expr(&arm.body, ast);
+                }
+            },
+            Expr::Literal(_, _) | Expr::Str(_, _) => {}
+        }
+    }
+
+    fn arm(&mut self, arm: &MatchArm, ast: &[AST]) {
+        let Pattern::Variant { enum_name, variant, bindings } = &arm.pattern else {return};
+        if bindings.is_empty() {
+            return;
+        }
+        // The arm ends at the comma or brace after its body
+        let start = self.index(arm.span);
+        let mut last = self.find(start, PlatTokenKinds::FatArrow);
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(last + 1) {
+            if depth == 0 && (is_close(&token.kind) || token.kind == PlatTokenKinds::Comma) {
+                break;
+            }
+            depth += is_open(&token.kind) as i32 - is_close(&token.kind) as i32;
+            last += 1;
+        }
+        let scope = self.range(start, last);
+
+        let fields = ast.iter().find_map(|node| match node {
+            AST::Enum(expr) if expr.name == *enum_name => expr.variants.iter().find(|other| other.name == *variant),
+            _ => None
+        }).map_or(&[][..], |variant| &variant.fields);
+        let tokens = self.members(self.find(start, PlatTokenKinds::OpenParen));
+        for (index, (binding, token)) in bindings.iter().zip(tokens).enumerate() {
+            let value = fields.get(index).cloned();
+            let detail = value.as_ref().map_or(binding.clone(), |kind| format!("{}: {}", binding, kind));
+            self.symbols.push(Symbol { value, scope: Some(scope), ..self.symbol(Kind::Binding, token, detail) });
+        }
+    }
+
+    // The innermost local called `name` at `at`
+    fn local(&self, name: &str, at: Pos) -> Option<&Symbol> {
+        self.symbols.iter()
+            .filter(|symbol| symbol.kind.is_local() && symbol.name == name && symbol.scope.is_some_and(|(start, end)| start <= at && at <= end))
+            .max_by_key(|symbol| symbol.selection.0)
+    }
+
+    fn global(&self, name: &str, kinds: &[Kind]) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name && kinds.contains(&symbol.kind))
+    }
+
+    fn member(&self, parent: &str, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.parent.as_deref() == Some(parent) && symbol.name == name)
+    }
+
+    // Identifier at or just before `at`
+    fn token_at(&self, at: Pos) -> Option<usize> {
+        self.tokens.iter().position(|token| token.kind == PlatTokenKinds::Identifier && pos(token.span) <= at && at <= end(token))
+    }
+
+    // The definition an identifier refers to
+    fn resolve(&self, index: usize) -> Option<&Symbol> {
+        let token = &self.tokens[index];
+        let at = pos(token.span);
+        if let Some(symbol) = self.symbols.iter().find(|symbol| symbol.selection.0 == at) {
+            return Some(symbol);
+        }
+        match index.checked_sub(2).map(|receiver| (&self.tokens[receiver], &self.tokens[index - 1].kind)) {
+            Some((parent, PlatTokenKinds::DoubleColon)) => return self.member(&parent.value, &token.value),
+            Some((_, PlatTokenKinds::Dot)) => {
+                let Some(PlatTypes::Named(parent)) = &self.resolve(index - 2)?.value else {return None};
+                return self.member(parent, &token.value);
+            },
+            _ => {}
+        }
+        if self.tokens.get(index + 1).is_some_and(|next| next.kind == PlatTokenKinds::OpenParen) {
+            return self.global(&token.value, &[Kind::Function]);
+        }
+        self.local(&token.value, at).or_else(|| self.global(&token.value, &[Kind::Struct, Kind::Enum, Kind::Function]))
+    }
+}
+
+// Errors of a file
+fn check(text: &str) -> Option<Diagnostic> {
+    let ast = match crate::parse(text) {
+        Ok(ast) => ast,
+        Err(diagnostic) => return Some(diagnostic)
+    };
+    let options = Options { target: Target::Wasi, validate: false, ..Options::default() };
+    let mut compiler = crate::new_compiler(text, &options);
+    compiler.start = false;
+    compiler.compile(&ast).err()
+}
+
+fn line(text: &str, line: u32) -> &str {
+    text.split('\n').nth(line.saturating_sub(1) as usize).unwrap_or("")
+}
+
+// LSP positions are 0-based and count UTF-16 code units
+fn position(text: &str, (number, column): Pos) -> Json {
+    let character: usize = line(text, number).chars().take(column.saturating_sub(1) as usize).map(char::len_utf16).sum();
+    Json::object([("line", number.saturating_sub(1).into()), ("character", (character as u32).into())])
+}
+
+fn range(text: &str, (start, end): (Pos, Pos)) -> Json {
+    Json::object([("start", position(text, start)), ("end", position(text, end))])
+}
+
+fn from_position(text: &str, position: &Json) -> Pos {
+    let number = position.get("line").as_u32().unwrap_or(0) + 1;
+    let character = position.get("character").as_u32().unwrap_or(0) as usize;
+    let mut units = 0;
+    let column = line(text, number).chars().take_while(|c| {units += c.len_utf16(); units <= character}).count();
+    (number, column as u32 + 1)
+}
+
+// Diagnostics only have a start, they cover the word there
+fn word(text: &str, (number, column): Pos) -> (Pos, Pos) {
+    let length = line(text, number).chars().skip(column.saturating_sub(1) as usize).take_while(|c| c.is_alphanumeric() || *c == '_').count();
+    ((number, column), (number, column + length.max(1) as u32))
+}
+
+struct Document {
+    text: String,
+    // Analysis of the last version that parsed
+    analysis: Option<Analysis>,
+    // Whether that was the current version
+    current: bool
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>
+}
+
+fn message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        match header.trim_end().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => length = value.trim().parse().ok(),
+            None if header.trim_end().is_empty() && length.is_some() => break,
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length.unwrap()];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let text = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", text.len(), text)?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, id: Json, result: Result<Json, (i32, String)>) -> io::Result<()> {
+        let (key, value) = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => ("error", Json::object([("code", Json::Number(code.into())), ("message", message.into())]))
+        };
+        self.send(Json::object([("jsonrpc", "2.0".into()), ("id", id), (key, value)]))
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> io::Result<()> {
+        let analysis = Analysis::new(&text);
+        let document = self.documents.entry(uri.to_string()).or_insert(Document { text: String::new(), analysis: None, current: false });
+        document.current = analysis.is_some();
+        if analysis.is_some() {
+            document.analysis = analysis;
+        }
+        document.text = text;
+        self.publish(uri)
+    }
+
+    fn publish(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics = self.documents.get(uri).map_or(vec![], |document| check(&document.text).iter().map(|diagnostic| {
+            let span = diagnostic.span.map_or((1, 1), pos);
+            Json::object([
+                ("range", range(&document.text, word(&document.text, span))),
+                ("severity", 1.into()),
+                ("source", "platinum".into()),
+                ("message", diagnostic.message.clone().into())
+            ])
+        }).collect());
+        let params = Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]);
+        self.send(Json::object([("jsonrpc", "2.0".into()), ("method", "textDocument/publishDiagnostics".into()), ("params", params)]))
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+        match method {
+            "textDocument/didOpen" => self.update(&uri, params.get("textDocument").get("text").as_str().unwrap_or_default().to_string()),
+            // Documents are synced in full, the last change holds the whole text
+            "textDocument/didChange" => match params.get("contentChanges").as_array().last().and_then(|change| change.get("text").as_str()) {
+                Some(text) => self.update(&uri, text.to_string()),
+                None => Ok(())
+            },
+            "textDocument/didSave" => match params.get("text").as_str() {
+                Some(text) => self.update(&uri, text.to_string()),
+                None => self.publish(&uri)
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish(&uri)
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn request(&self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default();
+        let document = self.documents.get(uri);
+        let at = document.map(|document| from_position(&document.text, params.get("position")));
+        match method {
+            "initialize" => Ok(Json::object([
+                ("capabilities", Json::object([
+                    ("textDocumentSync", Json::object([("openClose", true.into()), ("change", 1.into()), ("save", Json::object([("includeText", true.into())]))])),
+                    ("hoverProvider", true.into()),
+                    ("definitionProvider", true.into()),
+                    ("documentSymbolProvider", true.into()),
+                    ("completionProvider", Json::object([("triggerCharacters", vec![".".into(), ":".into()].into())]))
+                ])),
+                ("serverInfo", Json::object([("name", "platinum".into()), ("version", env!("CARGO_PKG_VERSION").into())]))
+            ])),
+            "shutdown" => Ok(Json::Null),
+            "textDocument/hover" => Ok(document.and_then(|document| hover(document, at.unwrap())).into()),
+            "textDocument/definition" => Ok(document.and_then(|document| definition(document, uri, at.unwrap())).into()),
+            "textDocument/documentSymbol" => Ok(document.map(symbols).into()),
+            "textDocument/completion" => Ok(document.map(|document| completion(document, at.unwrap())).into()),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method)))
+        }
+    }
+}
+
+// The analysis of a document if it's up to date, positions of older versions can be off
+fn current(document: &Document) -> Option<&Analysis> {
+    document.analysis.as_ref().filter(|_| document.current)
+}
+
+fn hover(document: &Document, at: Pos) -> Option<Json> {
+    let analysis = current(document)?;
+    let index = analysis.token_at(at)?;
+    let symbol = analysis.resolve(index)?;
+    let contents = Json::object([("kind", "markdown".into()), ("value", format!("```platinum\n{}\n```", symbol.detail).into())]);
+    Some(Json::object([("contents", contents), ("range", range(&document.text, analysis.range(index, index)))]))
+}
+
+fn definition(document: &Document, uri: &str, at: Pos) -> Option<Json> {
+    let analysis = current(document)?;
+    let symbol = analysis.resolve(analysis.token_at(at)?)?;
+    Some(Json::object([("uri", uri.into()), ("range", range(&document.text, symbol.selection))]))
+}
+
+fn symbols(document: &Document) -> Json {
+    let Some(analysis) = current(document) else {return Json::Array(vec![])};
+    let symbol = |symbol: &Symbol, children: Vec<Json>| Json::object([
+        ("name", symbol.name.clone().into()),
+        ("detail", symbol.detail.lines().next().unwrap_or_default().into()),
+        ("kind", symbol.kind.symbol_kind().into()),
+        ("range", range(&document.text, symbol.range)),
+        ("selectionRange", range(&document.text, symbol.selection)),
+        ("children", children.into())
+    ]);
+    analysis.symbols.iter().filter(|parent| matches!(parent.kind, Kind::Function | Kind::Struct | Kind::Enum)).map(|parent| {
+        let children = analysis.symbols.iter()
+            .filter(|child| match parent.kind {
+                Kind::Function => child.kind.is_local() && child.scope.is_some_and(|(start, _)| parent.range.0 <= start && start <= parent.range.1),
+                _ => child.parent.as_ref() == Some(&parent.name)
+            })
+            .map(|child| symbol(child, vec![]))
+            .collect();
+        symbol(parent, children)
+    }).collect::<Vec<Json>>().into()
+}
+
+fn item(label: &str, kind: u32, detail: &str) -> Json {
+    Json::object([("label", label.into()), ("kind", kind.into()), ("detail", detail.into())])
+}
+
+// Fields after `.`, variants after `::` and otherwise everything that is in scope
+fn completion(document: &Document, at: Pos) -> Json {
+    let Some(analysis) = &document.analysis else {
+        return KEYWORDS.iter().map(|keyword| item(keyword, 14, "keyword")).collect::<Vec<Json>>().into();
+    };
+    let tokens = crate::tokenize(&document.text).unwrap_or_default();
+    let mut before = tokens.partition_point(|token| pos(token.span) < at);
+    if before > 0 && tokens[before - 1].kind == PlatTokenKinds::Identifier && end(&tokens[before - 1]) >= at {
+        before -= 1;
+    }
+    let trigger = before.checked_sub(2).map(|receiver| (&tokens[receiver], &tokens[receiver + 1].kind));
+    let members = |parent: &str| analysis.symbols.iter()
+        .filter(|symbol| symbol.parent.as_deref() == Some(parent))
+        .map(|symbol| item(&symbol.name, symbol.kind.completion_kind(), &symbol.detail))
+        .collect::<Vec<Json>>();
+    match trigger {
+        Some((parent, PlatTokenKinds::DoubleColon)) => return members(&parent.value).into(),
+        Some((receiver, PlatTokenKinds::Dot)) => return match analysis.local(&receiver.value, at).and_then(|symbol| symbol.value.as_ref()) {
+            Some(PlatTypes::Named(parent)) => members(parent).into(),
+            _ => Json::Array(vec![])
+        },
+        _ => {}
+    }
+
+    let mut items: Vec<Json> = KEYWORDS.iter().map(|keyword| item(keyword, 14, "keyword")).collect();
+    items.extend(TYPES.iter().map(|kind| item(kind, 14, "type")));
+    items.extend(intrinsics::NAMES.iter().map(|name| item(name, 3, "built-in")));
+    let mut names = vec![];
+    for symbol in analysis.symbols.iter().rev() {
+        let visible = match symbol.scope {
+            Some((start, end)) => start <= at && at <= end,
+            None => symbol.parent.is_none()
+        };
+        if visible && !names.contains(&&symbol.name) {
+            names.push(&symbol.name);
+            items.push(item(&symbol.name, symbol.kind.completion_kind(), symbol.detail.lines().next().unwrap_or_default()));
+        }
+    }
+    items.into()
+}
+
+// Serves requests until the client sends exit or closes the input
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<()> {
+    let mut server = Server { output, documents: HashMap::new() };
+    while let Some(text) = message(&mut input)? {
+        let message = match Json::parse(&text) {
+            Ok(message) => message,
+            Err(error) => {
+                server.respond(Json::Null, Err((PARSE_ERROR, error)))?;
+                continue;
+            }
+        };
+        let method = message.get("method").as_str().unwrap_or_default();
+        if method == "exit" {
+            break;
+        }
+        match message.get("id") {
+            Json::Null => server.notification(method, message.get("params"))?,
+            id => {
+                let result = server.request(method, message.get("params"));
+                server.respond(id.clone(), result)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,19 +1,443 @@
-#![allow(dead_code)]
-
 use std::env;
+use std::fs;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process;
 
-mod lexer;
-mod parser;
-mod compiler;
+use platinum::interpreter::{self, Instance, Trap, Value};
+use platinum::repl::{self, Session};
+use platinum::{Allocator, Diagnostic, Options, Target};
 
-fn main() {
-    let file_name = env::args().nth(1).unwrap();
-    let mut compiler = compiler::Compiler::new(env::current_dir().unwrap().join(file_name.clone()).to_str().unwrap());
+// Exit codes
+const EXIT_ERROR: i32 = 1;                                          // The program has errors
+const EXIT_USAGE: i32 = 2;                                          // Invalid command line
+const EXIT_IO: i32 = 3;                                             // Reading or writing a file failed
+const EXIT_TRAP: i32 = 4;                                           // The program trapped while running
+
+const USAGE: &str = "Usage: platinum [options] [command] [options] <file.plat>
+       platinum run [options] <file.plat> [--invoke <function>] [args...]
+       platinum repl [options] [file.plat]
+       platinum lsp
+       platinum fmt [--check] <file.plat>
+
+Commands:
+  build       Compile to WASM (the default command)
+  check       Report errors without writing any output
+  tokens      Print the tokens
+  ast         Print the AST
+  run         Compile and run the module
+  repl        Evaluate expressions and definitions interactively
+  lsp         Run a language server on stdin and stdout
+  fmt         Format the source
+
+Options:
+  -o, --output <path>       Output file, - for stdout
+  --emit <kind>             wasm (default), wat, ast, ast-json, tokens or tokens-json
+  -O0, -O1, -O2, -O         Optimization level
+  --target <target>         wasm (default) or wasi
+  -q, --quiet               Only print errors
+  -v, --verbose             Print what each stage produced
+  --validate                Validate the module before writing it
+  --names, --strip-names    Emit or strip the name section
+  --source-map              Write a source map next to the module
+  --no-bounds-checks        Leave out runtime array bounds checks
+  --allocator <kind>        bump (default) or free-list
+  --invoke <function>       Function to run, main (or _start for wasi) by default
+  --memory-pages <n>        Initial memory size in 64KiB pages
+  --memory-max <n>          Maximum memory size in 64KiB pages
+  --check                   Fail instead of formatting when the file isn't formatted
+  --memory-export <name>    Export the memory under another name
+  --no-memory-export        Don't export the memory
+  -h, --help                Print this help";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Command {
+    Build,
+    Check,
+    Tokens,
+    Ast,
+    Run,
+    Repl,
+    Lsp,
+    Fmt
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Emit {
+    Wasm,
+    Wat,
+    Ast,
+    AstJson,
+    Tokens,
+    TokensJson
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, PartialOrd)]
+enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose
+}
+
+struct Cli {
+    command: Command,
+    file_name: String,
+    output: Option<String>,
+    emit: Emit,
+    verbosity: Verbosity,
+    options: Options,
+    // Function and arguments for `run`
+    invoke: Option<String>,
+    args: Vec<String>,
+    // `fmt` only reports whether the file is formatted
+    check: bool
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} expects a value", flag))
+}
+
+fn number(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u32, String> {
+    let text = value(args, flag)?;
+    text.parse().map_err(|_| format!("{} expects a number of pages, got {}", flag, text))
+}
+
+fn parse_command(arg: &str) -> Option<Command> {
+    match arg {
+        "build" => Some(Command::Build),
+        "check" => Some(Command::Check),
+        "tokens" => Some(Command::Tokens),
+        "ast" => Some(Command::Ast),
+        "run" => Some(Command::Run),
+        "repl" => Some(Command::Repl),
+        "lsp" => Some(Command::Lsp),
+        "fmt" => Some(Command::Fmt),
+        _ => None
+    }
+}
+
+// The command is the first argument that isn't an option, options can come before and after it
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Cli, String> {
+    let mut cli = Cli { command: Command::Build, file_name: String::new(), output: None, emit: Emit::Wasm, verbosity: Verbosity::Normal, options: Options::default(), invoke: None, args: vec![], check: false };
+    let (mut file_name, mut positional) = (None, false);
+    while let Some(arg) = args.next() {
+        let first = !positional && (!arg.starts_with('-') || arg == "-");
+        positional |= first;
+        match arg.as_str() {
+            _ if first && parse_command(&arg).is_some() => cli.command = parse_command(&arg).unwrap(),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "-o" | "--output" => cli.output = Some(value(&mut args, &arg)?),
+            "--emit" => cli.emit = match value(&mut args, &arg)?.as_str() {
+                "wasm" => Emit::Wasm,
+                "wat" => Emit::Wat,
+                "ast" => Emit::Ast,
+                "ast-json" => Emit::AstJson,
+                "tokens" => Emit::Tokens,
+                "tokens-json" => Emit::TokensJson,
+                other => return Err(format!("Unknown --emit kind {}, expected wasm, wat, ast, ast-json, tokens or tokens-json", other))
+            },
+            "-O" | "-O2" => cli.options.opt_level = 2,
+            "-O1" => cli.options.opt_level = 1,
+            "-O0" => cli.options.opt_level = 0,
+            "--target" => cli.options.target = match value(&mut args, &arg)?.as_str() {
+                "wasm" => Target::Wasm,
+                "wasi" => Target::Wasi,
+                other => return Err(format!("Unknown target {}, expected wasm or wasi", other))
+            },
+            "-q" | "--quiet" => cli.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => cli.verbosity = Verbosity::Verbose,
+            "--validate" => cli.options.validate = true,
+            "--names" => cli.options.names = true,
+            "--strip-names" => cli.options.names = false,
+            "--source-map" => cli.options.source_map = true,
+            "--no-bounds-checks" => cli.options.bounds_checks = false,
+            "--allocator" => cli.options.allocator = match value(&mut args, &arg)?.as_str() {
+                "bump" => Allocator::Bump,
+                "free-list" => Allocator::FreeList,
+                other => return Err(format!("Unknown allocator {}, expected bump or free-list", other))
+            },
+            "--memory-pages" => cli.options.memory.initial = number(&mut args, &arg)?,
+            "--memory-max" => cli.options.memory.maximum = Some(number(&mut args, &arg)?),
+            "--memory-export" => cli.options.memory.export = Some(value(&mut args, &arg)?),
+            "--no-memory-export" => cli.options.memory.export = None,
+            "--invoke" => cli.invoke = Some(value(&mut args, &arg)?),
+            "--check" => cli.check = true,
+            _ if cli.command == Command::Run && file_name.is_some() && (!arg.starts_with('-') || arg.parse::<f64>().is_ok()) => cli.args.push(arg),
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("Unknown option {}", flag)),
+            _ if file_name.is_some() => return Err(format!("Unexpected argument {}, only one input file is supported", arg)),
+            _ => file_name = Some(arg)
+        }
+    }
+    // The repl can start without a file and the language server gets its files from the editor
+    if matches!(cli.command, Command::Repl | Command::Lsp) && file_name.is_none() {
+        return Ok(cli);
+    }
+    cli.file_name = file_name.ok_or("No input file provided")?;
+    cli.options.file_name = Some(cli.file_name.clone());
+    Ok(cli)
+}
+
+fn fail(cli: &Cli, diagnostic: Diagnostic) -> ! {
+    eprintln!("{}:{}", cli.file_name, diagnostic);
+    process::exit(EXIT_ERROR);
+}
+
+fn write(path: &str, bytes: &[u8]) {
+    if path == "-" {
+        std::io::stdout().write_all(bytes).unwrap();
+    } else if let Err(error) = fs::write(path, bytes) {
+        eprintln!("Unable to write {}: {}", path, error);
+        process::exit(EXIT_IO);
+    }
+}
+
+fn tokens(cli: &Cli, src: &str) -> String {
+    let tokens = platinum::tokenize(src).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+    tokens.iter().map(|token| format!("{}:{} {:?} {:?}\n", token.span.line, token.span.column, token.kind, token.value)).collect()
+}
+
+fn ast(cli: &Cli, src: &str) -> String {
+    let ast = platinum::parse(src).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+    platinum::dump::ast_sexpr(&ast)
+}
+
+// Rewrites the file in place unless it's already formatted, or writes to the output
+fn fmt(cli: &Cli, src: &str) {
+    let formatted = platinum::format(src).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+    if cli.check {
+        if formatted != src {
+            eprintln!("{}: not formatted", cli.file_name);
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+    match cli.output.as_deref() {
+        Some(path) => write(path, formatted.as_bytes()),
+        None if formatted != src => {
+            write(&cli.file_name, formatted.as_bytes());
+            if cli.verbosity > Verbosity::Quiet {
+                eprintln!("Formatted {}", cli.file_name);
+            }
+        },
+        None => {}
+    }
+}
+
+fn check(cli: &Cli, src: &str) {
+    let options = Options { validate: true, ..cli.options.clone() };
+    let output = platinum::compile(src, &options).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+    if cli.verbosity == Verbosity::Verbose {
+        summary(&output.module);
+    }
+    if cli.verbosity > Verbosity::Quiet {
+        eprintln!("{}: no errors", cli.file_name);
+    }
+}
+
+fn summary(module: &platinum::ir::Module) {
+    let data: usize = module.data.iter().map(|data| data.bytes.len()).sum();
+    eprintln!("{} types, {} imports, {} functions, {} globals, {} bytes of data", module.types.len(), module.imports.len(), module.functions.len(), module.globals.len(), data);
+}
+
+// Binary output goes next to the source by default, text output to stdout
+fn build(cli: &Cli, src: &str) {
+    let text = match cli.emit {
+        Emit::Ast => ast(cli, src),
+        Emit::AstJson => platinum::ast_json(src, &cli.options).unwrap_or_else(|diagnostic| fail(cli, diagnostic)) + "\n",
+        Emit::Tokens => tokens(cli, src),
+        Emit::TokensJson => platinum::tokens_json(src).unwrap_or_else(|diagnostic| fail(cli, diagnostic)) + "\n",
+        Emit::Wasm | Emit::Wat => {
+            let path = cli.output.clone().unwrap_or_else(|| Path::new(&cli.file_name).with_extension("wasm").to_string_lossy().into_owned());
+            // The map is written next to the module, so the module finds it by its file name
+            let source_map_url = Path::new(&path).file_name().filter(|_| path != "-").map(|name| format!("{}.map", name.to_string_lossy()));
+            let options = Options { source_map_url, ..cli.options.clone() };
+            let output = platinum::compile(src, &options).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+            if cli.verbosity == Verbosity::Verbose {
+                summary(&output.module);
+            }
+            if cli.emit == Emit::Wat {
+                platinum::wat::print(&output.module)
+            } else {
+                write(&path, &output.wasm);
+                if let Some(map) = output.source_map.filter(|_| path != "-") {
+                    write(&format!("{}.map", path), map.as_bytes());
+                }
+                if cli.verbosity > Verbosity::Quiet && path != "-" {
+                    eprintln!("Wrote {} ({} bytes)", path, output.wasm.len());
+                }
+                return;
+            }
+        }
+    };
+    write(cli.output.as_deref().unwrap_or("-"), text.as_bytes());
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("{}\n\n{}", error, USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+// Invalid arguments for the program being run, the usage text doesn't help with those
+fn invalid_run(cli: &Cli, error: &str) -> ! {
+    eprintln!("{}: {}", cli.file_name, error);
+    process::exit(EXIT_USAGE);
+}
+
+// Runs an exported function in the built-in interpreter and prints its result
+fn run(cli: &Cli, src: &str) {
+    let output = platinum::compile(src, &cli.options).unwrap_or_else(|diagnostic| fail(cli, diagnostic));
+    let stdio = |fd, bytes: &[u8]| if fd == 1 {
+        std::io::stdout().write_all(bytes).and_then(|_| std::io::stdout().flush())
+    } else {
+        std::io::stderr().write_all(bytes)
+    };
+    let mut instance = Instance::new(&output.wasm, interpreter::wasi(stdio)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", cli.file_name, error);
+        process::exit(EXIT_ERROR);
+    });
+
+    let name = cli.invoke.clone().unwrap_or_else(|| (if cli.options.target == Target::Wasi {"_start"} else {"main"}).to_string());
+    let index = instance.export(&name).unwrap_or_else(|| invalid_run(cli, &format!("There is no exported function {}", name)));
+    let params = &instance.func_type(index).params;
+    if params.len() != cli.args.len() {
+        invalid_run(cli, &format!("{} expects {} arguments, got {}", name, params.len(), cli.args.len()));
+    }
+    let args: Vec<Value> = params.iter().zip(cli.args.iter()).map(|(kind, text)| Value::parse(*kind, text)).collect::<Result<_, _>>()
+        .unwrap_or_else(|error| invalid_run(cli, &error));
+
+    match instance.invoke(&name, &args) {
+        Ok(Some(result)) => println!("{}", result),
+        Ok(None) => {},
+        Err(Trap::Exit(code)) => process::exit(code),
+        Err(Trap::Error(message)) => {
+            eprintln!("{}: trap: {}", cli.file_name, message);
+            process::exit(EXIT_TRAP);
+        }
+    }
+}
+
+fn read(cli: &Cli) -> String {
+    fs::read_to_string(&cli.file_name).unwrap_or_else(|error| {
+        eprintln!("Unable to read {}: {}", cli.file_name, error);
+        process::exit(EXIT_IO);
+    })
+}
 
-    let ast = compiler.parse();
-    for node in ast.iter() {
-        print!("{node}");
+const REPL_HELP: &str = "Enter expressions, let statements, assignments and definitions. An entry continues
+until its brackets are closed.
+
+  :type <expr>   Print the type of an expression
+  :ast <src>     Print the AST of an entry
+  :wat <func>    Print a function as WAT
+  :help          Print this help
+  :quit          Leave the repl";
+
+fn report(diagnostic: Diagnostic) {
+    eprintln!("error: {}", diagnostic);
+}
+
+fn command(session: &Session, line: &str) -> bool {
+    let (name, arg) = line.split_once(char::is_whitespace).map_or((line, ""), |(name, arg)| (name, arg.trim()));
+    let text = match name {
+        ":type" => session.type_of(arg).map(|kind| kind.to_string()),
+        ":ast" => platinum::parse(arg).map(|ast| platinum::dump::ast_sexpr(&ast).trim_end().to_string()),
+        ":wat" => session.wat(arg).map(|wat| wat.trim_end().to_string()),
+        ":help" => Ok(REPL_HELP.to_string()),
+        ":quit" | ":q" => return false,
+        _ => {
+            eprintln!("Unknown command {}, :help lists the commands", name);
+            return true;
+        }
+    };
+    match text {
+        Ok(text) => println!("{}", text),
+        Err(diagnostic) => report(diagnostic)
+    }
+    true
+}
+
+fn eval(session: &mut Session, src: &str) {
+    let reply = match session.eval(src) {
+        Ok(reply) => reply,
+        Err(diagnostic) => return report(diagnostic)
+    };
+    let mut stdout = std::io::stdout();
+    stdout.write_all(&reply.output).unwrap();
+    if reply.output.last().is_some_and(|byte| *byte != b'\n') {
+        writeln!(stdout).unwrap();
+    }
+    match reply.result {
+        Ok(Some(value)) => writeln!(stdout, "{}", value).unwrap(),
+        Ok(None) => {},
+        Err(trap) => eprintln!("trap: {}", trap)
     }
+}
 
-    compiler::Compiler::write_file(&file_name.replace(".plat", ".wasm"), compiler.compile(ast))
+// Reads entries from stdin, prompting only when it's a terminal. A file given on the command line
+// is loaded as the first entry.
+fn repl(cli: &Cli) {
+    let mut session = Session::new(&cli.options);
+    if !cli.file_name.is_empty() {
+        let src = read(cli);
+        if let Err(diagnostic) = session.eval(&src) {
+            fail(cli, diagnostic);
+        }
+    }
+
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut entry = String::new();
+    loop {
+        if interactive {
+            print!("{}", if entry.is_empty() {"> "} else {"... "});
+            std::io::stdout().flush().unwrap();
+        }
+        let mut line = String::new();
+        let done = stdin.lock().read_line(&mut line).map_or(true, |n| n == 0);
+        entry.push_str(&line);
+        if !done && !repl::is_complete(&entry) {
+            continue;
+        }
+
+        let src = std::mem::take(&mut entry);
+        if src.trim().starts_with(':') {
+            if !command(&session, src.trim()) {
+                break;
+            }
+        } else if !src.trim().is_empty() {
+            eval(&mut session, &src);
+        }
+        if done {
+            break;
+        }
+    }
+}
+
+fn main() {
+    let cli = parse_args(env::args().skip(1)).unwrap_or_else(|error| usage(&error));
+    match cli.command {
+        Command::Repl => return repl(&cli),
+        Command::Lsp => {
+            if let Err(error) = platinum::lsp::serve(std::io::stdin().lock(), std::io::stdout()) {
+                eprintln!("The language server failed: {}", error);
+                process::exit(EXIT_IO);
+            }
+            return;
+        },
+        _ => {}
+    }
+    let src = read(&cli);
+
+    match cli.command {
+        Command::Build => build(&cli, &src),
+        Command::Check => check(&cli, &src),
+        Command::Tokens => write(cli.output.as_deref().unwrap_or("-"), tokens(&cli, &src).as_bytes()),
+        Command::Ast => write(cli.output.as_deref().unwrap_or("-"), ast(&cli, &src).as_bytes()),
+        Command::Run => run(&cli, &src),
+        Command::Repl | Command::Lsp => unreachable!(),
+        Command::Fmt => fmt(&cli, &src)
+    }
 }
@@ -0,0 +1,526 @@
+use std::collections::HashSet;
+
+use crate::ir::{self, BinOp, ConvOp, Instr, RelOp, UnOp, ValType};
+
+// Optimization passes over the IR, selected by the -O level.
+//
+// -O1: constant folding, dead code after return/br/unreachable, unused locals and peephole rules
+// -O2: additionally reuses locals whose live ranges don't overlap
+pub fn optimize(module: &mut ir::Module, level: u8) {
+    if level == 0 {
+        return;
+    }
+    for func in module.functions.iter_mut() {
+        let n_params = module.types[func.type_index as usize].params.len() as u32;
+        fold_constants(&mut func.body);
+        remove_dead_code(&mut func.body);
+        // Removing a local can turn its neighbours into new peephole candidates
+        peephole(&mut func.body);
+        while remove_unused_locals(func, n_params) {
+            peephole(&mut func.body);
+        }
+        if level >= 2 {
+            coalesce_locals(func, n_params);
+            peephole(&mut func.body);
+        }
+    }
+}
+
+fn for_each_body(body: &mut [Instr], pass: fn(&mut Vec<Instr>)) {
+    for instr in body.iter_mut() {
+        match instr {
+            Instr::Block(_, inner) | Instr::Loop(_, inner) => pass(inner),
+            Instr::If(_, then, otherwise) => {pass(then); pass(otherwise)},
+            _ => {}
+        }
+    }
+}
+
+fn for_each_local(body: &mut [Instr], f: &mut impl FnMut(&mut u32)) {
+    for instr in body.iter_mut() {
+        match instr {
+            Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => f(index),
+            Instr::Block(_, inner) | Instr::Loop(_, inner) => for_each_local(inner, f),
+            Instr::If(_, then, otherwise) => {for_each_local(then, f); for_each_local(otherwise, f)},
+            _ => {}
+        }
+    }
+}
+
+// Constant folding
+
+fn fold_i32(a: i32, b: i32, op: BinOp) -> Option<i32> {
+    match op {
+        BinOp::Add => Some(a.wrapping_add(b)),
+        BinOp::Sub => Some(a.wrapping_sub(b)),
+        BinOp::Mul => Some(a.wrapping_mul(b)),
+        BinOp::DivS => a.checked_div(b),
+        BinOp::DivU => (a as u32).checked_div(b as u32).map(|value| value as i32),
+        BinOp::RemS => if b == 0 {None} else {Some(a.wrapping_rem(b))},
+        BinOp::RemU => (a as u32).checked_rem(b as u32).map(|value| value as i32),
+        BinOp::And => Some(a & b),
+        BinOp::Or => Some(a | b),
+        BinOp::Xor => Some(a ^ b),
+        BinOp::Shl => Some(a.wrapping_shl(b as u32)),
+        BinOp::ShrS => Some(a.wrapping_shr(b as u32)),
+        BinOp::ShrU => Some((a as u32).wrapping_shr(b as u32) as i32),
+        BinOp::Rotl => Some((a as u32).rotate_left(b as u32 % 32) as i32),
+        BinOp::Rotr => Some((a as u32).rotate_right(b as u32 % 32) as i32),
+        _ => None
+    }
+}
+
+fn fold_i64(a: i64, b: i64, op: BinOp) -> Option<i64> {
+    match op {
+        BinOp::Add => Some(a.wrapping_add(b)),
+        BinOp::Sub => Some(a.wrapping_sub(b)),
+        BinOp::Mul => Some(a.wrapping_mul(b)),
+        BinOp::DivS => a.checked_div(b),
+        BinOp::DivU => (a as u64).checked_div(b as u64).map(|value| value as i64),
+        BinOp::RemS => if b == 0 {None} else {Some(a.wrapping_rem(b))},
+        BinOp::RemU => (a as u64).checked_rem(b as u64).map(|value| value as i64),
+        BinOp::And => Some(a & b),
+        BinOp::Or => Some(a | b),
+        BinOp::Xor => Some(a ^ b),
+        BinOp::Shl => Some(a.wrapping_shl(b as u32)),
+        BinOp::ShrS => Some(a.wrapping_shr(b as u32)),
+        BinOp::ShrU => Some((a as u64).wrapping_shr(b as u32) as i64),
+        BinOp::Rotl => Some((a as u64).rotate_left((b as u64 % 64) as u32) as i64),
+        BinOp::Rotr => Some((a as u64).rotate_right((b as u64 % 64) as u32) as i64),
+        _ => None
+    }
+}
+
+// min and max are left alone, their nan and -0 handling differs from Rust's
+fn fold_f64(a: f64, b: f64, op: BinOp) -> Option<f64> {
+    match op {
+        BinOp::Add => Some(a + b),
+        BinOp::Sub => Some(a - b),
+        BinOp::Mul => Some(a * b),
+        BinOp::Div => Some(a / b),
+        BinOp::Copysign => Some(a.copysign(b)),
+        _ => None
+    }
+}
+
+fn fold_f32(a: f32, b: f32, op: BinOp) -> Option<f32> {
+    match op {
+        BinOp::Add => Some(a + b),
+        BinOp::Sub => Some(a - b),
+        BinOp::Mul => Some(a * b),
+        BinOp::Div => Some(a / b),
+        BinOp::Copysign => Some(a.copysign(b)),
+        _ => None
+    }
+}
+
+fn compare<T: PartialOrd>(a: T, b: T, op: RelOp) -> bool {
+    match op {
+        RelOp::Eq => a == b,
+        RelOp::Ne => a != b,
+        RelOp::LtS | RelOp::LtU | RelOp::Lt => a < b,
+        RelOp::GtS | RelOp::GtU | RelOp::Gt => a > b,
+        RelOp::LeS | RelOp::LeU | RelOp::Le => a <= b,
+        RelOp::GeS | RelOp::GeU | RelOp::Ge => a >= b
+    }
+}
+
+fn is_unsigned(op: RelOp) -> bool {
+    matches!(op, RelOp::LtU | RelOp::GtU | RelOp::LeU | RelOp::GeU)
+}
+
+fn fold_binary(a: &Instr, b: &Instr, op: &Instr) -> Option<Instr> {
+    match (a, b, op) {
+        (Instr::I32Const(a), Instr::I32Const(b), Instr::Binary(ValType::I32, op)) => fold_i32(*a, *b, *op).map(Instr::I32Const),
+        (Instr::I64Const(a), Instr::I64Const(b), Instr::Binary(ValType::I64, op)) => fold_i64(*a, *b, *op).map(Instr::I64Const),
+        (Instr::F32Const(a), Instr::F32Const(b), Instr::Binary(ValType::F32, op)) => fold_f32(*a, *b, *op).map(Instr::F32Const),
+        (Instr::F64Const(a), Instr::F64Const(b), Instr::Binary(ValType::F64, op)) => fold_f64(*a, *b, *op).map(Instr::F64Const),
+        (Instr::I32Const(a), Instr::I32Const(b), Instr::Compare(ValType::I32, op)) =>
+            Some(Instr::I32Const(if is_unsigned(*op) {compare(*a as u32, *b as u32, *op)} else {compare(*a, *b, *op)} as i32)),
+        (Instr::I64Const(a), Instr::I64Const(b), Instr::Compare(ValType::I64, op)) =>
+            Some(Instr::I32Const(if is_unsigned(*op) {compare(*a as u64, *b as u64, *op)} else {compare(*a, *b, *op)} as i32)),
+        (Instr::F32Const(a), Instr::F32Const(b), Instr::Compare(ValType::F32, op)) => Some(Instr::I32Const(compare(*a, *b, *op) as i32)),
+        (Instr::F64Const(a), Instr::F64Const(b), Instr::Compare(ValType::F64, op)) => Some(Instr::I32Const(compare(*a, *b, *op) as i32)),
+        _ => None
+    }
+}
+
+fn fold_unary(a: &Instr, op: &Instr) -> Option<Instr> {
+    match (a, op) {
+        (Instr::I32Const(a), Instr::Eqz(ValType::I32)) => Some(Instr::I32Const((*a == 0) as i32)),
+        (Instr::I64Const(a), Instr::Eqz(ValType::I64)) => Some(Instr::I32Const((*a == 0) as i32)),
+        (Instr::I32Const(a), Instr::Unary(ValType::I32, op)) => match op {
+            UnOp::Clz => Some(Instr::I32Const(a.leading_zeros() as i32)),
+            UnOp::Ctz => Some(Instr::I32Const(a.trailing_zeros() as i32)),
+            UnOp::Popcnt => Some(Instr::I32Const(a.count_ones() as i32)),
+            _ => None
+        },
+        (Instr::I64Const(a), Instr::Unary(ValType::I64, op)) => match op {
+            UnOp::Clz => Some(Instr::I64Const(a.leading_zeros() as i64)),
+            UnOp::Ctz => Some(Instr::I64Const(a.trailing_zeros() as i64)),
+            UnOp::Popcnt => Some(Instr::I64Const(a.count_ones() as i64)),
+            _ => None
+        },
+        (Instr::F32Const(a), Instr::Unary(ValType::F32, UnOp::Neg)) => Some(Instr::F32Const(-a)),
+        (Instr::F32Const(a), Instr::Unary(ValType::F32, UnOp::Abs)) => Some(Instr::F32Const(a.abs())),
+        (Instr::F64Const(a), Instr::Unary(ValType::F64, UnOp::Neg)) => Some(Instr::F64Const(-a)),
+        (Instr::F64Const(a), Instr::Unary(ValType::F64, UnOp::Abs)) => Some(Instr::F64Const(a.abs())),
+        (Instr::I32Const(a), Instr::Convert(ConvOp::I64ExtendI32S)) => Some(Instr::I64Const(*a as i64)),
+        (Instr::I32Const(a), Instr::Convert(ConvOp::I64ExtendI32U)) => Some(Instr::I64Const(*a as u32 as i64)),
+        (Instr::I64Const(a), Instr::Convert(ConvOp::I32WrapI64)) => Some(Instr::I32Const(*a as i32)),
+        (Instr::F32Const(a), Instr::Convert(ConvOp::F64PromoteF32)) => Some(Instr::F64Const(*a as f64)),
+        (Instr::I32Const(a), Instr::Convert(ConvOp::F64ConvertI32S)) => Some(Instr::F64Const(*a as f64)),
+        _ => None
+    }
+}
+
+// Operands of an instruction are the instructions right before it, so folding the tail of the
+// output after every push also folds nested constant sub-expressions
+fn fold_constants(body: &mut Vec<Instr>) {
+    for_each_body(body, fold_constants);
+    let mut out: Vec<Instr> = Vec::with_capacity(body.len());
+    for instr in body.drain(..) {
+        let code = last_code(&out, 2);
+        let folded = match code[..] {
+            [a, b] => fold_binary(&out[a], &out[b], &instr).or_else(|| fold_unary(&out[b], &instr)),
+            [b] => fold_unary(&out[b], &instr),
+            _ => None
+        };
+        match folded {
+            Some(value) => {
+                let operands = if matches!(instr, Instr::Binary(..) | Instr::Compare(..)) {2} else {1};
+                for position in code[code.len() - operands..].iter().rev() {
+                    out.remove(*position);
+                }
+                out.push(value);
+            },
+            None => out.push(instr)
+        }
+    }
+    *body = out;
+}
+
+// Dead code elimination
+
+fn remove_dead_code(body: &mut Vec<Instr>) {
+    for_each_body(body, remove_dead_code);
+    if let Some(end) = body.iter().position(|instr| matches!(instr, Instr::Return | Instr::Br(_) | Instr::BrTable(..) | Instr::Unreachable)) {
+        body.truncate(end + 1);
+    }
+}
+
+// Peephole rules
+
+fn is_pure(instr: &Instr) -> bool {
+    matches!(instr, Instr::LocalGet(_) | Instr::I32Const(_) | Instr::I64Const(_) | Instr::F32Const(_) | Instr::F64Const(_))
+}
+
+// Positions of the last `n` instructions of `out` that aren't source locations, oldest first.
+// Passes look past locations, so that they optimize the same with and without a source map.
+fn last_code(out: &[Instr], n: usize) -> Vec<usize> {
+    let mut positions: Vec<usize> = out.iter().enumerate().rev().filter(|(_, instr)| !matches!(instr, Instr::Location(_))).map(|(position, _)| position).take(n).collect();
+    positions.reverse();
+    positions
+}
+
+// local.set x; local.get x -> local.tee x
+// local.tee x; drop        -> local.set x
+// <const or local.get>; drop -> (nothing)
+fn peephole(body: &mut Vec<Instr>) {
+    for_each_body(body, peephole);
+    let mut out: Vec<Instr> = Vec::with_capacity(body.len());
+    for instr in body.drain(..) {
+        if let (Some(Instr::Location(_)), Instr::Location(_)) = (out.last(), &instr) {
+            out.pop();                                              // Only the last of consecutive locations is used
+        }
+        let last = last_code(&out, 1).pop();
+        match (last, &instr) {
+            (Some(position), Instr::LocalGet(get)) if out[position] == Instr::LocalSet(*get) => out[position] = Instr::LocalTee(*get),
+            (Some(position), Instr::Drop) => match out[position] {
+                Instr::LocalTee(index) => out[position] = Instr::LocalSet(index),
+                ref last if is_pure(last) => {out.remove(position);},
+                _ => out.push(instr)
+            },
+            _ => out.push(instr)
+        }
+    }
+    *body = out;
+}
+
+// Local removal and coalescing
+
+// Renumbers the declared locals, `mapping[i]` is the new slot of local `n_params + i` or None if it's removed
+fn renumber_locals(func: &mut ir::Function, n_params: u32, mapping: &[Option<u32>], locals: Vec<ValType>) {
+    for_each_local(&mut func.body, &mut |index| {
+        if *index >= n_params {
+            *index = n_params + mapping[(*index - n_params) as usize].expect("A removed local is still referenced");
+        }
+    });
+    let mut local_names = vec![];
+    let mut named = HashSet::new();
+    for (index, name) in func.local_names.drain(..) {
+        let index = if index < n_params {Some(index)} else {mapping[(index - n_params) as usize].map(|slot| n_params + slot)};
+        if let Some(index) = index.filter(|index| named.insert(*index)) {
+            local_names.push((index, name));
+        }
+    }
+    func.local_names = local_names;
+    func.locals = locals;
+}
+
+// Reorders the declared locals so locals of the same type are adjacent and share one run-length
+// entry in the code section. Types keep the order of their first local. Applied at every -O level
+pub fn group_locals(module: &mut ir::Module) {
+    for func in module.functions.iter_mut() {
+        let n_params = module.types[func.type_index as usize].params.len() as u32;
+        let mut kinds: Vec<ValType> = vec![];
+        for kind in func.locals.iter() {
+            if !kinds.contains(kind) {
+                kinds.push(*kind);
+            }
+        }
+        let mut order: Vec<usize> = (0..func.locals.len()).collect();
+        order.sort_by_key(|local| kinds.iter().position(|kind| *kind == func.locals[*local]));
+
+        let mut mapping = vec![None; func.locals.len()];
+        for (slot, local) in order.iter().enumerate() {
+            mapping[*local] = Some(slot as u32);
+        }
+        let locals = order.iter().map(|local| func.locals[*local]).collect();
+        renumber_locals(func, n_params, &mapping, locals);
+    }
+}
+
+// Locals that are never read are turned into drops, then locals that are never referenced are removed.
+// Returns whether any local was removed
+fn remove_unused_locals(func: &mut ir::Function, n_params: u32) -> bool {
+    let mut reads = vec![0; func.locals.len()];
+    fn count_reads(body: &[Instr], n_params: u32, reads: &mut Vec<u32>) {
+        for instr in body.iter() {
+            match instr {
+                Instr::LocalGet(index) if *index >= n_params => reads[(*index - n_params) as usize] += 1,
+                Instr::Block(_, inner) | Instr::Loop(_, inner) => count_reads(inner, n_params, reads),
+                Instr::If(_, then, otherwise) => {count_reads(then, n_params, reads); count_reads(otherwise, n_params, reads)},
+                _ => {}
+            }
+        }
+    }
+    count_reads(&func.body, n_params, &mut reads);
+
+    fn remove_writes(body: &mut Vec<Instr>, n_params: u32, reads: &[u32]) {
+        let unread = |index: u32| index >= n_params && reads[(index - n_params) as usize] == 0;
+        body.retain(|instr| !matches!(instr, Instr::LocalTee(index) if unread(*index)));
+        for instr in body.iter_mut() {
+            match instr {
+                Instr::LocalSet(index) if unread(*index) => *instr = Instr::Drop,
+                Instr::Block(_, inner) | Instr::Loop(_, inner) => remove_writes(inner, n_params, reads),
+                Instr::If(_, then, otherwise) => {remove_writes(then, n_params, reads); remove_writes(otherwise, n_params, reads)},
+                _ => {}
+            }
+        }
+    }
+    remove_writes(&mut func.body, n_params, &reads);
+
+    let mut mapping = vec![];
+    let mut locals = vec![];
+    for (local, kind) in func.locals.iter().enumerate() {
+        if reads[local] > 0 {
+            mapping.push(Some(locals.len() as u32));
+            locals.push(*kind);
+        } else {
+            mapping.push(None);
+        }
+    }
+    let removed = locals.len() < func.locals.len();
+    renumber_locals(func, n_params, &mapping, locals);
+    removed
+}
+
+// Positions of the first and last reference of a local in the linearized body
+#[derive(Clone, Copy)]
+struct LiveRange {
+    start: usize,
+    end: usize
+}
+
+struct Liveness {
+    n_params: u32,
+    position: usize,
+    depth: usize,
+    ranges: Vec<Option<LiveRange>>
+}
+
+impl Liveness {
+    fn reference(&mut self, index: u32, write: bool) {
+        if index < self.n_params {
+            return;
+        }
+        let position = self.position;
+        let range = &mut self.ranges[(index - self.n_params) as usize];
+        match range {
+            Some(range) => range.end = position,
+            // A local that isn't written unconditionally before it's read relies on its zero
+            // initialization, so it has to stay live from the start of the function
+            None => *range = Some(LiveRange { start: if write && self.depth == 0 {position} else {0}, end: position })
+        }
+    }
+
+    fn visit(&mut self, body: &[Instr]) {
+        for instr in body.iter() {
+            self.position += 1;
+            match instr {
+                Instr::LocalGet(index) => self.reference(*index, false),
+                Instr::LocalSet(index) | Instr::LocalTee(index) => self.reference(*index, true),
+                Instr::Block(_, inner) => self.visit_nested(inner),
+                Instr::If(_, then, otherwise) => {self.visit_nested(then); self.visit_nested(otherwise)},
+                // Values can flow around a loop, so anything referenced inside stays live for the whole loop
+                Instr::Loop(_, inner) => {
+                    let start = self.position;
+                    self.visit_nested(inner);
+                    let end = self.position;
+                    for range in self.ranges.iter_mut().flatten() {
+                        if range.start <= end && range.end >= start {
+                            range.start = range.start.min(start);
+                            range.end = range.end.max(end);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn visit_nested(&mut self, body: &[Instr]) {
+        self.depth += 1;
+        self.visit(body);
+        self.depth -= 1;
+        self.position += 1;
+    }
+}
+
+// Assigns locals of the same type whose live ranges don't overlap to the same slot
+fn coalesce_locals(func: &mut ir::Function, n_params: u32) {
+    let mut liveness = Liveness { n_params, position: 0, depth: 0, ranges: vec![None; func.locals.len()] };
+    liveness.visit(&func.body);
+
+    let mut order: Vec<usize> = (0..func.locals.len()).filter(|local| liveness.ranges[*local].is_some()).collect();
+    order.sort_by_key(|local| liveness.ranges[*local].unwrap().start);
+
+    let mut slots: Vec<(ValType, usize)> = vec![]; // Type and end of the last range in each slot
+    let mut mapping = vec![None; func.locals.len()];
+    for local in order {
+        let range = liveness.ranges[local].unwrap();
+        let kind = func.locals[local];
+        let slot = match slots.iter().position(|(slot_kind, end)| *slot_kind == kind && *end < range.start) {
+            Some(slot) => {slots[slot].1 = range.end; slot},
+            None => {slots.push((kind, range.end)); slots.len() - 1}
+        };
+        mapping[local] = Some(slot as u32);
+    }
+    let locals = slots.iter().map(|(kind, _)| *kind).collect();
+    renumber_locals(func, n_params, &mapping, locals);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::BlockType;
+    use Instr::*;
+
+    fn function(locals: Vec<ValType>, body: Vec<Instr>) -> ir::Function {
+        ir::Function { name: "f".to_string(), type_index: 0, locals, local_names: vec![], body }
+    }
+
+    fn coalesce(locals: Vec<ValType>, body: Vec<Instr>) -> ir::Function {
+        let mut func = function(locals, body);
+        coalesce_locals(&mut func, 0);
+        func
+    }
+
+    #[test]
+    fn folds_and_rewrites_across_locations() {
+        let at = |column| Location(crate::lexer::Span { line: 1, column });
+        let mut body = vec![at(1), I32Const(1), at(3), I32Const(2), at(1), Binary(ValType::I32, BinOp::Add), LocalSet(0), at(3), LocalGet(0), at(1), Drop];
+        fold_constants(&mut body);
+        assert_eq!(body, vec![at(1), at(3), at(1), I32Const(3), LocalSet(0), at(3), LocalGet(0), at(1), Drop]);
+        peephole(&mut body);
+        assert_eq!(body, vec![at(1), I32Const(3), LocalSet(0), at(1)]);
+    }
+
+    #[test]
+    fn reuses_locals_after_their_last_read() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            I32Const(1), LocalSet(0), LocalGet(0), Drop,
+            I32Const(2), LocalSet(1), LocalGet(1), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32]);
+        assert_eq!(func.body, vec![I32Const(1), LocalSet(0), LocalGet(0), Drop, I32Const(2), LocalSet(0), LocalGet(0), Drop]);
+    }
+
+    #[test]
+    fn keeps_overlapping_locals_and_types_apart() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            I32Const(1), LocalSet(0), I32Const(2), LocalSet(1), LocalGet(0), LocalGet(1), Binary(ValType::I32, BinOp::Add), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32, ValType::I32]);
+        let func = coalesce(vec![ValType::I32, ValType::I64], vec![
+            I32Const(1), LocalSet(0), LocalGet(0), Drop, I64Const(2), LocalSet(1), LocalGet(1), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32, ValType::I64]);
+    }
+
+    // A local that is only written inside an if can be read with its zero value, so it's live from
+    // the start and can't take the slot of a local that is dead by then
+    #[test]
+    fn conditional_writes_keep_a_local_live_from_the_start() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            I32Const(1), LocalSet(0), LocalGet(0), Drop,
+            I32Const(1), If(BlockType::Empty, vec![I32Const(2), LocalSet(1)], vec![]),
+            LocalGet(1), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32, ValType::I32]);
+    }
+
+    #[test]
+    fn locals_used_only_inside_an_if_are_dead_after_it() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            I32Const(1),
+            If(BlockType::Empty, vec![I32Const(2), LocalSet(0), LocalGet(0), Drop], vec![]),
+            I32Const(3), LocalSet(1), LocalGet(1), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32]);
+    }
+
+    // Local 0 is read at the start of every iteration, so local 1, which is written later in the
+    // loop, must not overwrite it
+    #[test]
+    fn locals_read_in_a_loop_stay_live_for_the_whole_loop() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            I32Const(10), LocalSet(0),
+            Loop(BlockType::Empty, vec![
+                LocalGet(0), Drop,
+                I32Const(1), LocalSet(1), LocalGet(1), BrIf(0)
+            ])
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32, ValType::I32]);
+        let Loop(_, inner) = &func.body[2] else {unreachable!()};
+        assert_ne!(func.body[1], inner[3]);
+    }
+
+    #[test]
+    fn locals_after_a_loop_reuse_its_slots() {
+        let func = coalesce(vec![ValType::I32, ValType::I32], vec![
+            Loop(BlockType::Empty, vec![I32Const(1), LocalSet(0), LocalGet(0), BrIf(0)]),
+            I32Const(2), LocalSet(1), LocalGet(1), Drop
+        ]);
+        assert_eq!(func.locals, vec![ValType::I32]);
+    }
+
+    #[test]
+    fn unread_locals_are_removed() {
+        let mut func = function(vec![ValType::I64, ValType::I32], vec![I64Const(1), LocalSet(0), I32Const(2), LocalTee(1), Drop, LocalGet(1), Drop]);
+        assert!(remove_unused_locals(&mut func, 0));
+        assert_eq!(func.locals, vec![ValType::I32]);
+        assert_eq!(func.body, vec![I64Const(1), Drop, I32Const(2), LocalTee(0), Drop, LocalGet(0), Drop]);
+    }
+}
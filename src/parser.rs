@@ -1,23 +1,46 @@
+use crate::formatter;
 use crate::lexer::{self, PlatTokenKinds};
+use crate::Diagnostic;
 
 pub struct Parser {
-    pub lexer: lexer::Lexer
+    pub lexer: lexer::Lexer,
+    // Set while parsing a match scrutinee, where `x {` starts the arms instead of a struct literal
+    no_struct_literals: bool
 }
 
 #[derive(Debug)]
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone)]
 pub enum PlatTypes {
     Void,
     I32,
     I64,
     F32,
-    F64
+    F64,
+    Str,
+    Named(String),
+    Array(Box<PlatTypes>, u32)
+}
+
+// Types are printed the way they are written in source
+impl std::fmt::Display for PlatTypes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlatTypes::Void => write!(f, "void"),
+            PlatTypes::I32 => write!(f, "i32"),
+            PlatTypes::I64 => write!(f, "i64"),
+            PlatTypes::F32 => write!(f, "f32"),
+            PlatTypes::F64 => write!(f, "f64"),
+            PlatTypes::Str => write!(f, "str"),
+            PlatTypes::Named(name) => write!(f, "{}", name),
+            PlatTypes::Array(kind, count) => write!(f, "[{}; {}]", kind, count)
+        }
+    }
 }
 
 // Function Structs
 #[derive(Debug)]
 pub struct FuncExprArg {
-    name: String,
+    pub name: String,
     pub kind: PlatTypes
 }
 
@@ -26,54 +49,147 @@ pub struct FuncExpr {
     pub name: String,
     pub args: Vec<FuncExprArg>,
     pub ret_kind: PlatTypes,
-    pub body: Box<Option<Vec<AST>>>
+    pub body: Box<Option<Vec<AST>>>,
+    pub module: Option<String>,
+    pub span: lexer::Span
 }
 
 impl std::fmt::Display for FuncExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Function Definition:\n  - Name: {}\n  - Args:", self.name).expect("ERROR");
-        for arg in self.args.iter() {
-            writeln!(f, "\t- Function Argument: {} ({:?})", arg.name, arg.kind).expect("ERROR");
-        }
-        writeln!(f, "  - Ret-Type: {:?}\n  - Body:", self.ret_kind).expect("ERROR");
-        for node in self.body.as_ref().as_ref().unwrap().iter() {
-            write!(f, "\t{}", node).expect("ERROR");
-        }
-
-        Ok(())
+        write!(f, "{}", formatter::print(|printer| printer.func(self)))
     }
 }
 
 // Structs
 #[derive(Debug)]
 pub struct BinaryExpr{
-    left: Expr,
-    operator: lexer::PlatToken,
-    right: Expr
+    pub left: Expr,
+    pub operator: lexer::PlatToken,
+    pub right: Expr
 }
 
 #[derive(Debug)]
 pub struct UnaryExpr {
-    operator: lexer::PlatToken,
-    expr: Expr
+    pub operator: lexer::PlatToken,
+    pub expr: Expr
+}
+
+#[derive(Debug)]
+pub struct CallExpr {
+    pub name: String,
+    pub args: Vec<Expr>,
+    pub span: lexer::Span
+}
+
+#[derive(Debug)]
+pub struct StructLitExpr {
+    pub name: String,
+    pub fields: Vec<(String, Expr)>,
+    pub span: lexer::Span
+}
+
+#[derive(Debug)]
+pub struct FieldExpr {
+    pub expr: Expr,
+    pub field: String,
+    pub span: lexer::Span
+}
+
+#[derive(Debug)]
+pub struct IndexExpr {
+    pub expr: Expr,
+    pub index: Expr,
+    pub span: lexer::Span
+}
+
+// `[a, b, c]`, or `[value; count]` when `count` is set
+#[derive(Debug)]
+pub struct ArrayLitExpr {
+    pub elements: Vec<Expr>,
+    pub count: Option<u32>,
+    pub span: lexer::Span
+}
+
+// `Enum::Variant(args)`, or `Enum::Variant` for variants without a payload
+#[derive(Debug)]
+pub struct VariantExpr {
+    pub enum_name: String,
+    pub variant: String,
+    pub args: Vec<Expr>,
+    pub span: lexer::Span
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+    Wildcard,
+    Literal(String),
+    Variant { enum_name: String, variant: String, bindings: Vec<String> }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Literal(value) => write!(f, "{}", value),
+            Pattern::Variant { enum_name, variant, bindings } if bindings.is_empty() => write!(f, "{}::{}", enum_name, variant),
+            Pattern::Variant { enum_name, variant, bindings } => write!(f, "{}::{}({})", enum_name, variant, bindings.join(", "))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expr,
+    pub span: lexer::Span
+}
+
+#[derive(Debug)]
+pub struct MatchExpr {
+    pub expr: Expr,
+    pub arms: Vec<MatchArm>,
+    pub span: lexer::Span
 }
 
 #[derive(Debug)]
 pub enum Expr {
     Unary(Box<UnaryExpr>),
     Binary(Box<BinaryExpr>),
-    Literal(String),
-    Group(Box<Expr>)
+    Literal(String, lexer::Span),
+    Str(String, lexer::Span),
+    // Parenthesized expression, spans its `(`
+    Group(Box<Expr>, lexer::Span),
+    Call(Box<CallExpr>),
+    StructLit(Box<StructLitExpr>),
+    Field(Box<FieldExpr>),
+    ArrayLit(Box<ArrayLitExpr>),
+    Index(Box<IndexExpr>),
+    Variant(Box<VariantExpr>),
+    Match(Box<MatchExpr>)
+}
+
+impl Expr {
+    // Where the expression starts, or its operator for unary and binary ones, no two expressions
+    // share it
+    pub fn span(&self) -> lexer::Span {
+        match self {
+            Expr::Unary(expr) => expr.operator.span,
+            Expr::Binary(expr) => expr.operator.span,
+            Expr::Literal(_, span) | Expr::Str(_, span) | Expr::Group(_, span) => *span,
+            Expr::Call(expr) => expr.span,
+            Expr::StructLit(expr) => expr.span,
+            Expr::Field(expr) => expr.span,
+            Expr::ArrayLit(expr) => expr.span,
+            Expr::Index(expr) => expr.span,
+            Expr::Variant(expr) => expr.span,
+            Expr::Match(expr) => expr.span
+        }
+    }
 }
 
 impl std::fmt::Display for Expr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Expr::Unary(expr) => write!(f, "Unary Expr: {:?}{}", expr.operator.kind, expr.expr),
-            Expr::Binary(expr) => write!(f, "Binary Expr: {}{:?}{}", expr.left, expr.operator.kind, expr.right),
-            Expr::Literal(expr) => write!(f, "Literal Expr: {}", expr),
-            Expr::Group(expr) => write!(f, "Group Expr: {}", expr),
-        }    
+        write!(f, "{}", formatter::print(|printer| printer.expr(self)))
     }
 }
 
@@ -81,12 +197,64 @@ impl std::fmt::Display for Expr {
 pub struct DefExpr {
     pub name: String,
     pub kind: PlatTypes,
-    pub value: Option<Expr>
+    pub value: Option<Expr>,
+    pub span: lexer::Span
 }
 
 impl std::fmt::Display for DefExpr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Definition Expression:\n\tName: {}\n\tType: {:?}\n\tValue: {}", self.name, self.kind, self.value.as_ref().unwrap())
+        write!(f, "{}", formatter::print(|printer| printer.def(self)))
+    }
+}
+
+#[derive(Debug)]
+pub struct AssignExpr {
+    pub target: Expr,
+    pub value: Expr,
+    pub span: lexer::Span
+}
+
+impl std::fmt::Display for AssignExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", formatter::print(|printer| printer.assign(self)))
+    }
+}
+
+#[derive(Debug)]
+pub struct StructField {
+    pub name: String,
+    pub kind: PlatTypes
+}
+
+#[derive(Debug)]
+pub struct StructExpr {
+    pub name: String,
+    pub fields: Vec<StructField>,
+    pub span: lexer::Span
+}
+
+impl std::fmt::Display for StructExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", formatter::print(|printer| printer.structure(self)))
+    }
+}
+
+#[derive(Debug)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<PlatTypes>
+}
+
+#[derive(Debug)]
+pub struct EnumExpr {
+    pub name: String,
+    pub variants: Vec<EnumVariant>,
+    pub span: lexer::Span
+}
+
+impl std::fmt::Display for EnumExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", formatter::print(|printer| printer.enumeration(self)))
     }
 }
 
@@ -94,118 +262,473 @@ impl std::fmt::Display for DefExpr {
 #[derive(Debug)]
 pub enum AST {
     Func(FuncExpr),
+    Struct(StructExpr),
+    Enum(EnumExpr),
     Def(DefExpr),
-    Ret(Option<Expr>)
+    Assign(AssignExpr),
+    Ret(Option<Expr>, lexer::Span),
+    Expr(Expr, lexer::Span)
 }
 
-impl std::fmt::Display for AST {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl AST {
+    pub fn span(&self) -> lexer::Span {
         match self {
-            AST::Func(expr) => writeln!(f, "AST Function Node:\n{}", expr),
-            AST::Def(expr) => writeln!(f, "- AST Definition Node:\n\t{}\n", expr),
-            AST::Ret(expr) => writeln!(f, "- AST Return Node:\n\t{}\n", expr.as_ref().unwrap()),
+            AST::Func(expr) => expr.span,
+            AST::Struct(expr) => expr.span,
+            AST::Enum(expr) => expr.span,
+            AST::Def(expr) => expr.span,
+            AST::Assign(expr) => expr.span,
+            AST::Ret(_, span) | AST::Expr(_, span) => *span
         }
     }
 }
 
+impl std::fmt::Display for AST {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", formatter::print(|printer| printer.node(self)))
+    }
+}
+
 impl Parser {
     pub fn new(lexer: lexer::Lexer) -> Self {
-        Parser{lexer}
+        Parser{lexer, no_struct_literals: false}
     }
 
-    fn expect_next_kind(&mut self, kind: lexer::PlatTokenKinds) -> lexer::PlatToken {
-        let token = self.lexer.next();
-        assert_eq!(kind, token.kind, "Unexpected Token!\n{:?}", token);
-        return token;
+    fn unexpected(token: &lexer::PlatToken) -> Diagnostic {
+        match token.kind {
+            PlatTokenKinds::EOF => Diagnostic::new("Unexpected End Of File!", token.span),
+            _ => Diagnostic::new(format!("Unexpected Token \"{}\"!", token.value), token.span)
+        }
     }
 
-    fn parse_type(&mut self) -> PlatTypes {
-        let token = self.expect_next_kind(lexer::PlatTokenKinds::Identifier);
-        match token.value.as_str() {
+    fn expect_next_kind(&mut self, kind: lexer::PlatTokenKinds) -> Result<lexer::PlatToken, Diagnostic> {
+        let token = self.lexer.next()?;
+        if token.kind != kind {
+            return Err(Parser::unexpected(&token));
+        }
+        Ok(token)
+    }
+
+    fn parse_count(&mut self) -> Result<u32, Diagnostic> {
+        let token = self.expect_next_kind(lexer::PlatTokenKinds::Literal)?;
+        token.value.parse().map_err(|_| Diagnostic::new(format!("Invalid Array Length {}!", token.value), token.span))
+    }
+
+    fn parse_type(&mut self) -> Result<PlatTypes, Diagnostic> {
+        let token = self.lexer.next()?;
+        if token.kind == PlatTokenKinds::OpenBracket {
+            let kind = self.parse_type()?;
+            self.expect_next_kind(lexer::PlatTokenKinds::Semicolon)?;
+            let count = self.parse_count()?;
+            self.expect_next_kind(lexer::PlatTokenKinds::CloseBracket)?;
+            return Ok(PlatTypes::Array(Box::new(kind), count));
+        }
+        if token.kind != lexer::PlatTokenKinds::Identifier {
+            return Err(Parser::unexpected(&token));
+        }
+        Ok(match token.value.as_str() {
             "void" => PlatTypes::Void,
             "i32" => PlatTypes::I32,
             "i64" => PlatTypes::I64,
             "f32" => PlatTypes::F32,
             "f64" => PlatTypes::F64,
-            _ => {assert!(false, "Invalid Type Provided!"); PlatTypes::Void},
-        }
+            "str" => PlatTypes::Str,
+            _ => PlatTypes::Named(token.value),
+        })
     }
 
-    fn parse_func_args(&mut self) -> Vec<FuncExprArg> {
+    fn parse_func_args(&mut self) -> Result<Vec<FuncExprArg>, Diagnostic> {
         let mut args = vec![];
         let mut token;
 
-        assert_eq!(self.lexer.next().kind, PlatTokenKinds::OpenParen, "Invalid Function Signature! Missing Open Parenthesis \")\"");
+        ensure!(self.lexer.next()?.kind == PlatTokenKinds::OpenParen, self.lexer.last, "Invalid Function Signature! Missing Open Parenthesis \"(\"");
         while{
-            token = self.lexer.next(); 
+            token = self.lexer.next()?; 
             token.kind != lexer::PlatTokenKinds::CloseParen
         } {
-            if args.len() != 0
-                {assert!(token.kind == lexer::PlatTokenKinds::Comma, "Invalid Argument Notation!");}
-            let mut arg = FuncExprArg{name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier).value, kind: PlatTypes::Void};
-            self.expect_next_kind(lexer::PlatTokenKinds::Colon);
-            arg.kind = self.parse_type();
+            if !args.is_empty() {
+                ensure!(token.kind == lexer::PlatTokenKinds::Comma, token.span, "Invalid Argument Notation!");
+                token = self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?;
+            }
+            if token.kind != lexer::PlatTokenKinds::Identifier {
+                return Err(Parser::unexpected(&token));
+            }
+            let mut arg = FuncExprArg{name: token.value, kind: PlatTypes::Void};
+            self.expect_next_kind(lexer::PlatTokenKinds::Colon)?;
+            arg.kind = self.parse_type()?;
             args.push(arg);
         }
 
-        return args;
+        Ok(args)
+    }
+
+    fn parse_func_signature(&mut self, span: lexer::Span) -> Result<FuncExpr, Diagnostic> {
+        Ok(FuncExpr{ 
+            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value,
+            args: self.parse_func_args()?,
+            ret_kind: self.parse_type()?,
+            body: Box::new(None),
+            module: None,
+            span
+        })
+    }
+
+    fn parse_extern(&mut self, span: lexer::Span) -> Result<FuncExpr, Diagnostic> {
+        let module = self.expect_next_kind(lexer::PlatTokenKinds::StringLiteral)?.value;
+        let token = self.expect_next_kind(lexer::PlatTokenKinds::Keyword)?;
+        ensure!(token.value == "func", token.span, "Only functions can be declared as extern!");
+        let mut expr = self.parse_func_signature(span)?;
+        expr.module = Some(module);
+        self.expect_next_kind(lexer::PlatTokenKinds::Semicolon)?;
+
+        Ok(expr)
+    }
+
+    fn parse_func(&mut self, span: lexer::Span) -> Result<FuncExpr, Diagnostic> {
+        let mut expr = self.parse_func_signature(span)?;
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly)?;
+        expr.body = Box::new(Some(self.parse(Some(lexer::PlatTokenKinds::CloseCurly))?));
+
+        Ok(expr)
+    }
+
+    fn parse_struct(&mut self, span: lexer::Span) -> Result<StructExpr, Diagnostic> {
+        let mut expr = StructExpr {
+            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value,
+            fields: vec![],
+            span
+        };
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly)?;
+        loop {
+            let mut token = self.lexer.next()?;
+            if token.kind == PlatTokenKinds::CloseCurly {break;}
+            if !expr.fields.is_empty() {
+                ensure!(token.kind == PlatTokenKinds::Comma, token.span, "Invalid Field Notation!");
+                token = self.lexer.next()?;
+                if token.kind == PlatTokenKinds::CloseCurly {break;}
+            }
+            if token.kind != PlatTokenKinds::Identifier {
+                return Err(Parser::unexpected(&token));
+            }
+            self.expect_next_kind(lexer::PlatTokenKinds::Colon)?;
+            expr.fields.push(StructField { name: token.value, kind: self.parse_type()? });
+        }
+
+        Ok(expr)
     }
 
-    fn parse_func(&mut self) -> FuncExpr {
-        let mut expr = FuncExpr{ 
-            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier).value,
-            args: self.parse_func_args(),
-            ret_kind: self.parse_type(),
-            body: Box::new(None)
+    fn parse_enum(&mut self, span: lexer::Span) -> Result<EnumExpr, Diagnostic> {
+        let mut expr = EnumExpr {
+            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value,
+            variants: vec![],
+            span
         };
-        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly);
-        expr.body = Box::new(Some(self.parse(Some(lexer::PlatTokenKinds::CloseBracket))));
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly)?;
+        loop {
+            let mut token = self.lexer.next()?;
+            if token.kind == PlatTokenKinds::CloseCurly {break;}
+            if !expr.variants.is_empty() {
+                ensure!(token.kind == PlatTokenKinds::Comma, token.span, "Invalid Variant Notation!");
+                token = self.lexer.next()?;
+                if token.kind == PlatTokenKinds::CloseCurly {break;}
+            }
+            if token.kind != PlatTokenKinds::Identifier {
+                return Err(Parser::unexpected(&token));
+            }
+            let mut variant = EnumVariant { name: token.value, fields: vec![] };
+            if self.lexer.peek()?.kind == PlatTokenKinds::OpenParen {
+                self.lexer.next()?;
+                loop {
+                    variant.fields.push(self.parse_type()?);
+                    let token = self.lexer.next()?;
+                    match token.kind {
+                        PlatTokenKinds::Comma => continue,
+                        PlatTokenKinds::CloseParen => break,
+                        _ => return Err(Parser::unexpected(&token))
+                    }
+                }
+            }
+            expr.variants.push(variant);
+        }
 
-        return expr;
+        Ok(expr)
     }
 
-    fn parse_def(&mut self) -> DefExpr {
+    fn parse_def(&mut self, span: lexer::Span) -> Result<DefExpr, Diagnostic> {
         let mut expr = DefExpr{
-            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier).value,
+            name: self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value,
             kind: PlatTypes::Void, 
-            value: None
+            value: None,
+            span
         };
-        self.expect_next_kind(lexer::PlatTokenKinds::Colon);
-        expr.kind = self.parse_type();
-        self.expect_next_kind(lexer::PlatTokenKinds::Assign);
-        expr.value = self.parse_expr();
+        self.expect_next_kind(lexer::PlatTokenKinds::Colon)?;
+        expr.kind = self.parse_type()?;
+        self.expect_next_kind(lexer::PlatTokenKinds::Assign)?;
+        expr.value = self.parse_expr()?;
 
-        return expr;
+        Ok(expr)
     }
 
-    fn parse_expr(&mut self) -> Option<Expr> {
-        let token = self.lexer.next();
-        match token.kind {
-            lexer::PlatTokenKinds::Literal | lexer::PlatTokenKinds::Identifier => Some(Expr::Literal(token.value)),
-            lexer::PlatTokenKinds::Bang | lexer::PlatTokenKinds::Minus => Some(Expr::Unary(Box::new(UnaryExpr { operator: token, expr: self.parse_expr().unwrap() }))),
+    fn binary_precedence(kind: &lexer::PlatTokenKinds) -> u8 {
+        match kind {
+            PlatTokenKinds::Equals | PlatTokenKinds::BangEquals => 1,
+            PlatTokenKinds::Smaller | PlatTokenKinds::SmallerEquals | PlatTokenKinds::Greater | PlatTokenKinds::GreaterEquals => 2,
+            PlatTokenKinds::Plus | PlatTokenKinds::Minus => 3,
+            PlatTokenKinds::Asterisk | PlatTokenKinds::Slash => 4,
+            _ => 0
+        }
+    }
+
+    fn parse_call_args(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        let mut args = vec![];
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenParen)?;
+        if self.lexer.peek()?.kind == PlatTokenKinds::CloseParen {
+            self.lexer.next()?;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.require_expr("Invalid Call Argument!")?);
+            let token = self.lexer.next()?;
+            match token.kind {
+                PlatTokenKinds::Comma => continue,
+                PlatTokenKinds::CloseParen => break,
+                _ => return Err(Parser::unexpected(&token))
+            }
+        }
+
+        Ok(args)
+    }
+
+    fn parse_struct_lit(&mut self, name: String, span: lexer::Span) -> Result<StructLitExpr, Diagnostic> {
+        let mut expr = StructLitExpr { name, fields: vec![], span };
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly)?;
+        loop {
+            let mut token = self.lexer.next()?;
+            if token.kind == PlatTokenKinds::CloseCurly {break;}
+            if !expr.fields.is_empty() {
+                ensure!(token.kind == PlatTokenKinds::Comma, token.span, "Invalid Field Notation!");
+                token = self.lexer.next()?;
+                if token.kind == PlatTokenKinds::CloseCurly {break;}
+            }
+            if token.kind != PlatTokenKinds::Identifier {
+                return Err(Parser::unexpected(&token));
+            }
+            self.expect_next_kind(lexer::PlatTokenKinds::Colon)?;
+            expr.fields.push((token.value, self.require_expr("Invalid Field Value!")?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_variant(&mut self, enum_name: String, span: lexer::Span) -> Result<VariantExpr, Diagnostic> {
+        self.expect_next_kind(lexer::PlatTokenKinds::DoubleColon)?;
+        let variant = self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value;
+        let args = if self.lexer.peek()?.kind == PlatTokenKinds::OpenParen {self.parse_call_args()?} else {vec![]};
+        Ok(VariantExpr { enum_name, variant, args, span })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, Diagnostic> {
+        let token = self.lexer.next()?;
+        Ok(match token.kind {
+            PlatTokenKinds::Identifier if token.value == "_" => Pattern::Wildcard,
+            PlatTokenKinds::Identifier => {
+                self.expect_next_kind(lexer::PlatTokenKinds::DoubleColon)?;
+                let variant = self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value;
+                let mut bindings = vec![];
+                if self.lexer.peek()?.kind == PlatTokenKinds::OpenParen {
+                    self.lexer.next()?;
+                    loop {
+                        bindings.push(self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?.value);
+                        let token = self.lexer.next()?;
+                        match token.kind {
+                            PlatTokenKinds::Comma => continue,
+                            PlatTokenKinds::CloseParen => break,
+                            _ => return Err(Parser::unexpected(&token))
+                        }
+                    }
+                }
+                Pattern::Variant { enum_name: token.value, variant, bindings }
+            },
+            PlatTokenKinds::Literal => Pattern::Literal(token.value),
+            PlatTokenKinds::Minus => Pattern::Literal(format!("-{}", self.expect_next_kind(lexer::PlatTokenKinds::Literal)?.value)),
+            _ => bail!(token.span, "Invalid Pattern!\n{:?}", token)
+        })
+    }
+
+    fn parse_match(&mut self, span: lexer::Span) -> Result<MatchExpr, Diagnostic> {
+        self.no_struct_literals = true;
+        let scrutinee = self.require_expr("Invalid Match Expression!")?;
+        self.no_struct_literals = false;
+        let mut expr = MatchExpr { expr: scrutinee, arms: vec![], span };
+        self.expect_next_kind(lexer::PlatTokenKinds::OpenCurly)?;
+        loop {
+            if self.lexer.peek()?.kind == PlatTokenKinds::CloseCurly {
+                self.lexer.next()?;
+                break;
+            }
+            let span = self.lexer.peek()?.span;
+            let pattern = self.parse_pattern()?;
+            self.expect_next_kind(lexer::PlatTokenKinds::FatArrow)?;
+            let body = self.require_expr("Invalid Match Arm!")?;
+            expr.arms.push(MatchArm { pattern, body, span });
+            let token = self.lexer.next()?;
+            match token.kind {
+                PlatTokenKinds::Comma => continue,
+                PlatTokenKinds::CloseCurly => break,
+                _ => return Err(Parser::unexpected(&token))
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_array_lit(&mut self, span: lexer::Span) -> Result<ArrayLitExpr, Diagnostic> {
+        let mut expr = ArrayLitExpr { elements: vec![self.require_expr("Invalid Array Element!")?], count: None, span };
+        if self.lexer.peek()?.kind == PlatTokenKinds::Semicolon {
+            self.lexer.next()?;
+            expr.count = Some(self.parse_count()?);
+            self.expect_next_kind(lexer::PlatTokenKinds::CloseBracket)?;
+            return Ok(expr);
+        }
+        loop {
+            let token = self.lexer.next()?;
+            match token.kind {
+                PlatTokenKinds::Comma if self.lexer.peek()?.kind == PlatTokenKinds::CloseBracket => {},
+                PlatTokenKinds::Comma => {
+                    expr.elements.push(self.require_expr("Invalid Array Element!")?);
+                    continue
+                },
+                PlatTokenKinds::CloseBracket => break,
+                _ => return Err(Parser::unexpected(&token))
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // Field accesses and indexing bind tighter than unary and binary operators
+    fn parse_primary(&mut self, token: lexer::PlatToken) -> Result<Option<Expr>, Diagnostic> {
+        let Some(mut expr) = self.parse_atom(token)? else {return Ok(None)};
+        loop {
+            let token = self.lexer.peek()?;
+            match token.kind {
+                PlatTokenKinds::Dot => {
+                    self.lexer.next()?;
+                    let field = self.expect_next_kind(lexer::PlatTokenKinds::Identifier)?;
+                    expr = Expr::Field(Box::new(FieldExpr { expr, field: field.value, span: field.span }));
+                },
+                PlatTokenKinds::OpenBracket => {
+                    self.lexer.next()?;
+                    let index = self.require_expr("Invalid Index Expression!")?;
+                    self.expect_next_kind(lexer::PlatTokenKinds::CloseBracket)?;
+                    expr = Expr::Index(Box::new(IndexExpr { expr, index, span: token.span }));
+                },
+                _ => return Ok(Some(expr))
+            }
+        }
+    }
+
+    fn parse_atom(&mut self, token: lexer::PlatToken) -> Result<Option<Expr>, Diagnostic> {
+        Ok(match token.kind {
+            lexer::PlatTokenKinds::Identifier if self.lexer.peek()?.kind == PlatTokenKinds::OpenParen => {
+                Some(Expr::Call(Box::new(CallExpr { name: token.value, args: self.parse_call_args()?, span: token.span })))
+            },
+            lexer::PlatTokenKinds::Identifier if self.lexer.peek()?.kind == PlatTokenKinds::DoubleColon => {
+                Some(Expr::Variant(Box::new(self.parse_variant(token.value, token.span)?)))
+            },
+            lexer::PlatTokenKinds::Keyword if token.value == "match" => Some(Expr::Match(Box::new(self.parse_match(token.span)?))),
+            lexer::PlatTokenKinds::Identifier if self.lexer.peek()?.kind == PlatTokenKinds::OpenCurly && !self.no_struct_literals => {
+                Some(Expr::StructLit(Box::new(self.parse_struct_lit(token.value, token.span)?)))
+            },
+            lexer::PlatTokenKinds::Literal | lexer::PlatTokenKinds::Identifier => Some(Expr::Literal(token.value, token.span)),
+            lexer::PlatTokenKinds::StringLiteral => Some(Expr::Str(token.value, token.span)),
+            lexer::PlatTokenKinds::Bang | lexer::PlatTokenKinds::Minus => {
+                let operand = self.lexer.next()?;
+                let expr = self.parse_primary(operand)?.ok_or_else(|| Diagnostic::new("Invalid Unary Operand!", self.lexer.last))?;
+                Some(Expr::Unary(Box::new(UnaryExpr { operator: token, expr })))
+            },
+            lexer::PlatTokenKinds::OpenBracket => Some(Expr::ArrayLit(Box::new(self.parse_array_lit(token.span)?))),
+            lexer::PlatTokenKinds::OpenParen => {
+                let no_struct_literals = std::mem::replace(&mut self.no_struct_literals, false);
+                let expr = self.require_expr("Invalid Group Expression!")?;
+                self.no_struct_literals = no_struct_literals;
+                self.expect_next_kind(lexer::PlatTokenKinds::CloseParen)?;
+                Some(Expr::Group(Box::new(expr), token.span))
+            },
             _ => None
+        })
+    }
+
+    fn parse_binary(&mut self, mut left: Expr, min_precedence: u8) -> Result<Expr, Diagnostic> {
+        loop {
+            let precedence = Parser::binary_precedence(&self.lexer.peek()?.kind);
+            if precedence == 0 || precedence < min_precedence {
+                return Ok(left);
+            }
+            let operator = self.lexer.next()?;
+            let token = self.lexer.next()?;
+            let mut right = self.parse_primary(token)?.ok_or_else(|| Diagnostic::new("Invalid Binary Operand!", self.lexer.last))?;
+            while Parser::binary_precedence(&self.lexer.peek()?.kind) > precedence {
+                right = self.parse_binary(right, precedence + 1)?;
+            }
+            left = Expr::Binary(Box::new(BinaryExpr { left, operator, right }));
         }
     }
 
-    pub fn parse(&mut self, end: Option<lexer::PlatTokenKinds>) -> Vec<AST> {
+    fn parse_expr_from(&mut self, token: lexer::PlatToken) -> Result<Option<Expr>, Diagnostic> {
+        let Some(left) = self.parse_primary(token)? else {return Ok(None)};
+        Ok(Some(self.parse_binary(left, 1)?))
+    }
+
+    fn parse_expr(&mut self) -> Result<Option<Expr>, Diagnostic> {
+        let token = self.lexer.next()?;
+        self.parse_expr_from(token)
+    }
+
+    // An expression that has to be there, `message` is the error when it isn't
+    fn require_expr(&mut self, message: &str) -> Result<Expr, Diagnostic> {
+        self.parse_expr()?.ok_or_else(|| Diagnostic::new(message, self.lexer.last))
+    }
+
+    pub fn parse(&mut self, end: Option<lexer::PlatTokenKinds>) -> Result<Vec<AST>, Diagnostic> {
         let end_token = end.unwrap_or(lexer::PlatTokenKinds::EOF);
         let mut ast = Vec::<AST>::new();
         let mut token;
-        while {token = self.lexer.next(); token.kind != end_token && token.kind != lexer::PlatTokenKinds::EOF} {
+        while {token = self.lexer.next()?; token.kind != end_token} {
             match token.kind {
-                lexer::PlatTokenKinds::Keyword => {
-                    if token.value == "func".to_string() {
-                        ast.push(AST::Func(self.parse_func()));
-                    } else if token.value == "let".to_string() {
-                        ast.push(AST::Def(self.parse_def()));
-                    } else if token.value == "return".to_string() {
-                        ast.push(AST::Ret(self.parse_expr()));
+                // A block that isn't closed
+                lexer::PlatTokenKinds::EOF => return Err(Parser::unexpected(&token)),
+                lexer::PlatTokenKinds::Keyword if token.value != "match" => {
+                    if token.value == "func" {
+                        ast.push(AST::Func(self.parse_func(token.span)?));
+                    } else if token.value == "extern" {
+                        ast.push(AST::Func(self.parse_extern(token.span)?));
+                    } else if token.value == "struct" {
+                        ast.push(AST::Struct(self.parse_struct(token.span)?));
+                    } else if token.value == "enum" {
+                        ast.push(AST::Enum(self.parse_enum(token.span)?));
+                    } else if token.value == "let" {
+                        ast.push(AST::Def(self.parse_def(token.span)?));
+                    } else if token.value == "return" {
+                        ast.push(AST::Ret(self.parse_expr()?, token.span));
                     };
                 },
-                _ => continue
+                lexer::PlatTokenKinds::Semicolon => continue,
+                _ => {
+                    let span = token.span;
+                    let unexpected = Parser::unexpected(&token);
+                    let expr = self.parse_expr_from(token)?.ok_or(unexpected)?;
+                    if self.lexer.peek()?.kind == PlatTokenKinds::Assign {
+                        self.lexer.next()?;
+                        let value = self.require_expr("Invalid Assignment Value!")?;
+                        ast.push(AST::Assign(AssignExpr { target: expr, value, span }));
+                    } else {
+                        ast.push(AST::Expr(expr, span));
+                    }
+                }
             }
         }
 
-        return ast;
+        Ok(ast)
     }
 }
@@ -0,0 +1,206 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::interpreter::{self, Instance, Trap, Value};
+use crate::lexer::{PlatTokenKinds, Span};
+use crate::parser::{Expr, FuncExpr, PlatTypes, AST};
+use crate::{Diagnostic, Options, Output, Target};
+
+// Interactive sessions. Every entry is compiled into a scratch module together with the definitions
+// of the earlier entries and run on the built-in interpreter. Variables and memory don't outlive a
+// module, so the statements of all earlier entries run again before each entry, and only what is
+// printed after them is shown. Their other side effects, like stores and memory.grow, happen again.
+
+const ENTRY: &str = "__repl";                                       // Function an entry is compiled into
+
+pub struct Session {
+    options: Options,
+    // Entries that compiled and ran without a trap
+    entries: Vec<String>,
+    // Bytes printed by the statements of the entries
+    replayed: usize
+}
+
+// What an entry printed, and its formatted value or the trap it ran into
+pub struct Reply {
+    pub output: Vec<u8>,
+    pub result: Result<Option<String>, Trap>
+}
+
+// The earlier entries followed by a new one
+struct Program {
+    // Functions and types, a definition replaces earlier ones with the same name
+    definitions: Vec<AST>,
+    statements: Vec<AST>,
+    // Statements that come from the new entry
+    new: usize,
+    // Whether the new entry has more than an expression
+    defines: bool
+}
+
+fn error(message: String, span: Option<Span>) -> Diagnostic {
+    Diagnostic { message, span }
+}
+
+fn lines(src: &str) -> u32 {
+    src.split('\n').count() as u32
+}
+
+// Whether all brackets of `src` are closed, otherwise the entry continues on the next line. Input
+// that doesn't tokenize counts as complete so that its error is reported.
+pub fn is_complete(src: &str) -> bool {
+    let Ok(tokens) = crate::tokenize(src) else {return true};
+    let depth = tokens.iter().fold(0i32, |depth, token| match token.kind {
+        PlatTokenKinds::OpenParen | PlatTokenKinds::OpenBracket | PlatTokenKinds::OpenCurly => depth + 1,
+        PlatTokenKinds::CloseParen | PlatTokenKinds::CloseBracket | PlatTokenKinds::CloseCurly => depth - 1,
+        _ => depth
+    });
+    depth <= 0
+}
+
+// Functions and types live in separate namespaces
+fn definition(node: &AST) -> (bool, String) {
+    match node {
+        AST::Func(func) => (false, func.name.clone()),
+        AST::Struct(expr) => (true, expr.name.clone()),
+        AST::Enum(expr) => (true, expr.name.clone()),
+        _ => unreachable!()
+    }
+}
+
+// Strings are shown with their contents, other heap values as their address
+fn format(memory: &[u8], value: Value, kind: &PlatTypes) -> String {
+    match (kind, value) {
+        (PlatTypes::Str, Value::I32(address)) => {
+            let start = address as usize;
+            let text = memory.get(start..start + 4)
+                .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+                .and_then(|len| memory.get(start + 4..start + 4 + len));
+            match text {
+                Some(text) => format!("{:?}: str", String::from_utf8_lossy(text)),
+                None => format!("{}: str", address)
+            }
+        },
+        (PlatTypes::Named(_) | PlatTypes::Array(..), Value::I32(address)) => format!("{}: {}", address, kind),
+        (_, value) => value.to_string()
+    }
+}
+
+impl Session {
+    pub fn new(options: &Options) -> Session {
+        // Printing needs the wasi target, entries are called directly instead of through _start
+        let options = Options { target: Target::Wasi, source_map: false, ..options.clone() };
+        Session { options, entries: vec![], replayed: 0 }
+    }
+
+    // Every entry is parsed as if it followed the lines of the entries before it, so that the span of
+    // an error tells which entry it is in. Errors are reported relative to the new entry, or name the
+    // earlier entry that a new definition broke.
+    fn locate(&self, diagnostic: Diagnostic) -> Diagnostic {
+        let Some(span) = diagnostic.span else {return diagnostic};
+        let mut offset = 0;
+        for entry in self.entries.iter() {
+            if span.line <= offset + lines(entry) {
+                let first = entry.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+                return error(format!("The earlier entry `{}` doesn't compile anymore, {}:{}: {}", first, span.line - offset, span.column, diagnostic.message), None);
+            }
+            offset += lines(entry);
+        }
+        error(diagnostic.message, Some(Span { line: span.line - offset, ..span }))
+    }
+
+    fn program(&self, src: &str) -> Result<Program, Diagnostic> {
+        let mut program = Program { definitions: vec![], statements: vec![], new: 0, defines: false };
+        let mut offset = 0;
+        for (index, entry) in self.entries.iter().map(String::as_str).chain([src]).enumerate() {
+            let new = index == self.entries.len();
+            let ast = crate::parse(&("\n".repeat(offset as usize) + entry)).map_err(|diagnostic| self.locate(diagnostic))?;
+            offset += lines(entry);
+            for node in ast {
+                match &node {
+                    AST::Ret(_, span) => return Err(self.locate(error("return can only be used inside functions".to_string(), Some(*span)))),
+                    AST::Def(def) if program.statements.iter().any(|other| matches!(other, AST::Def(other) if other.name == def.name)) => {
+                        return Err(self.locate(error(format!("The variable {} is already defined, assign to it instead", def.name), Some(def.span))));
+                    },
+                    _ => {}
+                }
+                program.defines |= new && !matches!(node, AST::Expr(..));
+                if matches!(node, AST::Func(_) | AST::Struct(_) | AST::Enum(_)) {
+                    program.definitions.push(node);
+                } else {
+                    program.new += new as usize;
+                    program.statements.push(node);
+                }
+            }
+        }
+
+        let names: Vec<(bool, String)> = program.definitions.iter().map(definition).collect();
+        let mut position = 0;
+        program.definitions.retain(|_| {
+            position += 1;
+            !names[position..].contains(&names[position - 1])
+        });
+        Ok(program)
+    }
+
+    fn expr_type(&self, program: &Program, expr: &Expr, span: Span) -> Result<PlatTypes, Diagnostic> {
+        let mut compiler = crate::new_compiler("", &self.options);
+        compiler.expr_type(&program.definitions, &program.statements, expr, span).map_err(|diagnostic| self.locate(diagnostic))
+    }
+
+    fn compile(&self, ast: Vec<AST>) -> Result<Output, Diagnostic> {
+        let mut compiler = crate::new_compiler("", &self.options);
+        compiler.start = false;
+        let wasm = compiler.compile(&ast).map_err(|diagnostic| self.locate(diagnostic))?;
+        Ok(Output { wasm, module: compiler.module().clone(), source_map: None })
+    }
+
+    // Runs an entry: definitions, statements and an optional expression whose value is returned
+    pub fn eval(&mut self, src: &str) -> Result<Reply, Diagnostic> {
+        let mut program = self.program(src)?;
+        let mut kind = PlatTypes::Void;
+        if program.new > 0 && matches!(program.statements.last(), Some(AST::Expr(..))) {
+            let Some(AST::Expr(expr, span)) = program.statements.pop() else {unreachable!()};
+            kind = self.expr_type(&program, &expr, span)?;
+            program.statements.push(if kind == PlatTypes::Void {AST::Expr(expr, span)} else {AST::Ret(Some(expr), span)});
+        }
+
+        let span = program.statements.last().map_or(Span::default(), AST::span);
+        let entry = FuncExpr { name: ENTRY.to_string(), args: vec![], ret_kind: kind.clone(), body: Box::new(Some(program.statements)), module: None, span };
+        program.definitions.push(AST::Func(entry));
+        let output = self.compile(program.definitions)?;
+
+        let printed = Rc::new(RefCell::new(vec![]));
+        let stdout = printed.clone();
+        let host = interpreter::wasi(move |_, bytes| {
+            stdout.borrow_mut().extend_from_slice(bytes);
+            Ok(())
+        });
+        let mut instance = Instance::new(&output.wasm, host).map_err(|message| error(message, None))?;
+        let result = instance.invoke(ENTRY, &[]).map(|value| value.map(|value| format(instance.memory(), value, &kind)));
+
+        let printed = printed.take();
+        let output = printed.get(self.replayed..).unwrap_or_default().to_vec();
+        if result.is_ok() {
+            self.entries.push(src.to_string());
+            self.replayed = printed.len();
+        }
+        Ok(Reply { output, result })
+    }
+
+    // Type of an expression, which can use the definitions and variables of the session
+    pub fn type_of(&self, src: &str) -> Result<PlatTypes, Diagnostic> {
+        let mut program = self.program(src)?;
+        match program.statements.pop() {
+            Some(AST::Expr(expr, span)) if program.new == 1 && !program.defines => self.expr_type(&program, &expr, span),
+            _ => Err(error("Expected an expression".to_string(), None))
+        }
+    }
+
+    // The function `name` of the session as WAT
+    pub fn wat(&self, name: &str) -> Result<String, Diagnostic> {
+        let program = self.program("")?;
+        let output = self.compile(program.definitions)?;
+        crate::wat::print_function(&output.module, name).ok_or_else(|| error(format!("There is no function {}", name), None))
+    }
+}
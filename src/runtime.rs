@@ -0,0 +1,316 @@
+use crate::ir::{BinOp, BlockType, ConvOp, Instr, LoadKind, MemArg, RelOp, StoreKind, UnOp, ValType};
+use crate::parser::PlatTypes;
+
+// Runtime prelude. These functions are emitted by the compiler after the user defined functions
+// and are only linked in when a program uses them. Everything but the allocator needs the wasi target.
+//
+// __alloc(size, align)   bump allocator: aligns the heap top up to align (a power of two) and moves
+//                        it past size bytes, growing the memory when needed -> address
+// __alloc(size, align)   free-list allocator: reuses the first freed block that fits, otherwise bumps
+//                        like the bump allocator but keeps an 8 byte {size, next} header before
+//                        each block. next is IN_USE while the block is allocated -> address
+// __free(ptr)            pushes the block of ptr onto the free list, free(0) does nothing. Freeing
+//                        a block that isn't in use traps
+//
+// Both allocators trap when align isn't a power of two.
+// __write(ptr, len)      fd_write(stdout, [{ptr, len}])
+// __print_str(s)         writes a `str`
+// __print_u64(v)         writes an unsigned integer
+// __print_i64(v)         writes a signed integer
+// __print_f64(v)         writes a float with up to six fractional digits (or nan / inf)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RuntimeFn {
+    Alloc,
+    FreeListAlloc,
+    Free,
+    Write,
+    PrintStr,
+    PrintU64,
+    PrintI64,
+    PrintF64
+}
+
+// Scratch memory used by the runtime: an iovec, the nwritten result and a digit buffer
+pub const SCRATCH_SIZE: u32 = 48;
+const IOV: u32 = 0;
+const NWRITTEN: u32 = 8;
+const BUF_END: u32 = SCRATCH_SIZE;
+
+// The heap starts at the 8 byte aligned end of the data, the __heap_base global
+pub const HEAP_ALIGN: u32 = 8;
+pub const HEADER_SIZE: u32 = 8;
+// The next pointer of allocated free list blocks, blocks on the list have an address or 0 there
+pub const IN_USE: u32 = u32::MAX;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Allocator {
+    Bump,
+    FreeList
+}
+
+pub struct Context<'a> {
+    pub fd_write: u32,
+    pub scratch: u32,
+    pub heap_top: u32,
+    pub free_list: u32,
+    pub index: &'a dyn Fn(RuntimeFn) -> u32,
+    pub string: &'a dyn Fn(&str) -> u32
+}
+
+fn i32_const(value: u32) -> Instr {
+    Instr::I32Const(value as i32)
+}
+
+fn i32(op: BinOp) -> Instr {
+    Instr::Binary(ValType::I32, op)
+}
+
+fn i64(op: BinOp) -> Instr {
+    Instr::Binary(ValType::I64, op)
+}
+
+fn store8() -> Instr {
+    Instr::Store(StoreKind::I32U8, MemArg::natural(0))
+}
+
+fn load32() -> Instr {
+    Instr::Load(LoadKind::I32, MemArg::natural(2))
+}
+
+fn store32() -> Instr {
+    Instr::Store(StoreKind::I32, MemArg::natural(2))
+}
+
+fn cmp(op: RelOp) -> Instr {
+    Instr::Compare(ValType::I32, op)
+}
+
+// Traps unless align is a power of two
+fn check_align(align: u32) -> Vec<Instr> {
+    vec![
+        Instr::LocalGet(align), Instr::Eqz(ValType::I32),
+        Instr::LocalGet(align), Instr::LocalGet(align), i32_const(1), i32(BinOp::Sub), i32(BinOp::And), // align & (align - 1)
+        i32(BinOp::Or), Instr::If(BlockType::Empty, vec![Instr::Unreachable], vec![])
+    ]
+}
+
+// ptr = align_up(top + header, align), end = ptr + size. Grows the memory to fit end (trapping when
+// it can't) and moves the heap top to end.
+fn bump(ctx: &Context, header: u32, size: u32, align: u32, ptr: u32, end: u32, pages: u32) -> Vec<Instr> {
+    vec![
+        Instr::GlobalGet(ctx.heap_top), i32_const(header), i32(BinOp::Add),
+        Instr::LocalGet(align), i32_const(1), i32(BinOp::Sub), i32(BinOp::Add),
+        i32_const(0), Instr::LocalGet(align), i32(BinOp::Sub), i32(BinOp::And), Instr::LocalTee(ptr),
+        Instr::LocalGet(size), i32(BinOp::Add), Instr::LocalTee(end),
+        Instr::LocalGet(ptr), cmp(RelOp::LtU), Instr::If(BlockType::Empty, vec![Instr::Unreachable], vec![]), // trap if end < ptr
+        Instr::LocalGet(end), i32_const(0xFFFF), i32(BinOp::Add), i32_const(16), i32(BinOp::ShrU),
+        Instr::MemorySize, i32(BinOp::Sub), Instr::LocalTee(pages),                  // pages = ceil(end / 64KiB) - memory.size
+        i32_const(0), cmp(RelOp::GtS),
+        Instr::If(BlockType::Empty, vec![
+            Instr::LocalGet(pages), Instr::MemoryGrow, i32_const(u32::MAX), cmp(RelOp::Eq),
+            Instr::If(BlockType::Empty, vec![Instr::Unreachable], vec![])           // trap if memory.grow(pages) == -1
+        ], vec![]),
+        Instr::LocalGet(end), Instr::GlobalSet(ctx.heap_top)                        // top = end
+    ]
+}
+
+impl RuntimeFn {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuntimeFn::Alloc    => "__alloc",
+            RuntimeFn::FreeListAlloc => "__alloc",
+            RuntimeFn::Free     => "__free",
+            RuntimeFn::Write    => "__write",
+            RuntimeFn::PrintStr => "__print_str",
+            RuntimeFn::PrintU64 => "__print_u64",
+            RuntimeFn::PrintI64 => "__print_i64",
+            RuntimeFn::PrintF64 => "__print_f64",
+        }
+    }
+
+    pub fn args(&self) -> Vec<PlatTypes> {
+        match self {
+            RuntimeFn::Alloc    => vec![PlatTypes::I32, PlatTypes::I32],
+            RuntimeFn::FreeListAlloc => vec![PlatTypes::I32, PlatTypes::I32],
+            RuntimeFn::Free     => vec![PlatTypes::I32],
+            RuntimeFn::Write    => vec![PlatTypes::I32, PlatTypes::I32],
+            RuntimeFn::PrintStr => vec![PlatTypes::Str],
+            RuntimeFn::PrintU64 => vec![PlatTypes::I64],
+            RuntimeFn::PrintI64 => vec![PlatTypes::I64],
+            RuntimeFn::PrintF64 => vec![PlatTypes::F64],
+        }
+    }
+
+    pub fn ret_kind(&self) -> PlatTypes {
+        match self {
+            RuntimeFn::Alloc | RuntimeFn::FreeListAlloc => PlatTypes::I32,
+            _ => PlatTypes::Void
+        }
+    }
+
+    pub fn uses_heap(&self) -> bool {
+        matches!(self, RuntimeFn::Alloc | RuntimeFn::FreeListAlloc | RuntimeFn::Free)
+    }
+
+    pub fn needs_wasi(&self) -> bool {
+        !self.uses_heap()
+    }
+
+    pub fn locals(&self) -> Vec<ValType> {
+        match self {
+            RuntimeFn::Alloc    => vec![ValType::I32; 3],
+            RuntimeFn::FreeListAlloc => vec![ValType::I32; 4],
+            RuntimeFn::PrintU64 => vec![ValType::I32],
+            RuntimeFn::PrintF64 => vec![ValType::I64, ValType::I64, ValType::I32, ValType::I32],
+            _ => vec![]
+        }
+    }
+
+    pub fn dependencies(&self) -> Vec<RuntimeFn> {
+        match self {
+            RuntimeFn::Alloc    => vec![],
+            RuntimeFn::FreeListAlloc => vec![],
+            RuntimeFn::Free     => vec![],
+            RuntimeFn::Write    => vec![],
+            RuntimeFn::PrintStr => vec![RuntimeFn::Write],
+            RuntimeFn::PrintU64 => vec![RuntimeFn::Write],
+            RuntimeFn::PrintI64 => vec![RuntimeFn::PrintStr, RuntimeFn::PrintU64],
+            RuntimeFn::PrintF64 => vec![RuntimeFn::PrintStr, RuntimeFn::PrintU64, RuntimeFn::Write],
+        }
+    }
+
+    pub fn strings(&self) -> &'static [&'static str] {
+        match self {
+            RuntimeFn::PrintI64 => &["-"],
+            RuntimeFn::PrintF64 => &["-", "nan", "inf"],
+            _ => &[]
+        }
+    }
+
+    pub fn body(&self, ctx: &Context) -> Vec<Instr> {
+        let iov = ctx.scratch + IOV;
+        let nwritten = ctx.scratch + NWRITTEN;
+        let buf_end = ctx.scratch + BUF_END;
+        match self {
+            RuntimeFn::Alloc => [check_align(1), bump(ctx, 0, 0, 1, 2, 3, 4), vec![Instr::LocalGet(2)]].concat(),
+            RuntimeFn::FreeListAlloc => [check_align(1), vec![
+                Instr::LocalGet(1), i32_const(HEADER_SIZE), Instr::LocalGet(1), i32_const(HEADER_SIZE), cmp(RelOp::GtU),
+                Instr::Select, Instr::LocalSet(1),                                          // align = max(align, 8)
+                Instr::LocalGet(0), i32_const(HEAP_ALIGN - 1), i32(BinOp::Add),
+                i32_const(!(HEAP_ALIGN - 1)), i32(BinOp::And), Instr::LocalSet(0),          // size = size + 7 & ~7
+                Instr::GlobalGet(ctx.free_list), Instr::LocalSet(2),                        // ptr = free_list
+                Instr::Block(BlockType::Empty, vec![Instr::Loop(BlockType::Empty, vec![
+                    Instr::LocalGet(2), Instr::Eqz(ValType::I32), Instr::BrIf(1),           // br_if ptr == 0
+                    Instr::LocalGet(2), i32_const(8), i32(BinOp::Sub), load32(), Instr::LocalGet(0), cmp(RelOp::GeU),
+                    Instr::LocalGet(2), Instr::LocalGet(1), i32_const(1), i32(BinOp::Sub), i32(BinOp::And),
+                    Instr::Eqz(ValType::I32), i32(BinOp::And),                              // if load(ptr - 8) >= size && ptr is aligned
+                    Instr::If(BlockType::Empty, vec![
+                        Instr::LocalGet(2), i32_const(4), i32(BinOp::Sub), load32(), Instr::LocalSet(3), // next = load(ptr - 4)
+                        Instr::LocalGet(5), Instr::Eqz(ValType::I32),
+                        Instr::If(BlockType::Empty, vec![
+                            Instr::LocalGet(3), Instr::GlobalSet(ctx.free_list)             // free_list = next
+                        ], vec![
+                            Instr::LocalGet(5), i32_const(4), i32(BinOp::Sub), Instr::LocalGet(3), store32() // store(prev - 4, next)
+                        ]),
+                        Instr::LocalGet(2), i32_const(4), i32(BinOp::Sub), i32_const(IN_USE), store32(), // store(ptr - 4, IN_USE)
+                        Instr::LocalGet(2), Instr::Return
+                    ], vec![]),
+                    Instr::LocalGet(2), Instr::LocalSet(5),                                 // prev = ptr
+                    Instr::LocalGet(2), i32_const(4), i32(BinOp::Sub), load32(), Instr::LocalSet(2), // ptr = load(ptr - 4)
+                    Instr::Br(0)
+                ])])
+            ], bump(ctx, HEADER_SIZE, 0, 1, 2, 3, 4), vec![
+                Instr::LocalGet(2), i32_const(8), i32(BinOp::Sub), Instr::LocalGet(0), store32(), // store(ptr - 8, size)
+                Instr::LocalGet(2), i32_const(4), i32(BinOp::Sub), i32_const(IN_USE), store32(), // store(ptr - 4, IN_USE)
+                Instr::LocalGet(2)
+            ]].concat(),
+            RuntimeFn::Free => vec![
+                Instr::LocalGet(0),
+                Instr::If(BlockType::Empty, vec![                                           // if ptr != 0
+                    Instr::LocalGet(0), i32_const(4), i32(BinOp::Sub), load32(), i32_const(IN_USE), cmp(RelOp::Ne),
+                    Instr::If(BlockType::Empty, vec![Instr::Unreachable], vec![]),          // trap if load(ptr - 4) != IN_USE
+                    Instr::LocalGet(0), i32_const(4), i32(BinOp::Sub), Instr::GlobalGet(ctx.free_list), store32(), // store(ptr - 4, free_list)
+                    Instr::LocalGet(0), Instr::GlobalSet(ctx.free_list)                     // free_list = ptr
+                ], vec![])
+            ],
+            RuntimeFn::Write => vec![
+                i32_const(iov), Instr::LocalGet(0), Instr::Store(StoreKind::I32, MemArg { align: 2, offset: 0 }), // iov.base = ptr
+                i32_const(iov), Instr::LocalGet(1), Instr::Store(StoreKind::I32, MemArg { align: 2, offset: 4 }), // iov.len = len
+                i32_const(1), i32_const(iov), i32_const(1), i32_const(nwritten),
+                Instr::Call(ctx.fd_write), Instr::Drop                                     // fd_write(stdout, iov, 1, nwritten) drop
+            ],
+            RuntimeFn::PrintStr => vec![
+                Instr::LocalGet(0), i32_const(4), i32(BinOp::Add),                          // s + 4
+                Instr::LocalGet(0), Instr::Load(LoadKind::I32, MemArg::natural(2)),         // load(s)
+                Instr::Call((ctx.index)(RuntimeFn::Write))
+            ],
+            RuntimeFn::PrintU64 => vec![
+                i32_const(buf_end), Instr::LocalSet(1),                                     // pos = buf_end
+                Instr::Loop(BlockType::Empty, vec![
+                    Instr::LocalGet(1), i32_const(1), i32(BinOp::Sub), Instr::LocalTee(1),  // pos -= 1
+                    Instr::LocalGet(0), Instr::I64Const(10), i64(BinOp::RemU), Instr::Convert(ConvOp::I32WrapI64),
+                    i32_const(48), i32(BinOp::Add), store8(),                               // store8(pos, v % 10 + '0')
+                    Instr::LocalGet(0), Instr::I64Const(10), i64(BinOp::DivU), Instr::LocalTee(0), // v /= 10
+                    Instr::I64Const(0), Instr::Compare(ValType::I64, RelOp::Ne), Instr::BrIf(0) // br_if v != 0
+                ]),
+                Instr::LocalGet(1), i32_const(buf_end), Instr::LocalGet(1), i32(BinOp::Sub), // write(pos, buf_end - pos)
+                Instr::Call((ctx.index)(RuntimeFn::Write))
+            ],
+            RuntimeFn::PrintI64 => vec![
+                Instr::LocalGet(0), Instr::I64Const(0), Instr::Compare(ValType::I64, RelOp::LtS), // if v < 0
+                Instr::If(BlockType::Empty, vec![
+                    i32_const((ctx.string)("-")), Instr::Call((ctx.index)(RuntimeFn::PrintStr)),
+                    Instr::I64Const(0), Instr::LocalGet(0), i64(BinOp::Sub), Instr::LocalSet(0) // v = 0 - v
+                ], vec![]),
+                Instr::LocalGet(0), Instr::Call((ctx.index)(RuntimeFn::PrintU64))
+            ],
+            RuntimeFn::PrintF64 => vec![
+                Instr::LocalGet(0), Instr::LocalGet(0), Instr::Compare(ValType::F64, RelOp::Ne), // if v != v
+                Instr::If(BlockType::Empty, vec![
+                    i32_const((ctx.string)("nan")), Instr::Call((ctx.index)(RuntimeFn::PrintStr)), Instr::Return
+                ], vec![]),
+                Instr::LocalGet(0), Instr::F64Const(0.0), Instr::Compare(ValType::F64, RelOp::Lt), // if v < 0
+                Instr::If(BlockType::Empty, vec![
+                    i32_const((ctx.string)("-")), Instr::Call((ctx.index)(RuntimeFn::PrintStr)),
+                    Instr::LocalGet(0), Instr::Unary(ValType::F64, UnOp::Neg), Instr::LocalSet(0) // v = -v
+                ], vec![]),
+                Instr::LocalGet(0), Instr::F64Const(f64::INFINITY), Instr::Compare(ValType::F64, RelOp::Eq), // if v == inf
+                Instr::If(BlockType::Empty, vec![
+                    i32_const((ctx.string)("inf")), Instr::Call((ctx.index)(RuntimeFn::PrintStr)), Instr::Return
+                ], vec![]),
+                Instr::LocalGet(0), Instr::Unary(ValType::F64, UnOp::Floor),
+                Instr::Convert(ConvOp::I64TruncSatF64U), Instr::LocalSet(1),                // int = trunc_sat_u(floor(v))
+                Instr::LocalGet(0), Instr::LocalGet(0), Instr::Unary(ValType::F64, UnOp::Floor), Instr::Binary(ValType::F64, BinOp::Sub),
+                Instr::F64Const(1e6), Instr::Binary(ValType::F64, BinOp::Mul), Instr::Unary(ValType::F64, UnOp::Nearest),
+                Instr::Convert(ConvOp::I64TruncSatF64U), Instr::LocalSet(2),                // frac = trunc_sat_u(nearest((v - floor(v)) * 1e6))
+                Instr::LocalGet(2), Instr::I64Const(1_000_000), Instr::Compare(ValType::I64, RelOp::GeU), // if frac >= 1e6 (rounding carry)
+                Instr::If(BlockType::Empty, vec![
+                    Instr::LocalGet(1), Instr::I64Const(1), i64(BinOp::Add), Instr::LocalSet(1), // int += 1
+                    Instr::LocalGet(2), Instr::I64Const(1_000_000), i64(BinOp::Sub), Instr::LocalSet(2) // frac -= 1e6
+                ], vec![]),
+                Instr::LocalGet(1), Instr::Call((ctx.index)(RuntimeFn::PrintU64)),
+                i32_const(buf_end), Instr::LocalSet(3),                                     // pos = buf_end
+                Instr::Loop(BlockType::Empty, vec![
+                    Instr::LocalGet(3), i32_const(1), i32(BinOp::Sub), Instr::LocalTee(3),  // pos -= 1
+                    Instr::LocalGet(2), Instr::I64Const(10), i64(BinOp::RemU), Instr::Convert(ConvOp::I32WrapI64),
+                    i32_const(48), i32(BinOp::Add), store8(),                               // store8(pos, frac % 10 + '0')
+                    Instr::LocalGet(2), Instr::I64Const(10), i64(BinOp::DivU), Instr::LocalSet(2), // frac /= 10
+                    Instr::LocalGet(3), i32_const(buf_end - 6), Instr::Compare(ValType::I32, RelOp::GtU), Instr::BrIf(0) // br_if pos > buf_end - 6
+                ]),
+                i32_const(buf_end), Instr::LocalSet(4),                                     // end = buf_end
+                Instr::Block(BlockType::Empty, vec![Instr::Loop(BlockType::Empty, vec![
+                    Instr::LocalGet(4), Instr::LocalGet(3), i32_const(1), i32(BinOp::Add),
+                    Instr::Compare(ValType::I32, RelOp::LeU), Instr::BrIf(1),               // br_if end <= pos + 1
+                    Instr::LocalGet(4), i32_const(1), i32(BinOp::Sub), Instr::Load(LoadKind::I32U8, MemArg::natural(0)),
+                    i32_const(48), Instr::Compare(ValType::I32, RelOp::Ne), Instr::BrIf(1), // br_if load8(end - 1) != '0'
+                    Instr::LocalGet(4), i32_const(1), i32(BinOp::Sub), Instr::LocalSet(4),  // end -= 1
+                    Instr::Br(0)
+                ])]),
+                Instr::LocalGet(3), i32_const(1), i32(BinOp::Sub), Instr::LocalTee(3),      // pos -= 1
+                i32_const(46), store8(),                                                    // store8(pos, '.')
+                Instr::LocalGet(3), Instr::LocalGet(4), Instr::LocalGet(3), i32(BinOp::Sub), // write(pos, end - pos)
+                Instr::Call((ctx.index)(RuntimeFn::Write))
+            ],
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::json::Json;
+use crate::lexer::Span;
+
+// Source map (revision 3) from code offsets back to the .plat source.
+// WASM source maps have a single generated line, the generated column is the byte offset in the module.
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn vlq(value: i64, out: &mut String) {
+    let mut value = if value < 0 {((-value) << 1) | 1} else {value << 1};
+    loop {
+        let mut digit = value & 0x1F;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0x20;                                          // Continuation bit
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+// `locations` are (module offset, 1-based source position) pairs, in increasing offset order
+pub fn generate(source: &str, content: &str, locations: &[(u32, Span)]) -> String {
+    let mut mappings = String::new();
+    let (mut last_offset, mut last_line, mut last_column) = (0i64, 0i64, 0i64);
+    for (position, (offset, span)) in locations.iter().enumerate() {
+        if locations.get(position + 1).is_some_and(|(next, _)| next == offset) {
+            continue;                                               // Expressions without code share an offset with the next one
+        }
+        let (offset, line, column) = (*offset as i64, span.line as i64 - 1, span.column as i64 - 1);
+        if !mappings.is_empty() {
+            mappings.push(',');
+        }
+        vlq(offset - last_offset, &mut mappings);
+        vlq(0, &mut mappings);                                      // Source index
+        vlq(line - last_line, &mut mappings);
+        vlq(column - last_column, &mut mappings);
+        (last_offset, last_line, last_column) = (offset, line, column);
+    }
+    let map = Json::object([
+        ("version", 3.into()),
+        ("sources", vec![source.into()].into()),
+        ("sourcesContent", vec![content.into()].into()),
+        ("names", Json::Array(vec![])),
+        ("mappings", mappings.into())
+    ]);
+    format!("{}\n", map)
+}
@@ -0,0 +1,429 @@
+use std::collections::HashSet;
+
+use crate::decoder::{self, Immediate, Instruction, Module, F32, F64, I32, I64};
+
+// Validates modules produced by the compiler before they are written out.
+// Decoding already checks the section order, section sizes and LEB128 encodings,
+// the checks below cover indices, limits and the operand stack of every function.
+
+const MAX_PAGES: u32 = 65536;
+const MAX_LOCALS: u64 = 50000;
+
+// Operand and result types of the instructions that don't depend on the module
+fn opcode_signature(opcode: u32) -> Option<(&'static [u8], &'static [u8])> {
+    let signature: (&[u8], &[u8]) = match opcode {
+        0x28 | 0x2C..=0x2F => (&[I32], &[I32]),
+        0x29 | 0x30..=0x35 => (&[I32], &[I64]),
+        0x2A => (&[I32], &[F32]),
+        0x2B => (&[I32], &[F64]),
+        0x36 | 0x3A | 0x3B => (&[I32, I32], &[]),
+        0x37 | 0x3C..=0x3E => (&[I32, I64], &[]),
+        0x38 => (&[I32, F32], &[]),
+        0x39 => (&[I32, F64], &[]),
+        0x3F => (&[], &[I32]),
+        0x40 => (&[I32], &[I32]),
+        0x41 => (&[], &[I32]),
+        0x42 => (&[], &[I64]),
+        0x43 => (&[], &[F32]),
+        0x44 => (&[], &[F64]),
+        0x45 => (&[I32], &[I32]),
+        0x46..=0x4F => (&[I32, I32], &[I32]),
+        0x50 => (&[I64], &[I32]),
+        0x51..=0x5A => (&[I64, I64], &[I32]),
+        0x5B..=0x60 => (&[F32, F32], &[I32]),
+        0x61..=0x66 => (&[F64, F64], &[I32]),
+        0x67..=0x69 => (&[I32], &[I32]),
+        0x6A..=0x78 => (&[I32, I32], &[I32]),
+        0x79..=0x7B => (&[I64], &[I64]),
+        0x7C..=0x8A => (&[I64, I64], &[I64]),
+        0x8B..=0x91 => (&[F32], &[F32]),
+        0x92..=0x98 => (&[F32, F32], &[F32]),
+        0x99..=0x9F => (&[F64], &[F64]),
+        0xA0..=0xA6 => (&[F64, F64], &[F64]),
+        0xA7 => (&[I64], &[I32]),
+        0xA8 | 0xA9 | 0xBC | 0xFC00 | 0xFC01 => (&[F32], &[I32]),
+        0xAA | 0xAB | 0xFC02 | 0xFC03 => (&[F64], &[I32]),
+        0xAC | 0xAD => (&[I32], &[I64]),
+        0xAE | 0xAF | 0xFC04 | 0xFC05 => (&[F32], &[I64]),
+        0xB0 | 0xB1 | 0xBD | 0xFC06 | 0xFC07 => (&[F64], &[I64]),
+        0xB2 | 0xB3 | 0xBE => (&[I32], &[F32]),
+        0xB4 | 0xB5 => (&[I64], &[F32]),
+        0xB6 => (&[F64], &[F32]),
+        0xB7 | 0xB8 => (&[I32], &[F64]),
+        0xB9 | 0xBA | 0xBF => (&[I64], &[F64]),
+        0xBB => (&[F32], &[F64]),
+        0xC0 | 0xC1 => (&[I32], &[I32]),
+        0xC2..=0xC4 => (&[I64], &[I64]),
+        _ => return None
+    };
+    Some(signature)
+}
+
+fn type_name(kind: Option<u8>) -> &'static str {
+    kind.map(|kind| decoder::value_type_name(kind).unwrap_or("?")).unwrap_or("unknown")
+}
+
+struct Frame {
+    opcode: u32,
+    start_types: Vec<u8>,
+    end_types: Vec<u8>,
+    height: usize,
+    unreachable: bool
+}
+
+// Operand stack typing as described in the validation algorithm of the WASM spec.
+// `None` is the unknown type of values popped from an unreachable stack.
+struct FuncValidator<'a> {
+    module: &'a Module,
+    locals: Vec<u8>,
+    results: Vec<u8>,
+    vals: Vec<Option<u8>>,
+    ctrls: Vec<Frame>
+}
+
+impl<'a> FuncValidator<'a> {
+    fn push_val(&mut self, kind: Option<u8>) {
+        self.vals.push(kind);
+    }
+
+    fn pop_val(&mut self) -> Result<Option<u8>, String> {
+        let frame = self.ctrls.last().ok_or("Instruction after the end of the function")?;
+        if self.vals.len() == frame.height {
+            if frame.unreachable {
+                return Ok(None);
+            }
+            return Err("Operand stack underflow".to_string());
+        }
+        Ok(self.vals.pop().unwrap())
+    }
+
+    fn pop_expect(&mut self, expected: u8) -> Result<Option<u8>, String> {
+        let actual = self.pop_val()?;
+        match actual {
+            Some(actual) if actual != expected => Err(format!("Type mismatch, expected {} got {}", type_name(Some(expected)), type_name(Some(actual)))),
+            _ => Ok(actual)
+        }
+    }
+
+    fn pop_vals(&mut self, kinds: &[u8]) -> Result<(), String> {
+        for kind in kinds.iter().rev() {
+            self.pop_expect(*kind)?;
+        }
+        Ok(())
+    }
+
+    fn push_vals(&mut self, kinds: &[u8]) {
+        for kind in kinds.iter() {
+            self.push_val(Some(*kind));
+        }
+    }
+
+    fn push_ctrl(&mut self, opcode: u32, start_types: Vec<u8>, end_types: Vec<u8>) {
+        self.ctrls.push(Frame { opcode, start_types: start_types.clone(), end_types, height: self.vals.len(), unreachable: false });
+        self.push_vals(&start_types);
+    }
+
+    fn pop_ctrl(&mut self) -> Result<Frame, String> {
+        let end_types = self.ctrls.last().ok_or("Unbalanced end")?.end_types.clone();
+        self.pop_vals(&end_types)?;
+        let frame = self.ctrls.pop().unwrap();
+        if self.vals.len() != frame.height {
+            return Err(format!("{} values left on the operand stack at the end of a block", self.vals.len() - frame.height));
+        }
+        Ok(frame)
+    }
+
+    fn label_types(&self, depth: u32) -> Result<Vec<u8>, String> {
+        let index = self.ctrls.len().checked_sub(depth as usize + 1).ok_or_else(|| format!("Branch depth {} exceeds the block nesting of {}", depth, self.ctrls.len()))?;
+        let frame = &self.ctrls[index];
+        Ok(if frame.opcode == 0x03 {frame.start_types.clone()} else {frame.end_types.clone()})
+    }
+
+    fn unreachable(&mut self) {
+        let frame = self.ctrls.last_mut().unwrap();
+        self.vals.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn func_type(&self, index: u32) -> Result<&'a decoder::FuncType, String> {
+        let n_imports = self.module.imports.len() as u32;
+        let type_index = if index < n_imports {
+            self.module.imports[index as usize].type_index
+        } else {
+            *self.module.functions.get((index - n_imports) as usize).ok_or_else(|| format!("Unknown function {}", index))?
+        };
+        self.module.types.get(type_index as usize).ok_or_else(|| format!("Function {} has an unknown type {}", index, type_index))
+    }
+
+    fn local(&self, index: u32) -> Result<u8, String> {
+        self.locals.get(index as usize).copied().ok_or_else(|| format!("Unknown local {}", index))
+    }
+
+    fn global(&self, index: u32) -> Result<&'a decoder::Global, String> {
+        self.module.globals.get(index as usize).ok_or_else(|| format!("Unknown global {}", index))
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Result<(), String> {
+        match (instruction.opcode, &instruction.immediate) {
+            (0x00, _) => self.unreachable(),
+            (0x01, _) => {},
+            (0x02 | 0x03, Immediate::Block(kind)) => self.push_ctrl(instruction.opcode, vec![], kind.iter().copied().collect()),
+            (0x04, Immediate::Block(kind)) => {
+                self.pop_expect(I32)?;
+                self.push_ctrl(0x04, vec![], kind.iter().copied().collect());
+            },
+            (0x05, _) => {
+                if self.ctrls.last().is_none_or(|frame| frame.opcode != 0x04) {
+                    return Err("else without a matching if".to_string());
+                }
+                let frame = self.pop_ctrl()?;
+                self.push_ctrl(0x05, frame.start_types, frame.end_types);
+            },
+            (0x0B, _) => {
+                let frame = self.pop_ctrl()?;
+                if frame.opcode == 0x04 && frame.start_types != frame.end_types {
+                    return Err("if without else has to leave its operands unchanged".to_string());
+                }
+                self.push_vals(&frame.end_types);
+            },
+            (0x0C, Immediate::Index(depth)) => {
+                let labels = self.label_types(*depth)?;
+                self.pop_vals(&labels)?;
+                self.unreachable();
+            },
+            (0x0D, Immediate::Index(depth)) => {
+                self.pop_expect(I32)?;
+                let labels = self.label_types(*depth)?;
+                self.pop_vals(&labels)?;
+                self.push_vals(&labels);
+            },
+            (0x0E, Immediate::BrTable(depths, default)) => {
+                self.pop_expect(I32)?;
+                let labels = self.label_types(*default)?;
+                for depth in depths.iter() {
+                    if self.label_types(*depth)? != labels {
+                        return Err("br_table targets have different types".to_string());
+                    }
+                }
+                self.pop_vals(&labels)?;
+                self.unreachable();
+            },
+            (0x0F, _) => {
+                let results = self.results.clone();
+                self.pop_vals(&results)?;
+                self.unreachable();
+            },
+            (0x10, Immediate::Index(index)) => {
+                let kind = self.func_type(*index)?;
+                self.pop_vals(&kind.params)?;
+                self.push_vals(&kind.results);
+            },
+            (0x1A, _) => {self.pop_val()?;},
+            (0x1B, _) => {
+                self.pop_expect(I32)?;
+                let first = self.pop_val()?;
+                let second = self.pop_val()?;
+                match (first, second) {
+                    (Some(first), Some(second)) if first != second => return Err(format!("select operands differ: {} and {}", type_name(Some(first)), type_name(Some(second)))),
+                    _ => self.push_val(first.or(second))
+                }
+            },
+            (0x20, Immediate::Index(index)) => {
+                let kind = self.local(*index)?;
+                self.push_val(Some(kind));
+            },
+            (0x21, Immediate::Index(index)) => {
+                let kind = self.local(*index)?;
+                self.pop_expect(kind)?;
+            },
+            (0x22, Immediate::Index(index)) => {
+                let kind = self.local(*index)?;
+                self.pop_expect(kind)?;
+                self.push_val(Some(kind));
+            },
+            (0x23, Immediate::Index(index)) => {
+                let global = self.global(*index)?;
+                self.push_val(Some(global.kind));
+            },
+            (0x24, Immediate::Index(index)) => {
+                let global = self.global(*index)?;
+                if !global.mutable {
+                    return Err(format!("Global {} is immutable", index));
+                }
+                self.pop_expect(global.kind)?;
+            },
+            (opcode, immediate) => {
+                let (params, results) = opcode_signature(opcode).ok_or_else(|| format!("Unsupported instruction 0x{:02X}", opcode))?;
+                if (0x28..=0x40).contains(&opcode) && self.module.memories.is_empty() {
+                    return Err("Memory instruction without a memory".to_string());
+                }
+                if let Immediate::Memarg { align, .. } = immediate {
+                    let natural = decoder::natural_align(opcode).unwrap();
+                    if *align > natural {
+                        return Err(format!("Alignment 2^{} is larger than the natural alignment 2^{}", align, natural));
+                    }
+                }
+                self.pop_vals(params)?;
+                self.push_vals(results);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_function(module: &Module, index: u32, type_index: u32, body: &decoder::Body) -> Result<(), String> {
+    let kind = module.types.get(type_index as usize).ok_or_else(|| format!("Function {} has an unknown type {}", index, type_index))?;
+    let declared: u64 = body.locals.iter().map(|(count, _)| *count as u64).sum();
+    if kind.params.len() as u64 + declared > MAX_LOCALS {
+        return Err(format!("Function {} declares {} locals, the limit is {}", index, declared, MAX_LOCALS));
+    }
+
+    let mut locals = kind.params.clone();
+    for (count, kind) in body.locals.iter() {
+        locals.extend(std::iter::repeat_n(*kind, *count as usize));
+    }
+    let mut validator = FuncValidator { module, locals, results: kind.results.clone(), vals: vec![], ctrls: vec![] };
+    validator.push_ctrl(0x02, vec![], kind.results.clone());
+    for instruction in body.instructions.iter() {
+        let name = decoder::opcode_info(instruction.opcode).map(|(name, _)| name).unwrap_or("?");
+        validator.instruction(instruction).map_err(|error| format!("Function {} at offset {} ({}): {}", index, instruction.offset, name, error))?;
+    }
+    if !validator.ctrls.is_empty() {
+        return Err(format!("Function {} is missing {} end instructions", index, validator.ctrls.len()));
+    }
+    Ok(())
+}
+
+fn is_ascending<T>(entries: &[(u32, T)]) -> bool {
+    entries.windows(2).all(|pair| pair[0].0 < pair[1].0)
+}
+
+fn validate_names(module: &Module, names: &decoder::Names) -> Vec<String> {
+    let mut errors = vec![];
+    let n_imports = module.imports.len() as u32;
+    let n_funcs = n_imports + module.functions.len() as u32;
+    if !is_ascending(&names.functions) {
+        errors.push("Function names aren't sorted by index".to_string());
+    }
+    if let Some((index, name)) = names.functions.iter().find(|(index, _)| *index >= n_funcs) {
+        errors.push(format!("Function name {} references an unknown function {}", name, index));
+    }
+    if !is_ascending(&names.locals) {
+        errors.push("Local names aren't sorted by function index".to_string());
+    }
+    for (func, locals) in names.locals.iter() {
+        if *func < n_imports || *func >= n_funcs {
+            errors.push(format!("Local names reference an unknown function {}", func));
+            continue;
+        }
+        let position = (func - n_imports) as usize;
+        let n_params = module.types.get(module.functions[position] as usize).map(|kind| kind.params.len()).unwrap_or(0);
+        let n_locals = n_params as u64 + module.code.get(position).map(|body| body.locals.iter().map(|(count, _)| *count as u64).sum()).unwrap_or(0);
+        if !is_ascending(locals) {
+            errors.push(format!("Local names of function {} aren't sorted by index", func));
+        }
+        if let Some((index, name)) = locals.iter().find(|(index, _)| *index as u64 >= n_locals) {
+            errors.push(format!("Local name {} references an unknown local {} of function {}", name, index, func));
+        }
+    }
+    if !is_ascending(&names.globals) {
+        errors.push("Global names aren't sorted by index".to_string());
+    }
+    if let Some((index, name)) = names.globals.iter().find(|(index, _)| *index as usize >= module.globals.len()) {
+        errors.push(format!("Global name {} references an unknown global {}", name, index));
+    }
+    errors
+}
+
+pub fn validate_module(module: &Module) -> Vec<String> {
+    let mut errors = vec![];
+    let n_funcs = (module.imports.len() + module.functions.len()) as u32;
+
+    for import in module.imports.iter() {
+        if import.type_index as usize >= module.types.len() {
+            errors.push(format!("Import {}.{} has an unknown type {}", import.module, import.name, import.type_index));
+        }
+    }
+    if module.functions.len() != module.code.len() {
+        errors.push(format!("{} functions are declared but {} bodies are defined", module.functions.len(), module.code.len()));
+    }
+
+    if module.memories.len() > 1 {
+        errors.push(format!("{} memories are defined, only one is allowed", module.memories.len()));
+    }
+    for memory in module.memories.iter() {
+        if memory.min > MAX_PAGES || memory.max.is_some_and(|max| max > MAX_PAGES) {
+            errors.push(format!("Memory limits exceed {} pages", MAX_PAGES));
+        }
+        if memory.max.is_some_and(|max| max < memory.min) {
+            errors.push("The maximum memory size is smaller than the minimum".to_string());
+        }
+    }
+
+    // Globals can only be initialized by a constant of their own type
+    for (index, global) in module.globals.iter().enumerate() {
+        let kind = match &global.init[..] {
+            [Instruction { opcode: 0x41, .. }, Instruction { opcode: 0x0B, .. }] => I32,
+            [Instruction { opcode: 0x42, .. }, Instruction { opcode: 0x0B, .. }] => I64,
+            [Instruction { opcode: 0x43, .. }, Instruction { opcode: 0x0B, .. }] => F32,
+            [Instruction { opcode: 0x44, .. }, Instruction { opcode: 0x0B, .. }] => F64,
+            _ => {
+                errors.push(format!("Global {} needs a constant initializer", index));
+                continue;
+            }
+        };
+        if kind != global.kind {
+            errors.push(format!("Global {} of type {} is initialized with {}", index, type_name(Some(global.kind)), type_name(Some(kind))));
+        }
+    }
+
+    let mut export_names = HashSet::new();
+    for export in module.exports.iter() {
+        if !export_names.insert(&export.name) {
+            errors.push(format!("Duplicate export {}", export.name));
+        }
+        let valid = match export.kind {
+            0x00 => export.index < n_funcs,
+            0x02 => (export.index as usize) < module.memories.len(),
+            0x03 => (export.index as usize) < module.globals.len(),
+            _ => false
+        };
+        if !valid {
+            errors.push(format!("Export {} references an unknown item {} of kind {}", export.name, export.index, export.kind));
+        }
+    }
+
+    for (position, data) in module.data.iter().enumerate() {
+        match (module.memories.first(), &data.offset[..]) {
+            (None, _) => errors.push(format!("Data segment {} without a memory", position)),
+            (Some(memory), [Instruction { opcode: 0x41, immediate: Immediate::I32(offset), .. }, Instruction { opcode: 0x0B, .. }]) => {
+                if *offset as u32 as u64 + data.bytes.len() as u64 > memory.min as u64 * 65536 {
+                    errors.push(format!("Data segment {} doesn't fit into the initial memory", position));
+                }
+            },
+            _ => errors.push(format!("Data segment {} needs a constant i32 offset", position))
+        }
+    }
+
+    for custom in module.customs.iter().filter(|custom| custom.name == "name") {
+        match decoder::decode_names(&custom.bytes) {
+            Ok(names) => errors.append(&mut validate_names(module, &names)),
+            Err(error) => errors.push(format!("Invalid name section: {}", error))
+        }
+    }
+
+    let n_imports = module.imports.len() as u32;
+    for (position, (type_index, body)) in module.functions.iter().zip(module.code.iter()).enumerate() {
+        if let Err(error) = validate_function(module, n_imports + position as u32, *type_index, body) {
+            errors.push(error);
+        }
+    }
+
+    errors
+}
+
+pub fn validate(bytes: &[u8]) -> Result<(), Vec<String>> {
+    let module = decoder::decode(bytes).map_err(|error| vec![error])?;
+    let errors = validate_module(&module);
+    if errors.is_empty() {Ok(())} else {Err(errors)}
+}
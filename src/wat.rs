@@ -0,0 +1,207 @@
+use std::fmt::Write;
+
+use crate::decoder;
+use crate::encoder;
+use crate::ir::{BlockType, ExportKind, Function, Instr, Module, ValType};
+
+// Prints an IR module in the WebAssembly text format, one instruction per line
+
+fn float(value: f64) -> String {
+    if value.is_nan() {
+        if value.is_sign_negative() {"-nan".to_string()} else {"nan".to_string()}
+    } else if value.is_infinite() {
+        if value < 0.0 {"-inf".to_string()} else {"inf".to_string()}
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn string(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for byte in bytes {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7E => out.push(*byte as char),
+            _ => write!(out, "\\{:02x}", byte).unwrap()
+        }
+    }
+    out
+}
+
+fn type_name(kind: ValType) -> &'static str {
+    decoder::value_type_name(encoder::val_type_byte(kind)).unwrap()
+}
+
+fn func_ref(module: &Module, index: u32) -> String {
+    match module.func_name(index) {
+        Some(name) => format!("${}", name),
+        None => index.to_string()
+    }
+}
+
+fn global_ref(module: &Module, index: u32) -> String {
+    match module.globals.get(index as usize) {
+        Some(global) => format!("${}", global.name),
+        None => index.to_string()
+    }
+}
+
+fn local_name(func: &Function, index: u32) -> Option<&str> {
+    func.local_names.iter().find(|(local, _)| *local == index).map(|(_, name)| name.as_str())
+}
+
+fn local_ref(func: Option<&Function>, index: u32) -> String {
+    match func.and_then(|func| local_name(func, index)) {
+        Some(name) => format!("${}", name),
+        None => index.to_string()
+    }
+}
+
+// `func` is None for the constant initializers of globals
+fn instruction(module: &Module, func: Option<&Function>, instr: &Instr) -> String {
+    let opcode = encoder::opcode(instr);
+    let (name, _) = decoder::opcode_info(opcode).unwrap();
+    match instr {
+        Instr::Block(BlockType::Value(kind), _) | Instr::Loop(BlockType::Value(kind), _) | Instr::If(BlockType::Value(kind), _, _) =>
+            format!("{} (result {})", name, type_name(*kind)),
+        Instr::Call(index) => format!("{} {}", name, func_ref(module, *index)),
+        Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => format!("{} {}", name, local_ref(func, *index)),
+        Instr::GlobalGet(index) | Instr::GlobalSet(index) => format!("{} {}", name, global_ref(module, *index)),
+        Instr::Br(index) | Instr::BrIf(index) => format!("{} {}", name, index),
+        Instr::BrTable(labels, default) => {
+            let labels: Vec<String> = labels.iter().chain([default]).map(|label| label.to_string()).collect();
+            format!("{} {}", name, labels.join(" "))
+        },
+        Instr::Load(_, memarg) | Instr::Store(_, memarg) => {
+            let mut text = name.to_string();
+            if memarg.offset != 0 {write!(text, " offset={}", memarg.offset).unwrap();}
+            if Some(memarg.align) != decoder::natural_align(opcode) {write!(text, " align={}", 1u64 << memarg.align).unwrap();}
+            text
+        },
+        Instr::I32Const(value) => format!("{} {}", name, value),
+        Instr::I64Const(value) => format!("{} {}", name, value),
+        Instr::F32Const(value) => format!("{} {}", name, float(*value as f64)),
+        Instr::F64Const(value) => format!("{} {}", name, float(*value)),
+        _ => name.to_string()
+    }
+}
+
+fn body(out: &mut String, module: &Module, func: &Function, instrs: &[Instr], depth: usize) {
+    let indent = "  ".repeat(depth);
+    for instr in instrs.iter().filter(|instr| !matches!(instr, Instr::Location(_))) {
+        writeln!(out, "    {}{}", indent, instruction(module, Some(func), instr)).unwrap();
+        match instr {
+            Instr::Block(_, inner) | Instr::Loop(_, inner) => {
+                body(out, module, func, inner, depth + 1);
+                writeln!(out, "    {}end", indent).unwrap();
+            },
+            Instr::If(_, then, otherwise) => {
+                body(out, module, func, then, depth + 1);
+                if !otherwise.is_empty() {
+                    writeln!(out, "    {}else", indent).unwrap();
+                    body(out, module, func, otherwise, depth + 1);
+                }
+                writeln!(out, "    {}end", indent).unwrap();
+            },
+            _ => {}
+        }
+    }
+}
+
+fn signature(module: &Module, type_index: u32, func: Option<&Function>) -> String {
+    let kind = &module.types[type_index as usize];
+    let mut text = format!("(type {})", type_index);
+    let mut unnamed: Vec<&str> = vec![];
+    for (index, param) in kind.params.iter().enumerate() {
+        match func.and_then(|func| local_name(func, index as u32)) {
+            Some(name) => write!(text, " (param ${} {})", name, type_name(*param)).unwrap(),
+            None => unnamed.push(type_name(*param))
+        }
+    }
+    if !unnamed.is_empty() {
+        write!(text, " (param {})", unnamed.join(" ")).unwrap();
+    }
+    if !kind.results.is_empty() {
+        let results: Vec<&str> = kind.results.iter().map(|result| type_name(*result)).collect();
+        write!(text, " (result {})", results.join(" ")).unwrap();
+    }
+    text
+}
+
+fn function(out: &mut String, module: &Module, func: &Function) {
+    writeln!(out, "  (func ${} {}", func.name, signature(module, func.type_index, Some(func))).unwrap();
+
+    let n_params = module.types[func.type_index as usize].params.len() as u32;
+    for (index, kind) in func.locals.iter().enumerate() {
+        match local_name(func, n_params + index as u32) {
+            Some(name) => writeln!(out, "    (local ${} {})", name, type_name(*kind)).unwrap(),
+            None => writeln!(out, "    (local {})", type_name(*kind)).unwrap()
+        }
+    }
+
+    body(out, module, func, &func.body, 0);
+    writeln!(out, "  )").unwrap();
+}
+
+// Prints only the function called `name`, if the module defines one
+pub fn print_function(module: &Module, name: &str) -> Option<String> {
+    let func = module.functions.iter().find(|func| func.name == name)?;
+    let mut out = String::new();
+    function(&mut out, module, func);
+    Some(out)
+}
+
+pub fn print(module: &Module) -> String {
+    let mut out = String::new();
+    match &module.name {
+        Some(name) => writeln!(out, "(module ${}", name).unwrap(),
+        None => writeln!(out, "(module").unwrap()
+    }
+
+    for (index, kind) in module.types.iter().enumerate() {
+        let params: Vec<&str> = kind.params.iter().map(|param| type_name(*param)).collect();
+        let results: Vec<&str> = kind.results.iter().map(|result| type_name(*result)).collect();
+        write!(out, "  (type (;{};) (func", index).unwrap();
+        if !params.is_empty() {write!(out, " (param {})", params.join(" ")).unwrap();}
+        if !results.is_empty() {write!(out, " (result {})", results.join(" ")).unwrap();}
+        writeln!(out, "))").unwrap();
+    }
+
+    for import in module.imports.iter() {
+        writeln!(out, "  (import \"{}\" \"{}\" (func ${} {}))", import.module, import.name, import.name, signature(module, import.type_index, None)).unwrap();
+    }
+
+    for func in module.functions.iter() {
+        function(&mut out, module, func);
+    }
+
+    for (index, memory) in module.memories.iter().enumerate() {
+        match memory.max {
+            Some(max) => writeln!(out, "  (memory (;{};) {} {})", index, memory.min, max).unwrap(),
+            None => writeln!(out, "  (memory (;{};) {})", index, memory.min).unwrap()
+        }
+    }
+
+    for global in module.globals.iter() {
+        let kind = if global.mutable {format!("(mut {})", type_name(global.kind))} else {type_name(global.kind).to_string()};
+        let init = instruction(module, None, &global.init);
+        writeln!(out, "  (global ${} {} ({}))", global.name, kind, init).unwrap();
+    }
+
+    for export in module.exports.iter() {
+        let target = match export.kind {
+            ExportKind::Func => format!("func {}", func_ref(module, export.index)),
+            ExportKind::Memory => format!("memory {}", export.index),
+            ExportKind::Global => format!("global {}", global_ref(module, export.index))
+        };
+        writeln!(out, "  (export \"{}\" ({}))", export.name, target).unwrap();
+    }
+
+    for data in module.data.iter() {
+        writeln!(out, "  (data (i32.const {}) \"{}\")", data.offset, string(&data.bytes)).unwrap();
+    }
+
+    writeln!(out, ")").unwrap();
+    out
+}
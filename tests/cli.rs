@@ -0,0 +1,38 @@
+// The command line: where options can go and what errors print.
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn platinum(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_platinum")).args(args).output().unwrap()
+}
+
+fn source(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("platinum-cli-{}-{}.plat", std::process::id(), name));
+    fs::write(&path, "func main() i32 {\n    return 42;\n}\n").unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn options_go_before_or_after_the_command() {
+    let path = source("options");
+    for args in [["-q", "run", &path], ["run", "-q", &path], ["run", &path, "-q"]] {
+        let output = platinum(&args);
+        assert!(output.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "42: i32\n");
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn only_command_line_errors_print_the_usage() {
+    let path = source("errors");
+    let output = platinum(&["run", &path, "--invoke", "missing"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), format!("{}: There is no exported function missing\n", path));
+
+    let output = platinum(&["run", &path, "--unknown"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Unknown option --unknown\n\nUsage:"));
+    fs::remove_file(path).unwrap();
+}
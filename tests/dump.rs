@@ -0,0 +1,13 @@
+// The JSON dump of the AST: every expression has a span and the type the compiler resolved for it.
+
+use platinum::Options;
+
+#[test]
+fn expressions_have_spans_and_types() {
+    let src = "func main() i64 {\n    let y: i64 = 2;\n    let z: i32 = 2;\n    return (1 + y) * 2;\n}\n";
+    let json = platinum::ast_json(src, &Options::default()).unwrap();
+    assert!(json.contains(r#"{"kind":"literal","span":{"line":2,"column":18},"value":"2","type":"i64"}"#), "{}", json);
+    assert!(json.contains(r#"{"kind":"literal","span":{"line":3,"column":18},"value":"2","type":"i32"}"#), "{}", json);
+    assert!(json.contains(r#"{"kind":"group","span":{"line":4,"column":12},"#), "{}", json);
+    assert!(json.contains(r#"{"kind":"name","span":{"line":4,"column":17},"name":"y","type":"i64"}"#), "{}", json);
+}
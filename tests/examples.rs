@@ -0,0 +1,118 @@
+// Differential test: every example is evaluated from its AST and also compiled and run on the
+// built-in WASM interpreter, with several compiler options. Results, printed output and calls to
+// extern functions have to match.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use platinum::evaluator::Evaluator;
+use platinum::interpreter::{self, HostFn, Instance, Trap, Value};
+use platinum::{Allocator, Memory, Options, PlatTypes, Target, AST};
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+#[derive(Debug, PartialEq)]
+struct Run {
+    result: String,
+    output: String,
+    calls: Vec<String>
+}
+
+// Stubs for the extern functions of a program, which log their calls and return zero
+fn externs(ast: &[AST], calls: &Log) -> HashMap<(String, String), HostFn> {
+    let mut imports: HashMap<(String, String), HostFn> = HashMap::new();
+    for node in ast.iter() {
+        let AST::Func(func) = node else {continue};
+        let Some(module) = &func.module else {continue};
+        let result = match func.ret_kind {
+            PlatTypes::Void => None,
+            PlatTypes::I64 => Some(Value::I64(0)),
+            PlatTypes::F32 => Some(Value::F32(0.0)),
+            PlatTypes::F64 => Some(Value::F64(0.0)),
+            _ => Some(Value::I32(0))
+        };
+        let (calls, name) = (calls.clone(), func.name.clone());
+        imports.insert((module.clone(), func.name.clone()), Box::new(move |_, args| {
+            calls.borrow_mut().push(format!("{}{:?}", name, args));
+            Ok(result)
+        }));
+    }
+    imports
+}
+
+// Trap messages differ between the two, only whether a run trapped is compared
+fn result(result: Result<Option<Value>, Trap>) -> String {
+    match result {
+        Ok(value) => format!("{:?}", value),
+        Err(Trap::Exit(code)) => format!("exit {}", code),
+        Err(Trap::Error(_)) => "trap".to_string()
+    }
+}
+
+fn evaluate(ast: &[AST], options: &Options) -> Run {
+    let calls = Log::default();
+    let mut evaluator = Evaluator::new(ast, options, externs(ast, &calls)).unwrap();
+    let result = result(evaluator.call("main", &[]));
+    let output = String::from_utf8_lossy(evaluator.output()).into_owned();
+    Run { result, output, calls: calls.take() }
+}
+
+fn execute(ast: &[AST], wasm: &[u8]) -> Run {
+    let calls = Log::default();
+    let output = Rc::new(RefCell::new(vec![]));
+    let mut imports = externs(ast, &calls);
+    let stdout = output.clone();
+    imports.extend(interpreter::wasi(move |_, bytes| {
+        stdout.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }));
+    let mut instance = Instance::new(wasm, imports).unwrap();
+    let result = result(instance.invoke("main", &[]));
+    let output = String::from_utf8_lossy(&output.borrow()).into_owned();
+    Run { result, output, calls: calls.take() }
+}
+
+#[test]
+fn examples_match_the_evaluator() {
+    let mut paths: Vec<PathBuf> = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "plat"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    for path in paths.iter() {
+        let src = fs::read_to_string(path).unwrap();
+        let ast = platinum::parse(&src).unwrap();
+        for target in [Target::Wasm, Target::Wasi] {
+            for opt_level in [0, 2] {
+                for allocator in [Allocator::Bump, Allocator::FreeList] {
+                    let options = Options { target, opt_level, allocator, validate: true, ..Options::default() };
+                    let wasm = match platinum::compile_str(&src, &options) {
+                        Ok(wasm) => wasm,
+                        // Programs that print only compile for wasi
+                        Err(diagnostics) if target == Target::Wasm && diagnostics[0].message.contains("wasi target") => continue,
+                        Err(diagnostics) => panic!("{}:{}", path.display(), diagnostics[0])
+                    };
+                    assert_eq!(evaluate(&ast, &options), execute(&ast, &wasm), "{} with {:?}", path.display(), options);
+                }
+            }
+        }
+    }
+}
+
+// Growing the memory fails at the same point in both, and memories above the limit are rejected
+#[test]
+fn memory_limits_match_the_evaluator() {
+    let src = "func main() i32 {\n    return memory_grow(4000) * 10 + memory_grow(100);\n}\n";
+    let ast = platinum::parse(src).unwrap();
+    let options = Options::default();
+    assert_eq!(evaluate(&ast, &options), execute(&ast, &platinum::compile_str(src, &options).unwrap()));
+
+    let options = Options { memory: Memory { initial: 70000, ..Memory::default() }, ..Options::default() };
+    let error = Evaluator::new(&ast, &options, HashMap::new()).err().unwrap();
+    assert_eq!(error, "The program needs 70000 pages of memory, the limit is 4096");
+}
@@ -0,0 +1,53 @@
+// The formatter: the canonical layout, comments and blank lines, and formatting twice changing
+// nothing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn files(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files(&path, paths);
+        } else if path.extension().is_some_and(|extension| extension == "plat") {
+            paths.push(path);
+        }
+    }
+}
+
+#[test]
+fn prints_the_canonical_layout() {
+    let src = "// header\nstruct Point{x:i32,y:i32}\n\n\n\nfunc main()i32{let p:Point=Point{x:1,y:-2};\n  let a:[i32;3]=[0;3]; // zeroes\nreturn p.x+a[1]*(2+3);}\n";
+    assert_eq!(platinum::format(src).unwrap(), "// header\nstruct Point { x: i32, y: i32 }\n\nfunc main() i32 {\n    let p: Point = Point { x: 1, y: -2 };\n    let a: [i32; 3] = [0; 3]; // zeroes\n    return p.x + a[1] * (2 + 3);\n}\n");
+}
+
+#[test]
+fn breaks_matches_longer_than_a_line() {
+    let short = "func f(n: i32) i32 {\n    return match n { 0 => 1, _ => n };\n}\n";
+    assert_eq!(platinum::format(short).unwrap(), short);
+    let long = "func f(n: i32) i32 {\n    return match n { 0 => 1000000000, 1 => 2000000000, 2 => 300000000, _ => n * 1000000 };\n}\n";
+    assert_eq!(platinum::format(long).unwrap(), "func f(n: i32) i32 {\n    return match n {\n        0 => 1000000000,\n        1 => 2000000000,\n        2 => 300000000,\n        _ => n * 1000000,\n    };\n}\n");
+}
+
+#[test]
+fn reports_syntax_errors() {
+    let error = platinum::format("func main() i32 {\n    return 1 +;\n}\n").unwrap_err();
+    assert_eq!(error.to_string(), "2:15: Invalid Binary Operand!");
+}
+
+#[test]
+fn examples_are_formatted_and_formatting_is_stable() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = vec![];
+    files(&root.join("examples"), &mut paths);
+    for path in paths.iter() {
+        let src = fs::read_to_string(path).unwrap();
+        assert_eq!(platinum::format(&src).unwrap(), src, "{} isn't formatted", path.display());
+    }
+    paths.clear();
+    files(&root.join("tests/cases"), &mut paths);
+    for path in paths.iter() {
+        let Ok(formatted) = platinum::format(&fs::read_to_string(path).unwrap()) else {continue};
+        assert_eq!(platinum::format(&formatted).unwrap(), formatted, "formatting {} again changes it", path.display());
+    }
+}
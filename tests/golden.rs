@@ -0,0 +1,360 @@
+// Golden tests: every .plat file under tests/cases is a case whose tokens, AST, diagnostics, WAT and
+// run are compared with what it expects. Expectations are comment lines in the case itself,
+// `// run: returned 42: i32`, or sections of a sibling .expected file:
+//
+//     == ast
+//     (func main () i32 (return 42))
+//
+//     == run
+//     returned 42: i32
+//
+// `cargo test --test golden -- --bless` writes what the compiler produces now into the expectations
+// instead, and creates a .expected file for cases that have none. Other arguments filter the cases
+// by path.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+
+use platinum::decoder;
+use platinum::interpreter::{self, HostFn, Instance, Trap, Value};
+use platinum::{Allocator, Options, PlatTypes, Target, AST};
+
+// Sections in the order they are written
+const SECTIONS: [&str; 8] = ["tokens", "ast", "diagnostics", "wat", "names", "sourcemap", "run", "output"];
+// Comments that set options
+const OPTIONS: [&str; 4] = ["target", "opt-level", "allocator", "memory-pages"];
+
+type Sections = Vec<(String, String)>;
+
+// A case and its expectations
+struct Case {
+    path: PathBuf,
+    src: String,
+    options: Options,
+    // From the comments of the case
+    annotations: Sections,
+    // From the .expected file, if there is one
+    expected: Option<Sections>
+}
+
+fn expected_path(path: &Path) -> PathBuf {
+    path.with_extension("expected")
+}
+
+// `// name: text` comments at the start of a line, for the sections and the options
+fn annotation(line: &str) -> Option<(&str, &str)> {
+    let (name, text) = line.strip_prefix("// ")?.split_once(':')?;
+    (SECTIONS.contains(&name) || OPTIONS.contains(&name)).then_some((name, text.strip_prefix(' ').unwrap_or(text)))
+}
+
+fn add(sections: &mut Sections, name: &str, line: &str) {
+    match sections.iter_mut().find(|(section, _)| section == name) {
+        Some((_, text)) => {
+            text.push('\n');
+            text.push_str(line);
+        },
+        None => sections.push((name.to_string(), line.to_string()))
+    }
+}
+
+fn parse_expected(text: &str, path: &Path) -> Sections {
+    let mut sections: Sections = vec![];
+    for line in text.lines() {
+        match line.strip_prefix("== ") {
+            Some(name) => {
+                assert!(SECTIONS.contains(&name), "{}: unknown section {}", path.display(), name);
+                sections.push((name.to_string(), String::new()));
+            },
+            None => {
+                let (_, section) = sections.last_mut().unwrap_or_else(|| panic!("{}: expected a section header, got {:?}", path.display(), line));
+                section.push_str(line);
+                section.push('\n');
+            }
+        }
+    }
+    for (_, text) in sections.iter_mut() {
+        *text = text.trim_end().to_string();
+    }
+    sections
+}
+
+fn write_expected(sections: &Sections) -> String {
+    let sections: Vec<String> = sections.iter().map(|(name, text)| match text.is_empty() {
+        true => format!("== {}\n", name),
+        false => format!("== {}\n{}\n", name, text)
+    }).collect();
+    sections.join("\n")
+}
+
+fn load(path: PathBuf) -> Case {
+    let src = fs::read_to_string(&path).unwrap();
+    let mut options = Options { validate: true, names: true, ..Options::default() };
+    let mut annotations = vec![];
+    for (name, text) in src.lines().filter_map(annotation) {
+        match name {
+            "target" => options.target = match text {
+                "wasm" => Target::Wasm,
+                "wasi" => Target::Wasi,
+                _ => panic!("{}: unknown target {}", path.display(), text)
+            },
+            "allocator" => options.allocator = match text {
+                "bump" => Allocator::Bump,
+                "free-list" => Allocator::FreeList,
+                _ => panic!("{}: unknown allocator {}", path.display(), text)
+            },
+            "opt-level" => options.opt_level = text.parse().unwrap_or_else(|_| panic!("{}: invalid opt-level {}", path.display(), text)),
+            "memory-pages" => options.memory.initial = text.parse().unwrap_or_else(|_| panic!("{}: invalid memory-pages {}", path.display(), text)),
+            _ => add(&mut annotations, name, text)
+        }
+    }
+    let expected = fs::read_to_string(expected_path(&path)).ok().map(|text| parse_expected(&text, &path));
+    if let Some((name, _)) = expected.iter().flatten().find(|(name, _)| annotations.iter().any(|(other, _)| other == name)) {
+        panic!("{}: the {} section is expected in both the comments and the .expected file", path.display(), name);
+    }
+    Case { path, src, options, annotations, expected }
+}
+
+// Stubs for the extern functions, which log their calls and return zero
+fn externs(ast: &[AST], log: &Rc<RefCell<Vec<String>>>) -> HashMap<(String, String), HostFn> {
+    let mut imports: HashMap<(String, String), HostFn> = HashMap::new();
+    for node in ast.iter() {
+        let AST::Func(func) = node else {continue};
+        let Some(module) = &func.module else {continue};
+        let result = match func.ret_kind {
+            PlatTypes::Void => None,
+            PlatTypes::I64 => Some(Value::I64(0)),
+            PlatTypes::F32 => Some(Value::F32(0.0)),
+            PlatTypes::F64 => Some(Value::F64(0.0)),
+            _ => Some(Value::I32(0))
+        };
+        let (log, name) = (log.clone(), format!("{}.{}", module, func.name));
+        imports.insert((module.clone(), func.name.clone()), Box::new(move |_, args| {
+            let args: Vec<String> = args.iter().map(Value::to_string).collect();
+            log.borrow_mut().push(format!("call {}({})", name, args.join(", ")));
+            Ok(result)
+        }));
+    }
+    imports
+}
+
+// Calls to externs followed by how main ended, and what it printed
+fn run(ast: &[AST], wasm: &[u8]) -> (String, String) {
+    let log = Rc::new(RefCell::new(vec![]));
+    let printed = Rc::new(RefCell::new(vec![]));
+    let stdout = printed.clone();
+    let mut imports = externs(ast, &log);
+    imports.extend(interpreter::wasi(move |_, bytes| {
+        stdout.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }));
+    let mut instance = match Instance::new(wasm, imports) {
+        Ok(instance) => instance,
+        Err(error) => return (format!("instantiating failed: {}", error), String::new())
+    };
+    let end = match instance.invoke("main", &[]) {
+        Ok(Some(value)) => format!("returned {}", value),
+        Ok(None) => "returned".to_string(),
+        Err(Trap::Exit(code)) => format!("exited {}", code),
+        Err(Trap::Error(message)) => format!("trapped: {}", message)
+    };
+    log.borrow_mut().push(end);
+    let output = String::from_utf8_lossy(&printed.borrow()).trim_end().to_string();
+    (log.take().join("\n"), output)
+}
+
+// The name section of the module, one name per line
+fn names(wasm: &[u8]) -> String {
+    let module = decoder::decode(wasm).unwrap();
+    let Some(custom) = module.customs.iter().find(|custom| custom.name == "name") else {return String::new()};
+    let names = decoder::decode_names(&custom.bytes).unwrap();
+    let mut lines: Vec<String> = names.module.iter().map(|name| format!("module {}", name)).collect();
+    lines.extend(names.functions.iter().map(|(index, name)| format!("func {} {}", index, name)));
+    for (func, locals) in names.locals.iter() {
+        lines.extend(locals.iter().map(|(index, name)| format!("local {} {} {}", func, index, name)));
+    }
+    lines.extend(names.globals.iter().map(|(index, name)| format!("global {} {}", index, name)));
+    lines.join("\n")
+}
+
+// Decodes the base64 VLQ values of a source map segment
+fn vlq(segment: &str) -> Vec<i64> {
+    const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let (mut values, mut value, mut shift) = (vec![], 0, 0);
+    for c in segment.bytes() {
+        let digit = BASE64.iter().position(|b| *b == c).unwrap() as i64;
+        value |= (digit & 31) << shift;
+        shift += 5;
+        if digit & 32 == 0 {
+            values.push(if value & 1 == 1 {-(value >> 1)} else {value >> 1});
+            (value, shift) = (0, 0);
+        }
+    }
+    values
+}
+
+// The mappings of the source map, one per line: the module offset, the line and column and the
+// source from there to the end of the line
+fn sourcemap(src: &str, map: &str) -> String {
+    let (_, mappings) = map.split_once("\"mappings\":\"").unwrap();
+    let (mappings, _) = mappings.split_once('"').unwrap();
+    let lines: Vec<&str> = src.lines().collect();
+    let (mut offset, mut line, mut column) = (0, 0, 0);
+    mappings.split(',').filter(|segment| !segment.is_empty()).map(|segment| {
+        let values = vlq(segment);
+        (offset, line, column) = (offset + values[0], line + values[2], column + values[3]);
+        let text = lines[line as usize].get(column as usize..).unwrap_or("");
+        format!("{:#x} {}:{} {}", offset, line + 1, column + 1, text).trim_end().to_string()
+    }).collect::<Vec<_>>().join("\n")
+}
+
+// Every section for the case as it compiles now. The sections after the stage that failed hold
+// its diagnostics.
+fn actual(case: &Case) -> HashMap<&'static str, String> {
+    let mut sections = HashMap::new();
+    let tokens = match platinum::tokenize(&case.src) {
+        Ok(tokens) => tokens.iter().map(|token| format!("{}:{} {:?} {:?}", token.span.line, token.span.column, token.kind, token.value)).collect::<Vec<_>>().join("\n"),
+        Err(error) => error.to_string()
+    };
+    sections.insert("tokens", tokens);
+    let (ast, output) = match platinum::parse(&case.src) {
+        Ok(ast) => {
+            let output = platinum::compile(&case.src, &case.options);
+            sections.insert("ast", platinum::dump::ast_sexpr(&ast).trim_end().to_string());
+            (ast, output)
+        },
+        Err(error) => (vec![], Err(error))
+    };
+    match output {
+        Ok(output) => {
+            sections.insert("diagnostics", String::new());
+            sections.insert("wat", platinum::wat::print(&output.module).trim_end().to_string());
+            sections.insert("names", names(&output.wasm));
+            // Source maps are only made for the cases that expect one, so the others show the
+            // code as it is without
+            let mapped = case.annotations.iter().chain(case.expected.iter().flatten()).any(|(name, _)| name == "sourcemap");
+            let file_name = case.path.file_name().unwrap().to_string_lossy().into_owned();
+            let options = Options { source_map: true, file_name: Some(file_name), ..case.options.clone() };
+            let map = mapped.then(|| platinum::compile(&case.src, &options).unwrap().source_map.unwrap());
+            sections.insert("sourcemap", map.map(|map| sourcemap(&case.src, &map)).unwrap_or_default());
+            let has_main = ast.iter().any(|node| matches!(node, AST::Func(func) if func.name == "main"));
+            let (run, printed) = if has_main {run(&ast, &output.wasm)} else {("there is no main function".to_string(), String::new())};
+            sections.insert("run", run);
+            sections.insert("output", printed);
+        },
+        Err(error) => {
+            for name in SECTIONS {
+                sections.entry(name).or_insert_with(|| error.to_string());
+            }
+        }
+    }
+    sections
+}
+
+// Prints the lines that differ, between the lines both have in common at the start and the end
+fn report(path: &Path, name: &str, expected: &str, actual: &str) {
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let prefix = expected.iter().zip(actual.iter()).take_while(|(a, b)| a == b).count();
+    let suffix = expected[prefix..].iter().rev().zip(actual[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    println!("{}: {} differs at line {}", path.display(), name, prefix + 1);
+    for line in expected[prefix..expected.len() - suffix].iter() {
+        println!("  - {}", line);
+    }
+    for line in actual[prefix..actual.len() - suffix].iter() {
+        println!("  + {}", line);
+    }
+}
+
+// The source with its section comments replaced by the actual ones, where the first of them was
+fn bless_annotations(case: &Case, actual: &HashMap<&str, String>) -> String {
+    let lines: Vec<&str> = case.src.lines().collect();
+    let is_section = |line: &&str| annotation(line).is_some_and(|(name, _)| SECTIONS.contains(&name));
+    let first = lines.iter().position(is_section).unwrap();
+    let mut blessed: Vec<String> = lines[..first].iter().map(|line| line.to_string()).collect();
+    for name in SECTIONS.iter().filter(|name| case.annotations.iter().any(|(other, _)| other == *name)) {
+        blessed.extend(actual[name].lines().map(|line| format!("// {}: {}", name, line).trim_end().to_string()));
+    }
+    blessed.extend(lines[first..].iter().filter(|line| !is_section(line)).map(|line| line.to_string()));
+    blessed.join("\n") + "\n"
+}
+
+// Whether the case is as expected, or when blessing, whether the expectations stayed the same
+fn check(case: &Case, bless: bool) -> bool {
+    let actual = actual(case);
+    let mut passed = true;
+    for (name, expected) in case.annotations.iter().chain(case.expected.iter().flatten()) {
+        if *expected != actual[name.as_str()] {
+            if !bless {
+                report(&case.path, name, expected, &actual[name.as_str()]);
+            }
+            passed = false;
+        }
+    }
+    let new = case.annotations.is_empty() && case.expected.is_none();
+    if !bless || passed && !new {
+        return passed;
+    }
+
+    if !case.annotations.is_empty() {
+        fs::write(&case.path, bless_annotations(case, &actual)).unwrap();
+    }
+    if case.expected.is_some() || new {
+        // New cases get the AST and either their diagnostics or their WAT and run
+        let names: Vec<String> = match &case.expected {
+            Some(expected) => expected.iter().map(|(name, _)| name.clone()).collect(),
+            None if !actual["diagnostics"].is_empty() => vec!["diagnostics".to_string()],
+            None => ["ast", "wat", "run", "output"].iter().filter(|name| **name != "output" || !actual["output"].is_empty()).map(|name| name.to_string()).collect()
+        };
+        let sections: Sections = names.into_iter().map(|name| {
+            let text = actual[name.as_str()].clone();
+            (name, text)
+        }).collect();
+        fs::write(expected_path(&case.path), write_expected(&sections)).unwrap();
+    }
+    println!("blessed {}", case.path.display());
+    true
+}
+
+fn cases(dir: &Path, paths: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            cases(&path, paths);
+        } else if path.extension().is_some_and(|extension| extension == "plat") {
+            paths.push(path);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let bless = args.iter().any(|arg| arg == "--bless") || std::env::var_os("BLESS").is_some();
+    // Flags of the default test harness, like --nocapture, are ignored
+    let filters: Vec<&String> = args.iter().filter(|arg| !arg.starts_with('-')).collect();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut paths = vec![];
+    cases(&root, &mut paths);
+    paths.sort();
+    paths.retain(|path| filters.is_empty() || filters.iter().any(|filter| path.to_string_lossy().contains(filter.as_str())));
+
+    let mut failed = vec![];
+    for path in paths.iter() {
+        let case = load(path.clone());
+        if case.annotations.is_empty() && case.expected.is_none() && !bless {
+            println!("{}: no expectations, run with --bless to record them", path.display());
+            failed.push(path);
+        } else if !check(&case, bless) {
+            failed.push(path);
+        }
+    }
+
+    println!("\n{} golden cases, {} failed", paths.len(), failed.len());
+    if !failed.is_empty() {
+        println!("Rerun with `cargo test --test golden -- --bless` if the changes are intended");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,60 @@
+// Running modules on the built-in interpreter: memory limits, host imports and the call depth, which
+// the validator can't check.
+
+use std::collections::HashMap;
+
+use platinum::interpreter::{HostFn, Instance, Value};
+use platinum::{Memory, Options};
+
+fn run(src: &str, memory: Memory) -> Option<Value> {
+    let options = Options { memory, ..Options::default() };
+    let wasm = platinum::compile(src, &options).unwrap().wasm;
+    Instance::new(&wasm, HashMap::new()).unwrap().invoke("main", &[]).unwrap()
+}
+
+#[test]
+fn memory_grow_stops_at_the_declared_maximum() {
+    let src = "func main() i32 {\n    return memory_grow(1) * 10 + memory_grow(2);\n}\n";
+    assert_eq!(run(src, Memory { maximum: Some(3), ..Memory::default() }), Some(Value::I32(9)));
+}
+
+#[test]
+fn memory_grow_stops_at_the_implementation_maximum() {
+    let src = "func main() i32 {\n    return memory_grow(5000);\n}\n";
+    assert_eq!(run(src, Memory::default()), Some(Value::I32(-1)));
+    assert_eq!(run(src, Memory { maximum: Some(65536), ..Memory::default() }), Some(Value::I32(-1)));
+}
+
+#[test]
+fn rejects_memories_above_the_limit() {
+    let options = Options { memory: Memory { initial: 5000, ..Memory::default() }, validate: false, ..Options::default() };
+    let wasm = platinum::compile("func main() i32 {\n    return 0;\n}\n", &options).unwrap().wasm;
+    let error = Instance::new(&wasm, HashMap::new()).err().unwrap();
+    assert_eq!(error, "The module needs 5000 pages of memory, the limit is 4096");
+}
+
+fn trap(src: &str, host: HashMap<(String, String), HostFn>) -> String {
+    let wasm = platinum::compile(src, &Options::default()).unwrap().wasm;
+    Instance::new(&wasm, host).unwrap().invoke("main", &[]).err().unwrap().to_string()
+}
+
+#[test]
+fn imports_resolve_when_they_are_called() {
+    let src = "extern \"env\" func add(a: i32, b: i32) i32;\nfunc main() i32 {\n    return add(2, 3) * 2;\n}\n";
+    assert_eq!(trap(src, HashMap::new()), "Call to the unresolved import env.add");
+    let mut host: HashMap<(String, String), HostFn> = HashMap::new();
+    host.insert(("env".to_string(), "add".to_string()), Box::new(|_, args| match args {
+        [Value::I32(a), Value::I32(b)] => Ok(Some(Value::I32(a + b))),
+        _ => panic!("add got {:?}", args)
+    }));
+    let wasm = platinum::compile(src, &Options::default()).unwrap().wasm;
+    assert_eq!(Instance::new(&wasm, host).unwrap().invoke("main", &[]).unwrap(), Some(Value::I32(10)));
+}
+
+#[test]
+fn deep_recursion_traps() {
+    let src = "func down(n: i32) i32 {\n    return down(n + 1);\n}\nfunc main() i32 {\n    return down(0);\n}\n";
+    // Test threads get 2 MiB of stack, a main thread 8 MiB
+    let thread = std::thread::Builder::new().stack_size(8 << 20).spawn(move || trap(src, HashMap::new())).unwrap();
+    assert_eq!(thread.join().unwrap(), "Call stack exhausted");
+}
@@ -0,0 +1,73 @@
+// The language server over an in-memory session: requests are framed with Content-Length headers
+// and every message it sends back is compared as JSON text.
+
+use std::io::Cursor;
+
+use platinum::lsp;
+
+const URI: &str = "file:///main.plat";
+
+fn frame(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn request(id: u32, method: &str, params: &str) -> String {
+    frame(&format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params))
+}
+
+fn notification(method: &str, params: &str) -> String {
+    frame(&format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params))
+}
+
+fn open(text: &str) -> String {
+    notification("textDocument/didOpen", &format!(r#"{{"textDocument":{{"uri":"{}","text":{:?}}}}}"#, URI, text))
+}
+
+fn at(id: u32, method: &str, line: u32, character: u32) -> String {
+    request(id, method, &format!(r#"{{"textDocument":{{"uri":"{}"}},"position":{{"line":{},"character":{}}}}}"#, URI, line, character))
+}
+
+// The bodies of the messages the server sent for the input
+fn session(messages: &[String]) -> Vec<String> {
+    let input = messages.concat() + &frame(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    let mut output = vec![];
+    lsp::serve(Cursor::new(input), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    output.split("Content-Length: ").skip(1).map(|message| message.split_once("\r\n\r\n").unwrap().1.to_string()).collect()
+}
+
+#[test]
+fn reports_and_clears_diagnostics() {
+    let replies = session(&[
+        open("func main() i32 {\n    return y;\n}\n"),
+        notification("textDocument/didChange", &format!(r#"{{"textDocument":{{"uri":"{}"}},"contentChanges":[{{"text":"func main() i32 {{\n    return 1;\n}}\n"}}]}}"#, URI))
+    ]);
+    assert_eq!(replies, [
+        r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///main.plat","diagnostics":[{"range":{"start":{"line":1,"character":4},"end":{"line":1,"character":10}},"severity":1,"source":"platinum","message":"The variable y does not exist..."}]}}"#,
+        r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///main.plat","diagnostics":[]}}"#
+    ]);
+}
+
+#[test]
+fn hovers_and_goes_to_definitions() {
+    let replies = session(&[
+        open("func add(a: i32, b: i32) i32 {\n    return a + b;\n}\nfunc main() i32 {\n    return add(1, 2);\n}\n"),
+        at(1, "textDocument/hover", 4, 12),
+        at(2, "textDocument/definition", 4, 12),
+        at(3, "textDocument/hover", 4, 4)
+    ]);
+    assert_eq!(&replies[1..], [
+        r#"{"jsonrpc":"2.0","id":1,"result":{"contents":{"kind":"markdown","value":"```platinum\nfunc add(a: i32, b: i32) i32\n```"},"range":{"start":{"line":4,"character":11},"end":{"line":4,"character":14}}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"result":{"uri":"file:///main.plat","range":{"start":{"line":0,"character":5},"end":{"line":0,"character":8}}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"result":null}"#
+    ]);
+}
+
+#[test]
+fn answers_invalid_and_unknown_requests_with_errors() {
+    let replies = session(&[frame("{"), request(1, "workspace/unknown", "{}")]);
+    assert_eq!(replies, [
+        r#"{"jsonrpc":"2.0","id":null,"error":{"code":-32700,"message":"Unexpected end of JSON"}}"#,
+        r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Unknown method workspace/unknown"}}"#
+    ]);
+}
@@ -0,0 +1,45 @@
+// REPL sessions: what later entries see of earlier ones, and where errors are reported.
+
+use platinum::repl::Session;
+use platinum::Options;
+
+fn eval(session: &mut Session, src: &str) -> String {
+    match session.eval(src) {
+        Ok(reply) => String::from_utf8_lossy(&reply.output).into_owned() + &format!("{:?}", reply.result.map_err(|trap| trap.to_string())),
+        Err(diagnostic) => format!("error: {}", diagnostic)
+    }
+}
+
+#[test]
+fn earlier_entries_run_again() {
+    let mut session = Session::new(&Options::default());
+    assert_eq!(eval(&mut session, "store_i32(0, 7)"), "Ok(None)");
+    assert_eq!(eval(&mut session, "let x: i32 = load_i32(0);"), "Ok(None)");
+    assert_eq!(eval(&mut session, "println(x)"), "7\nOk(None)");
+    assert_eq!(eval(&mut session, "x * 6"), "Ok(Some(\"42: i32\"))");
+}
+
+#[test]
+fn failed_entries_are_dropped() {
+    let mut session = Session::new(&Options::default());
+    assert_eq!(eval(&mut session, "let y: i32 = z;"), "error: 1:1: The variable z does not exist...");
+    assert_eq!(eval(&mut session, "let y: i32 = 1;"), "Ok(None)");
+}
+
+#[test]
+fn errors_are_relative_to_the_new_entry() {
+    let mut session = Session::new(&Options::default());
+    assert_eq!(eval(&mut session, "func f() i32 {\n    return 1;\n}"), "Ok(None)");
+    assert_eq!(eval(&mut session, "func g() i32 {\n    return h();\n}"), "error: 2:5: The function h does not exist...");
+}
+
+#[test]
+fn redefinitions_name_the_entries_they_break() {
+    let mut session = Session::new(&Options::default());
+    assert_eq!(eval(&mut session, "func f() i32 {\n    return 1;\n}"), "Ok(None)");
+    assert_eq!(eval(&mut session, "let y: i32 = f();"), "Ok(None)");
+    assert_eq!(eval(&mut session, "func f() i64 {\n    return 2;\n}"),
+        "error: The earlier entry `let y: i32 = f();` doesn't compile anymore, 1:1: Invalid definition value, expected i32 got i64");
+    assert_eq!(eval(&mut session, "func f() i32 {\n    return 3;\n}"), "Ok(None)");
+    assert_eq!(eval(&mut session, "y"), "Ok(Some(\"3: i32\"))");
+}
@@ -0,0 +1,89 @@
+// Source locations in the IR: every instruction is mapped to the expression or statement it was
+// compiled from, with and without optimizations.
+
+use platinum::ir::Instr;
+use platinum::{Options, Span};
+
+const SRC: &str = "func f(a: i32) i32 {\n    return a;\n}\nfunc main() i32 {\n    let x: i32 = 2;\n    return f(x) + x * 3;\n}\n";
+
+// Every instruction of the functions, flattened, with the location it is mapped to
+fn mapped(body: &[Instr], location: &mut Span, out: &mut Vec<(Span, String)>) {
+    for instr in body.iter() {
+        match instr {
+            Instr::Location(span) => *location = *span,
+            Instr::Block(_, body) | Instr::Loop(_, body) => mapped(body, location, out),
+            Instr::If(_, then, otherwise) => {
+                mapped(then, location, out);
+                mapped(otherwise, location, out);
+            },
+            instr => out.push((*location, format!("{:?}", instr)))
+        }
+    }
+}
+
+fn locations(opt_level: u8) -> Vec<(Span, String)> {
+    let options = Options { opt_level, source_map: true, file_name: Some("main.plat".to_string()), ..Options::default() };
+    let output = platinum::compile(SRC, &options).unwrap();
+    let mut out = vec![];
+    for func in output.module.functions.iter().filter(|func| func.name == "main") {
+        mapped(&func.body, &mut Span::default(), &mut out);
+    }
+    out
+}
+
+fn at(line: u32, column: u32) -> Span {
+    Span { line, column }
+}
+
+#[test]
+fn instructions_map_to_their_expressions() {
+    let out = locations(0);
+    assert!(out.contains(&(at(5, 18), "I32Const(2)".to_string())), "{:?}", out);
+    assert!(out.iter().any(|(span, instr)| *span == at(6, 12) && instr.starts_with("Call")), "{:?}", out);
+    assert!(out.iter().any(|(span, instr)| *span == at(6, 17) && instr.contains("Add")), "{:?}", out);
+    assert!(out.iter().any(|(span, instr)| *span == at(6, 21) && instr.contains("Mul")), "{:?}", out);
+    assert!(out.contains(&(at(6, 23), "I32Const(3)".to_string())), "{:?}", out);
+    assert!(out.contains(&(at(6, 5), "Return".to_string())), "{:?}", out);
+}
+
+#[test]
+fn optimizing_keeps_the_locations() {
+    let out = locations(2);
+    assert!(out.iter().any(|(span, instr)| *span == at(6, 17) && instr.contains("Add")), "{:?}", out);
+    assert!(out.iter().any(|(span, instr)| *span == at(6, 21) && instr.contains("Mul")), "{:?}", out);
+}
+
+#[test]
+fn source_maps_do_not_change_the_code() {
+    for opt_level in 0..=2 {
+        let options = Options { opt_level, file_name: Some("main.plat".to_string()), ..Options::default() };
+        let plain = platinum::compile(SRC, &options).unwrap();
+        let mapped = platinum::compile(SRC, &Options { source_map: true, ..options }).unwrap();
+        assert_eq!(platinum::wat::print(&plain.module), platinum::wat::print(&mapped.module));
+    }
+}
+
+#[test]
+fn modules_point_at_the_map_written_next_to_them() {
+    let dir = std::env::temp_dir().join(format!("platinum-sourcemap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (source, output) = (dir.join("main.plat"), dir.join("out.wasm"));
+    std::fs::write(&source, SRC).unwrap();
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_platinum"))
+        .args(["build", "--source-map", "-q", "-o"]).arg(&output).arg(&source).status().unwrap();
+    assert!(status.success());
+    let module = platinum::decoder::decode(&std::fs::read(&output).unwrap()).unwrap();
+    let url = module.customs.iter().find(|custom| custom.name == "sourceMappingURL").unwrap();
+    assert_eq!(&url.bytes[1..], b"out.wasm.map");
+    assert!(dir.join("out.wasm.map").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn the_map_url_defaults_to_the_source_name() {
+    let options = Options { source_map: true, file_name: Some("src/a.plat.d/main.plat".to_string()), ..Options::default() };
+    let wasm = platinum::compile(SRC, &options).unwrap().wasm;
+    let module = platinum::decoder::decode(&wasm).unwrap();
+    let url = module.customs.iter().find(|custom| custom.name == "sourceMappingURL").unwrap();
+    assert_eq!(&url.bytes[1..], b"main.wasm.map");
+}
@@ -0,0 +1,99 @@
+// Modules the validator has to reject, written by hand section by section, and modules with
+// corrupted bytes, which it has to reject or accept without panicking.
+
+use platinum::validator::validate;
+use platinum::{Options, Target};
+
+const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+const I32: u8 = 0x7F;
+const I64: u8 = 0x7E;
+
+// Section contents and function bodies in these tests are shorter than 128 bytes, so their sizes
+// are single byte LEB128 values
+fn section(id: u8, count: u8, body: &[u8]) -> Vec<u8> {
+    [&[id, body.len() as u8 + 1, count][..], body].concat()
+}
+
+fn func_type(params: &[u8], results: &[u8]) -> Vec<u8> {
+    [&[0x60, params.len() as u8][..], params, &[results.len() as u8], results].concat()
+}
+
+fn body(locals: &[(u8, u8)], code: &[u8]) -> Vec<u8> {
+    let locals: Vec<u8> = [locals.len() as u8].into_iter().chain(locals.iter().flat_map(|(count, kind)| [*count, *kind])).collect();
+    let content = [&locals[..], code, &[0x0B]].concat();
+    [&[content.len() as u8][..], &content].concat()
+}
+
+// A module with the given types, the type index of every function and their bodies
+fn module(types: &[Vec<u8>], funcs: &[u8], bodies: &[Vec<u8>]) -> Vec<u8> {
+    [
+        &HEADER[..],
+        &section(0x01, types.len() as u8, &types.concat()),
+        &section(0x03, funcs.len() as u8, funcs),
+        &section(0x0A, bodies.len() as u8, &bodies.concat())
+    ].concat()
+}
+
+fn rejects(bytes: &[u8], message: &str) {
+    match validate(bytes) {
+        Ok(()) => panic!("The module was accepted, expected {}", message),
+        Err(errors) => assert!(errors.iter().any(|error| error.contains(message)), "Expected {}, got {:?}", message, errors)
+    }
+}
+
+#[test]
+fn accepts_a_valid_module() {
+    let bytes = module(&[func_type(&[I32, I32], &[I32])], &[0], &[body(&[], &[0x20, 0x00, 0x20, 0x01, 0x6A])]);
+    assert_eq!(validate(&bytes), Ok(()));
+}
+
+#[test]
+fn rejects_malformed_modules() {
+    rejects(&[0x00, 0x61, 0x73, 0x6E, 0x01, 0x00, 0x00, 0x00], "magic");
+    rejects(&[&HEADER[..], &[0x01, 0x05, 0x01]].concat(), "Unexpected end of module at offset 10, 4 bytes missing");
+    let bytes = module(&[func_type(&[], &[])], &[0], &[body(&[], &[])]);
+    rejects(&bytes[..bytes.len() - 1], "Unexpected end of module at offset 20, 1 bytes missing");
+    rejects(&module(&[func_type(&[], &[])], &[0, 0], &[body(&[], &[])]), "2 functions are declared but 1 bodies are defined");
+    rejects(&[0x00, 0x61, 0x73, 0x6D, 0x02, 0x00, 0x00, 0x00], "Unsupported WASM version");
+    rejects(&[&HEADER[..], &section(3, 0, &[]), &section(1, 0, &[])].concat(), "Section 1 at offset 11 is out of order or duplicated");
+    rejects(&[&HEADER[..], &section(13, 0, &[])].concat(), "Unsupported section 13 at offset 8");
+}
+
+#[test]
+fn rejects_ill_typed_functions() {
+    let types = [func_type(&[I32], &[I32])];
+    rejects(&module(&types, &[0], &[body(&[(1, I64)], &[0x20, 0x00, 0x20, 0x01, 0x6A])]), "Type mismatch, expected i32 got i64");
+    rejects(&module(&types, &[0], &[body(&[], &[0x6A])]), "Operand stack underflow");
+    rejects(&module(&types, &[0], &[body(&[], &[0x20, 0x00, 0x20, 0x00])]), "values left on the operand stack");
+    rejects(&module(&types, &[0], &[body(&[], &[0x20, 0x05])]), "Unknown local 5");
+    rejects(&module(&types, &[0], &[body(&[], &[0x20, 0x00, 0x0C, 0x03])]), "Branch depth 3");
+}
+
+#[test]
+fn rejects_calls_to_functions_of_unknown_types() {
+    let bytes = module(&[func_type(&[], &[])], &[0, 7], &[body(&[], &[0x10, 0x01]), body(&[], &[])]);
+    rejects(&bytes, "Function 1 has an unknown type 7");
+}
+
+#[test]
+fn rejects_br_table_targets_of_different_types() {
+    // block (result i64) block (result i32) i32.const 0 br_table 0 1 end end
+    let code = [0x02, I64, 0x02, I32, 0x41, 0x00, 0x41, 0x00, 0x0E, 0x01, 0x00, 0x01, 0x0B, 0x1A, 0x42, 0x00, 0x0B, 0x1A];
+    rejects(&module(&[func_type(&[], &[])], &[0], &[body(&[], &code)]), "br_table targets have different types");
+}
+
+// Every byte of a compiled module, replaced by a few other values
+#[test]
+fn corrupted_modules_do_not_panic() {
+    let src = "struct P { x: i32, y: f64 }\nfunc main() i32 {\n    let p: P = P { x: 1, y: 2.0 };\n    println(p.x);\n    return match p.x { 1 => 2, _ => 3 };\n}\n";
+    let options = Options { target: Target::Wasi, ..Options::default() };
+    let wasm = platinum::compile(src, &options).unwrap().wasm;
+    assert_eq!(validate(&wasm), Ok(()));
+    for position in 0..wasm.len() {
+        for value in [0x00, 0x01, 0x0B, 0x7F, 0x80, 0xFF, wasm[position] ^ 0x01] {
+            let mut bytes = wasm.clone();
+            bytes[position] = value;
+            let _ = validate(&bytes);
+        }
+    }
+}
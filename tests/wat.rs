@@ -0,0 +1,92 @@
+// Printing IR modules as WAT: names, immediates, nesting and the sections after the functions.
+
+use platinum::ir::{BinOp, BlockType, Data, Export, ExportKind, FuncType, Function, Global, Import, Instr, Limits, LoadKind, MemArg, Module, StoreKind, ValType};
+use platinum::wat;
+
+fn module(body: Vec<Instr>) -> Module {
+    Module {
+        types: vec![FuncType { params: vec![ValType::I32, ValType::I32], results: vec![ValType::F64] }, FuncType { params: vec![ValType::I64], results: vec![] }],
+        imports: vec![Import { module: "env".to_string(), name: "log".to_string(), type_index: 1 }],
+        functions: vec![Function { name: "f".to_string(), type_index: 0, locals: vec![ValType::I32, ValType::F32], local_names: vec![(0, "a".to_string()), (2, "x".to_string())], body }],
+        memories: vec![Limits { min: 1, max: Some(2) }],
+        globals: vec![Global { name: "top".to_string(), kind: ValType::I32, mutable: true, init: Instr::I32Const(1024) }],
+        exports: vec![Export { name: "f".to_string(), kind: ExportKind::Func, index: 1 }, Export { name: "memory".to_string(), kind: ExportKind::Memory, index: 0 }],
+        data: vec![Data { offset: 1024, bytes: b"a\"b\\\n".to_vec() }],
+        ..Module::default()
+    }
+}
+
+#[test]
+fn prints_a_module() {
+    let body = vec![
+        Instr::I64Const(-1), Instr::Call(0),
+        Instr::LocalGet(0), Instr::LocalGet(1), Instr::Binary(ValType::I32, BinOp::Add), Instr::LocalSet(2),
+        Instr::Location(platinum::Span { line: 1, column: 1 }),
+        Instr::F64Const(f64::NEG_INFINITY), Instr::Return
+    ];
+    assert_eq!(wat::print(&module(body)), "(module
+  (type (;0;) (func (param i32 i32) (result f64)))
+  (type (;1;) (func (param i64)))
+  (import \"env\" \"log\" (func $log (type 1) (param i64)))
+  (func $f (type 0) (param $a i32) (param i32) (result f64)
+    (local $x i32)
+    (local f32)
+    i64.const -1
+    call $log
+    local.get $a
+    local.get 1
+    i32.add
+    local.set $x
+    f64.const -inf
+    return
+  )
+  (memory (;0;) 1 2)
+  (global $top (mut i32) (i32.const 1024))
+  (export \"f\" (func $f))
+  (export \"memory\" (memory 0))
+  (data (i32.const 1024) \"a\\\"b\\\\\\0a\")
+)
+");
+}
+
+#[test]
+fn prints_blocks_and_immediates() {
+    let body = vec![
+        Instr::Block(BlockType::Value(ValType::I32), vec![
+            Instr::LocalGet(0),
+            Instr::If(BlockType::Empty, vec![Instr::Br(1)], vec![Instr::Nop]),
+            Instr::Loop(BlockType::Empty, vec![Instr::LocalGet(0), Instr::BrTable(vec![0, 1], 2)]),
+            Instr::I32Const(0), Instr::Load(LoadKind::I32U8, MemArg { align: 0, offset: 4 }),
+            Instr::I32Const(0), Instr::F32Const(f32::NAN), Instr::Store(StoreKind::F32, MemArg { align: 0, offset: 0 }),
+            Instr::GlobalGet(0)
+        ]),
+        Instr::Drop, Instr::F64Const(0.5)
+    ];
+    let printed = wat::print_function(&module(body), "f").unwrap();
+    assert_eq!(printed, "  (func $f (type 0) (param $a i32) (param i32) (result f64)
+    (local $x i32)
+    (local f32)
+    block (result i32)
+      local.get $a
+      if
+        br 1
+      else
+        nop
+      end
+      loop
+        local.get $a
+        br_table 0 1 2
+      end
+      i32.const 0
+      i32.load8_u offset=4
+      i32.const 0
+      f32.const nan
+      f32.store align=1
+      global.get $top
+    end
+    drop
+    f64.const 0.5
+  )
+");
+    assert_eq!(wat::print_function(&module(vec![]), "g"), None);
+}